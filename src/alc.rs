@@ -0,0 +1,125 @@
+//! Ambient light cancellation (ALC) overflow handling.
+//!
+//! When the ambient light cancellation reaches its limit (e.g. in direct
+//! sunlight), the samples no longer reflect the LED light.
+
+use crate::{AdcRange, Error, InterruptStatus, Max3010x};
+use hal::i2c;
+
+/// Value of the samples marked invalid by [`read_fifo_checked()`](Max3010x::read_fifo_checked).
+///
+/// The ADC resolution is at most 18 bits so this is never a valid sample.
+pub const INVALID_SAMPLE: u32 = u32::MAX;
+
+/// Actions to take when an ambient light cancellation overflow occurs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AlcOverflowPolicy {
+    /// Replace the samples read with [`INVALID_SAMPLE`].
+    pub mark_invalid: bool,
+    /// Step up the ADC range to the next one, if it is not at the maximum
+    /// already.
+    pub step_up_adc_range: bool,
+}
+
+impl Default for AlcOverflowPolicy {
+    /// Mark the samples invalid without changing the configuration.
+    fn default() -> Self {
+        AlcOverflowPolicy {
+            mark_invalid: true,
+            step_up_adc_range: false,
+        }
+    }
+}
+
+/// Ambient light cancellation overflow event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AlcOverflowEvent {
+    /// Number of samples marked invalid.
+    pub invalid_sample_count: u8,
+    /// New ADC range if it was stepped up.
+    pub adc_range: Option<AdcRange>,
+}
+
+/// Result of [`read_fifo_checked()`](Max3010x::read_fifo_checked).
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CheckedFifoRead {
+    /// Number of samples read.
+    pub sample_count: u8,
+    /// Interrupt status read before the samples.
+    ///
+    /// Reading the status clears the interrupts so it is returned here for
+    /// the application to handle the other interrupts.
+    pub status: InterruptStatus,
+    /// Ambient light cancellation overflow event, if any.
+    pub alc_overflow: Option<AlcOverflowEvent>,
+}
+
+impl<I2C, E, IC, MODE> Max3010x<I2C, IC, MODE>
+where
+    I2C: i2c::I2c<Error = E>,
+    MODE: crate::reading::ChannelCount<IC, MODE>,
+{
+    /// Read samples from the FIFO handling ambient light cancellation
+    /// overflows.
+    ///
+    /// This reads the interrupt status before reading the FIFO as in
+    /// [`read_fifo()`](Max3010x::read_fifo). If an ALC overflow occurred,
+    /// the actions of the policy are performed and an event is returned.
+    /// The ALC overflow interrupt does not need to be enabled for this.
+    pub fn read_fifo_checked(
+        &mut self,
+        output_data: &mut [u32],
+        policy: AlcOverflowPolicy,
+    ) -> Result<CheckedFifoRead, Error<E>> {
+        let status = self.read_interrupt_status()?;
+        let sample_count = self.read_fifo(output_data)?;
+        let alc_overflow = if status.alc_overflow {
+            let mut invalid_sample_count = 0;
+            if policy.mark_invalid {
+                let values = usize::from(sample_count) * usize::from(self.channel_count());
+                for value in &mut output_data[..values] {
+                    *value = INVALID_SAMPLE;
+                }
+                invalid_sample_count = sample_count;
+            }
+            let adc_range = if policy.step_up_adc_range {
+                self.step_up_adc_range()?
+            } else {
+                None
+            };
+            Some(AlcOverflowEvent {
+                invalid_sample_count,
+                adc_range,
+            })
+        } else {
+            None
+        };
+        Ok(CheckedFifoRead {
+            sample_count,
+            status,
+            alc_overflow,
+        })
+    }
+}
+
+impl<I2C, E, IC, MODE> Max3010x<I2C, IC, MODE>
+where
+    I2C: i2c::I2c<Error = E>,
+{
+    fn step_up_adc_range(&mut self) -> Result<Option<AdcRange>, Error<E>> {
+        let range = match self.get_adc_range() {
+            AdcRange::Fs2k => AdcRange::Fs4k,
+            AdcRange::Fs4k => AdcRange::Fs8k,
+            AdcRange::Fs8k => AdcRange::Fs16k,
+            AdcRange::Fs16k => return Ok(None),
+        };
+        self.set_adc_range(range)?;
+        Ok(Some(range))
+    }
+}
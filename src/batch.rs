@@ -0,0 +1,147 @@
+//! Batched configuration writes.
+
+use crate::{Error, Max3010x, Register as Reg};
+use hal::i2c;
+
+/// Groups of contiguous registers cached by the driver, in flush order.
+///
+/// The mode is written on its own after all the other registers so that
+/// the device starts sampling with the complete configuration. It lies
+/// between the FIFO and SpO2 configurations, which are therefore written
+/// separately.
+const GROUPS: [(u8, u8); 6] = [
+    (Reg::INT_EN1, Reg::INT_EN2),
+    (Reg::LED1_PA, Reg::LED2_PA),
+    (Reg::SLOT_CONFIG0, Reg::SLOT_CONFIG1),
+    (Reg::FIFO_CONFIG, Reg::FIFO_CONFIG),
+    (Reg::SPO2_CONFIG, Reg::SPO2_CONFIG),
+    (Reg::MODE, Reg::MODE),
+];
+
+const fn bit(register: u8) -> u32 {
+    1 << register
+}
+
+impl<I2C, E, IC, MODE> Max3010x<I2C, IC, MODE>
+where
+    I2C: i2c::I2c<Error = E>,
+{
+    /// Start accumulating configuration changes instead of writing them.
+    ///
+    /// Afterwards, the configuration methods only update the configuration
+    /// cached by the driver until [`flush_batch()`](Max3010x::flush_batch)
+    /// is called, which writes all the changes with as few bus
+    /// transactions as possible. This includes mode changes and clearing
+    /// the FIFO, so a whole setup can be done with a handful of
+    /// transactions:
+    ///
+    /// ```no_run
+    /// use linux_embedded_hal::I2cdev;
+    /// use max3010x::{Led, Max3010x, SampleAveraging};
+    ///
+    /// let dev = I2cdev::new("/dev/i2c-1").unwrap();
+    /// let mut sensor = Max3010x::new_max30102(dev);
+    /// sensor.begin_batch();
+    /// let mut sensor = sensor.into_oximeter().unwrap();
+    /// sensor.set_sample_averaging(SampleAveraging::Sa4).unwrap();
+    /// sensor.set_pulse_amplitude(Led::All, 15).unwrap();
+    /// sensor.enable_fifo_rollover().unwrap();
+    /// // Writes LED1_PA + LED2_PA, FIFO_CONFIG, MODE and clears the FIFO.
+    /// sensor.flush_batch().unwrap();
+    /// ```
+    ///
+    /// Methods using the cached configuration, like
+    /// [`get_power_config()`](Max3010x::get_power_config), already see the
+    /// pending changes. Reading methods and the software reset still access
+    /// the device immediately. Reading the FIFO flushes the batch first so
+    /// that the samples are decoded with the configuration they were taken
+    /// with.
+    pub fn begin_batch(&mut self) {
+        if self.batch.is_none() {
+            self.batch = Some(0);
+        }
+    }
+
+    /// Whether configuration changes are being accumulated.
+    pub fn is_batching(&self) -> bool {
+        self.batch.is_some()
+    }
+
+    /// Write the accumulated configuration changes and stop batching.
+    ///
+    /// Each group of contiguous registers (interrupt enables, LED pulse
+    /// amplitudes and multi-LED time slots) is written in a single burst
+    /// including any unchanged register in between. The FIFO configuration,
+    /// the SpO2 configuration and then the mode follow. If the FIFO was
+    /// cleared, this is done last.
+    ///
+    /// On error, the changes not written yet remain pending and the batch
+    /// is kept open so that this can be called again.
+    pub fn flush_batch(&mut self) -> Result<(), Error<E>> {
+        let mut pending = match self.batch {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        for (first, last) in GROUPS.iter() {
+            let mut dirty = (*first..=*last).filter(|reg| pending & bit(*reg) != 0);
+            let start = match dirty.next() {
+                Some(start) => start,
+                None => continue,
+            };
+            let end = dirty.next_back().unwrap_or(start);
+            let mut data = [0; 4];
+            data[0] = start;
+            let len = usize::from(end - start) + 1;
+            for (offset, value) in data[1..=len].iter_mut().enumerate() {
+                *value = self.cached_register(start + offset as u8);
+            }
+            if let Err(e) = self.write_data(&data[..=len]) {
+                self.batch = Some(pending);
+                return Err(e);
+            }
+            for reg in start..=end {
+                pending &= !bit(reg);
+            }
+        }
+        if pending & bit(Reg::FIFO_WR_PTR) != 0 {
+            if let Err(e) = self.write_data(&[Reg::FIFO_WR_PTR, 0, 0, 0]) {
+                self.batch = Some(pending);
+                return Err(e);
+            }
+        }
+        self.batch = None;
+        Ok(())
+    }
+
+    /// Write configuration registers starting at `data[0]` or mark them as
+    /// pending if batching.
+    pub(crate) fn write_config(&mut self, data: &[u8]) -> Result<(), Error<E>> {
+        match &mut self.batch {
+            Some(pending) if data[0] == Reg::FIFO_WR_PTR => {
+                *pending |= bit(Reg::FIFO_WR_PTR);
+                Ok(())
+            }
+            Some(pending) => {
+                for offset in 1..data.len() {
+                    *pending |= bit(data[0] + offset as u8 - 1);
+                }
+                Ok(())
+            }
+            None => self.write_data(data),
+        }
+    }
+
+    fn cached_register(&self, register: u8) -> u8 {
+        match register {
+            Reg::INT_EN1 => self.int_en1.bits,
+            Reg::INT_EN2 => self.int_en2.bits,
+            Reg::FIFO_CONFIG => self.fifo_config.bits,
+            Reg::MODE => self.mode.bits,
+            Reg::SPO2_CONFIG => self.spo2_config.bits,
+            Reg::LED1_PA => self.led1_pa.bits,
+            Reg::LED2_PA => self.led2_pa.bits,
+            Reg::SLOT_CONFIG0 => self.slot_config0.bits,
+            _ => self.slot_config1.bits,
+        }
+    }
+}
@@ -0,0 +1,527 @@
+//! Command-line tool streaming samples from a MAX3010x device on a Linux
+//! I²C bus.
+//!
+//! Run `max3010x-cli --help` for the available options.
+
+use linux_embedded_hal::I2cdev;
+use max3010x::{
+    AdcRange, Error, Led, LedPulseWidth, Max3010x, ModeChangeError, SampleAveraging, SamplingRate,
+    TimeSlot,
+};
+use std::{
+    env, fmt,
+    io::{self, Write},
+    process, thread,
+    time::Duration,
+};
+
+const USAGE: &str = "\
+Usage: max3010x-cli [OPTIONS]
+
+Stream samples from a MAX30102 to stdout.
+
+Options:
+  --device <PATH>         I2C device [default: /dev/i2c-1]
+  --address <ADDRESS>     I2C address, decimal or 0x-prefixed hex [default: 0x57]
+  --mode <MODE>           hr, spo2 or multi-led [default: spo2]
+  --sample-rate <SPS>     50, 100, 200, 400, 800, 1000, 1600 or 3200 [default: 100]
+  --pulse-width <US>      69, 118, 215 or 411 [default: 411]
+  --adc-range <NA>        2048, 4096, 8192 or 16384 [default: 2048]
+  --averaging <N>         1, 2, 4, 8, 16 or 32 [default: 1]
+  --led1 <MA>             LED1 (red) current in mA, 0 to 51 [default: 7]
+  --led2 <MA>             LED2 (IR) current in mA, 0 to 51 [default: 7]
+  --slots <SLOTS>         One to four comma-separated time slots in multi-led
+                          mode: led1 or led2 [default: led1,led2]
+  --format <FORMAT>       csv or json [default: csv]
+  --count <N>             Stop after N samples [default: unlimited]
+  -h, --help              Print this help
+
+Each sample is printed with the time since the start of the capture in us,
+derived from the effective sampling rate, and the number of samples lost
+before it due to a FIFO overflow.";
+
+/// FIFO depth in samples.
+const FIFO_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    HeartRate,
+    Oximeter,
+    MultiLed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Debug)]
+struct Options {
+    device: String,
+    address: u8,
+    mode: Mode,
+    sampling_rate: SamplingRate,
+    pulse_width: LedPulseWidth,
+    adc_range: AdcRange,
+    averaging: SampleAveraging,
+    led1_amplitude: u8,
+    led2_amplitude: u8,
+    slots: [TimeSlot; 4],
+    format: Format,
+    count: Option<u64>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            device: "/dev/i2c-1".into(),
+            address: 0x57,
+            mode: Mode::Oximeter,
+            sampling_rate: SamplingRate::Sps100,
+            pulse_width: LedPulseWidth::Pw411,
+            adc_range: AdcRange::Fs2k,
+            averaging: SampleAveraging::Sa1,
+            led1_amplitude: 35,
+            led2_amplitude: 35,
+            slots: [
+                TimeSlot::Led1,
+                TimeSlot::Led2,
+                TimeSlot::Disabled,
+                TimeSlot::Disabled,
+            ],
+            format: Format::Csv,
+            count: None,
+        }
+    }
+}
+
+fn parse_address(value: &str) -> Option<u8> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn parse_mode(value: &str) -> Option<Mode> {
+    match value {
+        "hr" => Some(Mode::HeartRate),
+        "spo2" => Some(Mode::Oximeter),
+        "multi-led" => Some(Mode::MultiLed),
+        _ => None,
+    }
+}
+
+fn parse_sampling_rate(value: &str) -> Option<SamplingRate> {
+    match value {
+        "50" => Some(SamplingRate::Sps50),
+        "100" => Some(SamplingRate::Sps100),
+        "200" => Some(SamplingRate::Sps200),
+        "400" => Some(SamplingRate::Sps400),
+        "800" => Some(SamplingRate::Sps800),
+        "1000" => Some(SamplingRate::Sps1000),
+        "1600" => Some(SamplingRate::Sps1600),
+        "3200" => Some(SamplingRate::Sps3200),
+        _ => None,
+    }
+}
+
+fn parse_pulse_width(value: &str) -> Option<LedPulseWidth> {
+    match value {
+        "69" => Some(LedPulseWidth::Pw69),
+        "118" => Some(LedPulseWidth::Pw118),
+        "215" => Some(LedPulseWidth::Pw215),
+        "411" => Some(LedPulseWidth::Pw411),
+        _ => None,
+    }
+}
+
+fn parse_adc_range(value: &str) -> Option<AdcRange> {
+    match value {
+        "2048" => Some(AdcRange::Fs2k),
+        "4096" => Some(AdcRange::Fs4k),
+        "8192" => Some(AdcRange::Fs8k),
+        "16384" => Some(AdcRange::Fs16k),
+        _ => None,
+    }
+}
+
+fn parse_averaging(value: &str) -> Option<SampleAveraging> {
+    match value {
+        "1" => Some(SampleAveraging::Sa1),
+        "2" => Some(SampleAveraging::Sa2),
+        "4" => Some(SampleAveraging::Sa4),
+        "8" => Some(SampleAveraging::Sa8),
+        "16" => Some(SampleAveraging::Sa16),
+        "32" => Some(SampleAveraging::Sa32),
+        _ => None,
+    }
+}
+
+/// Convert a current in mA to a pulse amplitude (0.2 mA/LSB).
+fn parse_led_current(value: &str) -> Option<u8> {
+    let current: f32 = value.parse().ok()?;
+    if (0.0..=51.0).contains(&current) {
+        Some((current / 0.2).round() as u8)
+    } else {
+        None
+    }
+}
+
+fn parse_slots(value: &str) -> Option<[TimeSlot; 4]> {
+    let mut slots = [TimeSlot::Disabled; 4];
+    if value.split(',').count() > slots.len() {
+        return None;
+    }
+    for (slot, name) in slots.iter_mut().zip(value.split(',')) {
+        *slot = match name {
+            "led1" => TimeSlot::Led1,
+            "led2" => TimeSlot::Led2,
+            _ => return None,
+        };
+    }
+    Some(slots)
+}
+
+fn parse_format(value: &str) -> Option<Format> {
+    match value {
+        "csv" => Some(Format::Csv),
+        "json" => Some(Format::Json),
+        _ => None,
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    fn parse<T>(
+        option: &str,
+        value: Option<String>,
+        f: fn(&str) -> Option<T>,
+    ) -> Result<T, String> {
+        let value = value.ok_or_else(|| format!("missing value for {}", option))?;
+        f(&value).ok_or_else(|| format!("invalid value for {}: {}", option, value))
+    }
+
+    let mut options = Options::default();
+    while let Some(option) = args.next() {
+        let value = args.next();
+        match option.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--device" => options.device = parse(&option, value, |v| Some(v.to_string()))?,
+            "--address" => options.address = parse(&option, value, parse_address)?,
+            "--mode" => options.mode = parse(&option, value, parse_mode)?,
+            "--sample-rate" => options.sampling_rate = parse(&option, value, parse_sampling_rate)?,
+            "--pulse-width" => options.pulse_width = parse(&option, value, parse_pulse_width)?,
+            "--adc-range" => options.adc_range = parse(&option, value, parse_adc_range)?,
+            "--averaging" => options.averaging = parse(&option, value, parse_averaging)?,
+            "--led1" => options.led1_amplitude = parse(&option, value, parse_led_current)?,
+            "--led2" => options.led2_amplitude = parse(&option, value, parse_led_current)?,
+            "--slots" => options.slots = parse(&option, value, parse_slots)?,
+            "--format" => options.format = parse(&option, value, parse_format)?,
+            "--count" => options.count = Some(parse(&option, value, |v| v.parse().ok())?),
+            _ => return Err(format!("unknown option: {}", option)),
+        }
+    }
+    Ok(Some(options))
+}
+
+/// Writes the samples in the selected format.
+struct SampleWriter<W> {
+    output: W,
+    format: Format,
+    channels: &'static [&'static str],
+    period_us: f64,
+    sample_index: u64,
+}
+
+impl<W: Write> SampleWriter<W> {
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.format == Format::Csv {
+            write!(self.output, "timestamp_us")?;
+            for channel in self.channels {
+                write!(self.output, ",{}", channel)?;
+            }
+            writeln!(self.output, ",lost")?;
+        }
+        Ok(())
+    }
+
+    fn write_sample(&mut self, values: &[u32], lost: u8) -> io::Result<()> {
+        self.sample_index += u64::from(lost);
+        let timestamp_us = (self.sample_index as f64 * self.period_us).round() as u64;
+        self.sample_index += 1;
+        match self.format {
+            Format::Csv => {
+                write!(self.output, "{}", timestamp_us)?;
+                for value in values {
+                    write!(self.output, ",{}", value)?;
+                }
+                writeln!(self.output, ",{}", lost)
+            }
+            Format::Json => {
+                write!(self.output, "{{\"timestamp_us\":{}", timestamp_us)?;
+                for (channel, value) in self.channels.iter().zip(values) {
+                    write!(self.output, ",\"{}\":{}", channel, value)?;
+                }
+                writeln!(self.output, ",\"lost\":{}}}", lost)
+            }
+        }
+    }
+}
+
+/// Errors stopping the capture.
+enum CliError {
+    Open(String),
+    Device(Error<linux_embedded_hal::I2CError>),
+    Output(io::Error),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Device(Error::I2C(e)) => write!(f, "I2C error: {}", e),
+            CliError::Device(Error::InvalidArguments) => {
+                write!(f, "invalid configuration for the selected mode")
+            }
+            CliError::Device(Error::Timeout) => write!(f, "timeout waiting for samples"),
+            CliError::Device(Error::UnsupportedSamplingConfiguration {
+                pulse_width,
+                sampling_rate,
+            }) => write!(
+                f,
+                "pulse width {:?} and sampling rate {:?} not supported in the selected mode",
+                pulse_width, sampling_rate
+            ),
+            CliError::Open(e) => write!(f, "cannot open I2C device: {}", e),
+            CliError::Output(e) => write!(f, "output error: {}", e),
+        }
+    }
+}
+
+impl From<Error<linux_embedded_hal::I2CError>> for CliError {
+    fn from(e: Error<linux_embedded_hal::I2CError>) -> Self {
+        CliError::Device(e)
+    }
+}
+
+impl<DEV> From<ModeChangeError<linux_embedded_hal::I2CError, DEV>> for CliError {
+    fn from(e: ModeChangeError<linux_embedded_hal::I2CError, DEV>) -> Self {
+        CliError::Device(e.error)
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(e: io::Error) -> Self {
+        CliError::Output(e)
+    }
+}
+
+/// Read the FIFO until the sample count is reached.
+///
+/// `read` returns the number of samples lost since the previous read and
+/// the number of samples read into the buffer.
+fn stream<R>(
+    options: &Options,
+    mut writer: SampleWriter<impl Write>,
+    mut read: R,
+) -> Result<(), CliError>
+where
+    R: FnMut(&mut [u32]) -> Result<(u8, u8), Error<linux_embedded_hal::I2CError>>,
+{
+    let channels = writer.channels.len();
+    let mut buffer = [0; FIFO_DEPTH * 4];
+    let wait = Duration::from_micros((writer.period_us * (FIFO_DEPTH / 4) as f64) as u64);
+    writer.write_header()?;
+    let mut remaining = options.count.unwrap_or(u64::MAX);
+    while remaining != 0 {
+        let (lost, count) = read(&mut buffer[..FIFO_DEPTH * channels])?;
+        for (i, values) in buffer.chunks(channels).take(usize::from(count)).enumerate() {
+            writer.write_sample(values, if i == 0 { lost } else { 0 })?;
+            remaining -= 1;
+            if remaining == 0 {
+                break;
+            }
+        }
+        writer.output.flush()?;
+        if count == 0 {
+            thread::sleep(wait);
+        }
+    }
+    Ok(())
+}
+
+macro_rules! configure {
+    ($sensor:ident, $options:ident) => {
+        $sensor.set_sample_averaging($options.averaging)?;
+        $sensor.set_pulse_width($options.pulse_width)?;
+        $sensor.set_sampling_rate($options.sampling_rate)?;
+        $sensor.set_adc_range($options.adc_range)?;
+        $sensor.set_pulse_amplitude(Led::Led1, $options.led1_amplitude)?;
+        $sensor.set_pulse_amplitude(Led::Led2, $options.led2_amplitude)?;
+    };
+}
+
+macro_rules! read_with_overflow {
+    ($sensor:ident) => {
+        |buffer: &mut [u32]| {
+            let lost = $sensor.get_overflow_sample_count()?;
+            let count = $sensor.read_fifo(buffer)?;
+            Ok((lost, count))
+        }
+    };
+}
+
+fn run(options: &Options) -> Result<(), CliError> {
+    let dev = I2cdev::new(&options.device).map_err(|e| CliError::Open(e.to_string()))?;
+    let sensor = Max3010x::new_max30102_with_address(dev, options.address);
+    let stdout = io::stdout();
+    let writer = |channels, rate: f32| SampleWriter {
+        output: io::BufWriter::new(stdout.lock()),
+        format: options.format,
+        channels,
+        period_us: 1e6 / f64::from(rate),
+        sample_index: 0,
+    };
+    match options.mode {
+        Mode::HeartRate => {
+            let mut sensor = sensor.into_heart_rate()?;
+            configure!(sensor, options);
+            sensor.clear_fifo()?;
+            let writer = writer(&["red"], sensor.get_effective_sampling_rate());
+            stream(options, writer, read_with_overflow!(sensor))
+        }
+        Mode::Oximeter => {
+            let mut sensor = sensor.into_oximeter()?;
+            configure!(sensor, options);
+            sensor.clear_fifo()?;
+            let writer = writer(&["red", "ir"], sensor.get_effective_sampling_rate());
+            stream(options, writer, read_with_overflow!(sensor))
+        }
+        Mode::MultiLed => {
+            let mut sensor = sensor.into_multi_led()?;
+            configure!(sensor, options);
+            sensor.set_led_time_slots(options.slots)?;
+            sensor.clear_fifo()?;
+            let slot_count = options
+                .slots
+                .iter()
+                .filter(|slot| **slot != TimeSlot::Disabled);
+            let channels = &["slot1", "slot2", "slot3", "slot4"][..slot_count.count()];
+            let writer = writer(channels, sensor.get_effective_sampling_rate());
+            stream(options, writer, read_with_overflow!(sensor))
+        }
+    }
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    match run(&options) {
+        Ok(()) => (),
+        Err(CliError::Output(e)) if e.kind() == io::ErrorKind::BrokenPipe => (),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn written(
+        format: Format,
+        channels: &'static [&'static str],
+        samples: &[(&[u32], u8)],
+    ) -> String {
+        let mut writer = SampleWriter {
+            output: Vec::new(),
+            format,
+            channels,
+            period_us: 10_000.0,
+            sample_index: 0,
+        };
+        writer.write_header().unwrap();
+        for (values, lost) in samples {
+            writer.write_sample(values, *lost).unwrap();
+        }
+        String::from_utf8(writer.output).unwrap()
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = parse_args(args(&[
+            "--mode",
+            "hr",
+            "--sample-rate",
+            "400",
+            "--led1",
+            "10.2",
+            "--address",
+            "0x58",
+            "--adc-range",
+            "8192",
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(Mode::HeartRate, options.mode);
+        assert_eq!(SamplingRate::Sps400, options.sampling_rate);
+        assert_eq!(51, options.led1_amplitude);
+        assert_eq!(0x58, options.address);
+        assert_eq!(AdcRange::Fs8k, options.adc_range);
+    }
+
+    #[test]
+    fn parses_one_to_four_slots() {
+        use TimeSlot::*;
+        assert_eq!(
+            Some([Led2, Disabled, Disabled, Disabled]),
+            parse_slots("led2")
+        );
+        assert_eq!(
+            Some([Led1, Led2, Led2, Led1]),
+            parse_slots("led1,led2,led2,led1")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        assert!(parse_args(args(&["--pulse-width", "100"])).is_err());
+        assert!(parse_args(args(&["--led2", "60"])).is_err());
+        assert!(parse_args(args(&["--slots", "led1,led2,led1,led2,led1"])).is_err());
+        assert!(parse_args(args(&["--slots", "led1,red"])).is_err());
+        assert!(parse_args(args(&["--count"])).is_err());
+        assert!(parse_args(args(&["--adc-range", "1024"])).is_err());
+    }
+
+    #[test]
+    fn writes_csv_with_lost_samples() {
+        let output = written(Format::Csv, &["red", "ir"], &[(&[1, 2], 0), (&[3, 4], 2)]);
+        assert_eq!("timestamp_us,red,ir,lost\n0,1,2,0\n30000,3,4,2\n", output);
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        let output = written(Format::Json, &["red"], &[(&[1], 0), (&[2], 0)]);
+        assert_eq!(
+            "{\"timestamp_us\":0,\"red\":1,\"lost\":0}\n{\"timestamp_us\":10000,\"red\":2,\"lost\":0}\n",
+            output
+        );
+    }
+}
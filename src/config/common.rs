@@ -1,7 +1,8 @@
 //! Common device configuration methods.
+use crate::reading::active_slot_count;
 use crate::{
     marker, private, AdcRange, BitFlags as BF, Config, Error, FifoAlmostFullLevelInterrupt,
-    LedPulseWidth, Max3010x, Register as Reg, SampleAveraging, SamplingRate,
+    LedPulseWidth, Max3010x, ModeChangePolicy, Register as Reg, SampleAveraging, SamplingRate,
 };
 use hal::i2c;
 
@@ -34,7 +35,7 @@ macro_rules! flip_flag_method_impl {
         #[doc = $doc]
         pub fn $name(&mut self) -> Result<(), Error<E>> {
             let $reg_variable = self.$reg_variable.$config_method(BF::$bitflag);
-            self.write_data(&[Reg::$reg, $reg_variable.bits])?;
+            self.write_config(&[Reg::$reg, $reg_variable.bits])?;
             self.$reg_variable = $reg_variable;
             Ok(())
         }
@@ -68,7 +69,7 @@ where
 {
     /// Resets the FIFO read and write pointers and overflow counter to 0.
     pub fn clear_fifo(&mut self) -> Result<(), Error<E>> {
-        self.write_data(&[Reg::FIFO_WR_PTR, 0, 0, 0])
+        self.write_config(&[Reg::FIFO_WR_PTR, 0, 0, 0])
     }
 
     /// Set sample averaging
@@ -85,11 +86,31 @@ where
             SampleAveraging::Sa16 => fifo_config.with_high(0b1000_0000),
             SampleAveraging::Sa32 => fifo_config.with_high(0b1010_0000),
         };
-        self.write_data(&[Reg::FIFO_CONFIG, fifo_config.bits])?;
+        self.write_config(&[Reg::FIFO_CONFIG, fifo_config.bits])?;
         self.fifo_config = fifo_config;
         Ok(())
     }
 
+    /// Configure analog-to-digital converter range.
+    ///
+    /// The range applies to all modes and is kept when changing modes.
+    pub fn set_adc_range(&mut self, range: AdcRange) -> Result<(), Error<E>> {
+        use AdcRange::*;
+        let new_config = self
+            .spo2_config
+            .with_low(BF::ADC_RGE0)
+            .with_low(BF::ADC_RGE1);
+        let new_config = match range {
+            Fs2k => new_config,
+            Fs4k => new_config.with_high(BF::ADC_RGE0),
+            Fs8k => new_config.with_high(BF::ADC_RGE1),
+            Fs16k => new_config.with_high(BF::ADC_RGE0).with_high(BF::ADC_RGE1),
+        };
+        self.write_config(&[Reg::SPO2_CONFIG, new_config.bits])?;
+        self.spo2_config = new_config;
+        Ok(())
+    }
+
     /// Trigger a software reset
     pub fn reset(&mut self) -> Result<(), Error<E>> {
         let mode = self.mode.with_high(BF::RESET);
@@ -128,7 +149,7 @@ where
             .fifo_config
             .with_low(0b0000_0111)
             .with_high(level.get_register_value());
-        self.write_data(&[Reg::FIFO_CONFIG, fifo_config.bits])?;
+        self.write_config(&[Reg::FIFO_CONFIG, fifo_config.bits])?;
         self.fifo_config = fifo_config;
         Ok(())
     }
@@ -164,16 +185,91 @@ where
     );
 
     pub(crate) fn change_mode(&mut self, mode: Config) -> Result<(), Error<E>> {
-        self.write_data(&[Reg::MODE, mode.bits])?;
+        self.write_config(&[Reg::MODE, mode.bits])?;
         self.mode = mode;
         Ok(())
     }
+
+    /// Change into `mode` after checking the pulse width and sampling rate
+    /// for `NEWMODE`, then clear the FIFO.
+    pub(crate) fn change_mode_with_policy<NEWMODE: ValidateSrPw>(
+        &mut self,
+        mode: Config,
+        policy: ModeChangePolicy,
+    ) -> Result<(), Error<E>> {
+        let previous = self.spo2_config.clone();
+        let spo2_config = self.mode_change_spo2_config::<NEWMODE>(policy)?;
+        let adjusted = spo2_config != previous;
+        if adjusted {
+            self.write_config(&[Reg::SPO2_CONFIG, spo2_config.bits])?;
+            self.spo2_config = spo2_config;
+        }
+        if let Err(error) = self.change_mode(mode) {
+            // Keep the driver in its previous configuration as far as possible.
+            if adjusted
+                && self
+                    .write_config(&[Reg::SPO2_CONFIG, previous.bits])
+                    .is_ok()
+            {
+                self.spo2_config = previous;
+            }
+            return Err(error);
+        }
+        self.clear_fifo()
+    }
+
+    /// Get the SpO2 configuration for `NEWMODE`, with the pulse width and
+    /// sampling rate adjusted according to the policy if they are not valid.
+    fn mode_change_spo2_config<NEWMODE: ValidateSrPw>(
+        &self,
+        policy: ModeChangePolicy,
+    ) -> Result<Config, Error<E>> {
+        let (slot_config0, slot_config1) = (self.slot_config0.bits, self.slot_config1.bits);
+        let is_valid =
+            |width, rate| NEWMODE::check::<E>(slot_config0, slot_config1, width, rate).is_ok();
+        let width = self.get_pulse_width();
+        let rate = self.get_sampling_rate();
+        let error = match NEWMODE::check::<E>(slot_config0, slot_config1, width, rate) {
+            Ok(()) => return Ok(self.spo2_config.clone()),
+            Err(error) => error,
+        };
+        let lower_rates = SamplingRate::ALL.iter().rev().skip_while(|r| **r != rate);
+        let adjusted = match policy {
+            ModeChangePolicy::Fail => None,
+            ModeChangePolicy::ReduceSamplingRate => lower_rates
+                .filter(|r| is_valid(width, **r))
+                .map(|r| (width, *r))
+                .next(),
+            ModeChangePolicy::ReducePulseWidth => {
+                let shorter_widths = LedPulseWidth::ALL.iter().rev().skip_while(|w| **w != width);
+                let pw69 = LedPulseWidth::Pw69;
+                shorter_widths
+                    .filter(|w| is_valid(**w, rate))
+                    .map(|w| (*w, rate))
+                    .chain(
+                        lower_rates
+                            .filter(|r| is_valid(pw69, **r))
+                            .map(|r| (pw69, *r)),
+                    )
+                    .next()
+            }
+        };
+        let (width, rate) = adjusted.ok_or(error)?;
+        Ok(rate.apply(&width.apply(&self.spo2_config)))
+    }
 }
 
 #[doc(hidden)]
 pub trait ValidateSrPw: private::Sealed {
-    /// Check the pulse width and sample rate combination
-    fn check<E>(width: LedPulseWidth, rate: SamplingRate) -> Result<(), Error<E>>;
+    /// Check the pulse width and sample rate combination for the time slot
+    /// configuration, returning `Error::UnsupportedSamplingConfiguration` if
+    /// it is not supported
+    fn check<E>(
+        slot_config0: u8,
+        slot_config1: u8,
+        width: LedPulseWidth,
+        rate: SamplingRate,
+    ) -> Result<(), Error<E>>;
 }
 
 fn check_red_only<E>(pw: LedPulseWidth, sr: SamplingRate) -> Result<(), Error<E>> {
@@ -183,7 +279,10 @@ fn check_red_only<E>(pw: LedPulseWidth, sr: SamplingRate) -> Result<(), Error<E>
     if (sr == Sps3200 && (pw == Pw118 || pw == Pw215 || pw == Pw411))
         || (sr == Sps1600 && pw == Pw411)
     {
-        Err(Error::InvalidArguments)
+        Err(Error::UnsupportedSamplingConfiguration {
+            pulse_width: pw,
+            sampling_rate: sr,
+        })
     } else {
         Ok(())
     }
@@ -198,66 +297,85 @@ fn check_red_ir<E>(pw: LedPulseWidth, sr: SamplingRate) -> Result<(), Error<E>>
         || (sr == Sps1000 && (pw == Pw215 || pw == Pw411))
         || (sr == Sps800 && pw == Pw411)
     {
-        Err(Error::InvalidArguments)
+        Err(Error::UnsupportedSamplingConfiguration {
+            pulse_width: pw,
+            sampling_rate: sr,
+        })
     } else {
         Ok(())
     }
 }
 
 impl ValidateSrPw for marker::mode::HeartRate {
-    fn check<E>(pw: LedPulseWidth, sr: SamplingRate) -> Result<(), Error<E>> {
+    fn check<E>(_: u8, _: u8, pw: LedPulseWidth, sr: SamplingRate) -> Result<(), Error<E>> {
         check_red_only(pw, sr)
     }
 }
 
 impl ValidateSrPw for marker::mode::Oximeter {
-    fn check<E>(pw: LedPulseWidth, sr: SamplingRate) -> Result<(), Error<E>> {
+    fn check<E>(_: u8, _: u8, pw: LedPulseWidth, sr: SamplingRate) -> Result<(), Error<E>> {
         check_red_ir(pw, sr)
     }
 }
 
 impl ValidateSrPw for marker::mode::MultiLed {
-    fn check<E>(_width: LedPulseWidth, _rate: SamplingRate) -> Result<(), Error<E>> {
-        Ok(())
+    fn check<E>(
+        slot_config0: u8,
+        slot_config1: u8,
+        pw: LedPulseWidth,
+        sr: SamplingRate,
+    ) -> Result<(), Error<E>> {
+        // The datasheet only lists the limits for one and two LEDs, so
+        // three and four time slots are not checked.
+        match active_slot_count(slot_config0, slot_config1) {
+            1 => check_red_only(pw, sr),
+            2 => check_red_ir(pw, sr),
+            _ => Ok(()),
+        }
     }
 }
 
-impl<I2C, E, IC, MODE> Max3010x<I2C, IC, MODE>
-where
-    I2C: i2c::I2c<Error = E>,
-    MODE: ValidateSrPw,
-{
-    /// Configure the LED pulse width.
-    ///
-    /// This determines the ADC resolution.
-    pub fn set_pulse_width(&mut self, width: LedPulseWidth) -> Result<(), Error<E>> {
+impl LedPulseWidth {
+    /// Pulse widths from the shortest to the longest.
+    const ALL: [LedPulseWidth; 4] = [
+        LedPulseWidth::Pw69,
+        LedPulseWidth::Pw118,
+        LedPulseWidth::Pw215,
+        LedPulseWidth::Pw411,
+    ];
+
+    fn apply(self, spo2_config: &Config) -> Config {
         use LedPulseWidth::*;
-        MODE::check::<E>(width, self.get_sampling_rate())?;
-        let config = self.spo2_config.with_low(BF::LED_PW0).with_low(BF::LED_PW1);
-        let config = match width {
+        let config = spo2_config.with_low(BF::LED_PW0).with_low(BF::LED_PW1);
+        match self {
             Pw69 => config,
             Pw118 => config.with_high(BF::LED_PW0),
             Pw215 => config.with_high(BF::LED_PW1),
             Pw411 => config.with_high(BF::LED_PW0).with_high(BF::LED_PW1),
-        };
-        self.write_data(&[Reg::SPO2_CONFIG, config.bits])?;
-        self.spo2_config = config;
-        Ok(())
+        }
     }
+}
 
-    /// Configure the sample rate
-    ///
-    /// This depends on the LED pulse width. Calling this with an inappropriate
-    /// value for the selected pulse with will return `Error::InvalidArgument`
-    pub fn set_sampling_rate(&mut self, sampling_rate: SamplingRate) -> Result<(), Error<E>> {
+impl SamplingRate {
+    /// Sampling rates from the lowest to the highest.
+    const ALL: [SamplingRate; 8] = [
+        SamplingRate::Sps50,
+        SamplingRate::Sps100,
+        SamplingRate::Sps200,
+        SamplingRate::Sps400,
+        SamplingRate::Sps800,
+        SamplingRate::Sps1000,
+        SamplingRate::Sps1600,
+        SamplingRate::Sps3200,
+    ];
+
+    fn apply(self, spo2_config: &Config) -> Config {
         use SamplingRate::*;
-        MODE::check::<E>(self.get_pulse_width(), sampling_rate)?;
-        let config = self
-            .spo2_config
+        let config = spo2_config
             .with_low(BF::SPO2_SR0)
             .with_low(BF::SPO2_SR1)
             .with_low(BF::SPO2_SR2);
-        let config = match sampling_rate {
+        match self {
             Sps50 => config,
             Sps100 => config.with_high(BF::SPO2_SR0),
             Sps200 => config.with_high(BF::SPO2_SR1),
@@ -269,32 +387,52 @@ where
                 .with_high(BF::SPO2_SR2)
                 .with_high(BF::SPO2_SR1)
                 .with_high(BF::SPO2_SR0),
-        };
-        self.write_data(&[Reg::SPO2_CONFIG, config.bits])?;
-        self.spo2_config = config;
-        Ok(())
+        }
     }
 }
 
-impl<I2C, E, IC> Max3010x<I2C, IC, marker::mode::Oximeter>
+impl<I2C, E, IC, MODE> Max3010x<I2C, IC, MODE>
 where
     I2C: i2c::I2c<Error = E>,
+    MODE: ValidateSrPw,
 {
-    /// Configure analog-to-digital converter range. (Only available in Oximeter mode)
-    pub fn set_adc_range(&mut self, range: AdcRange) -> Result<(), Error<E>> {
-        use AdcRange::*;
-        let new_config = self
-            .spo2_config
-            .with_low(BF::ADC_RGE0)
-            .with_low(BF::ADC_RGE1);
-        let new_config = match range {
-            Fs2k => new_config,
-            Fs4k => new_config.with_high(BF::ADC_RGE0),
-            Fs8k => new_config.with_high(BF::ADC_RGE1),
-            Fs16k => new_config.with_high(BF::ADC_RGE0).with_high(BF::ADC_RGE1),
-        };
-        self.write_data(&[Reg::SPO2_CONFIG, new_config.bits])?;
-        self.spo2_config = new_config;
+    /// Configure the LED pulse width.
+    ///
+    /// This determines the ADC resolution. Calling this with a pulse width
+    /// which is not supported at the configured sampling rate will return
+    /// `Error::UnsupportedSamplingConfiguration`.
+    pub fn set_pulse_width(&mut self, width: LedPulseWidth) -> Result<(), Error<E>> {
+        MODE::check::<E>(
+            self.slot_config0.bits,
+            self.slot_config1.bits,
+            width,
+            self.get_sampling_rate(),
+        )?;
+        let config = width.apply(&self.spo2_config);
+        self.write_config(&[Reg::SPO2_CONFIG, config.bits])?;
+        self.spo2_config = config;
+        Ok(())
+    }
+
+    /// Configure the sample rate
+    ///
+    /// This depends on the LED pulse width. Calling this with an inappropriate
+    /// value for the selected pulse with will return
+    /// `Error::UnsupportedSamplingConfiguration`.
+    ///
+    /// In multi-LED mode the limits depend on the number of enabled time
+    /// slots. The datasheet only lists them for one and two LEDs, so no
+    /// limits are checked with no or more than two enabled time slots.
+    pub fn set_sampling_rate(&mut self, sampling_rate: SamplingRate) -> Result<(), Error<E>> {
+        MODE::check::<E>(
+            self.slot_config0.bits,
+            self.slot_config1.bits,
+            self.get_pulse_width(),
+            sampling_rate,
+        )?;
+        let config = sampling_rate.apply(&self.spo2_config);
+        self.write_config(&[Reg::SPO2_CONFIG, config.bits])?;
+        self.spo2_config = config;
         Ok(())
     }
 }
@@ -304,6 +442,7 @@ pub trait HasDataReadyInterrupt {}
 
 impl HasDataReadyInterrupt for marker::mode::HeartRate {}
 impl HasDataReadyInterrupt for marker::mode::Oximeter {}
+impl HasDataReadyInterrupt for marker::mode::MultiLed {}
 
 impl<I2C, E, IC, MODE> Max3010x<I2C, IC, MODE>
 where
@@ -1,6 +1,9 @@
 //! Max30102-specific configuration methods.
-use crate::{marker, Error, Led, Max3010x, Register as Reg, TimeSlot};
-use core::marker::PhantomData;
+use crate::config::common::ValidateSrPw;
+use crate::{
+    marker, Config, Error, Led, Max3010x, ModeChangeError, ModeChangePolicy, Register as Reg,
+    TimeSlot,
+};
 use hal::i2c;
 
 impl<I2C, E, MODE> Max3010x<I2C, marker::ic::Max30102, MODE>
@@ -10,70 +13,102 @@ where
     /// Change into heart-rate mode.
     ///
     /// This changes the mode and clears the FIFO data.
+    ///
+    /// If the configured pulse width and sampling rate are not supported in
+    /// heart-rate mode, `Error::UnsupportedSamplingConfiguration` is returned
+    /// together with the unchanged driver in a [`ModeChangeError`]. See
+    /// [`into_heart_rate_with_policy()`](Max3010x::into_heart_rate_with_policy).
     pub fn into_heart_rate(
+        self,
+    ) -> Result<
+        Max3010x<I2C, marker::ic::Max30102, marker::mode::HeartRate>,
+        ModeChangeError<E, Self>,
+    > {
+        self.into_heart_rate_with_policy(ModeChangePolicy::Fail)
+    }
+
+    /// Change into heart-rate mode handling an unsupported pulse width and
+    /// sampling rate combination according to `policy`.
+    ///
+    /// This changes the mode and clears the FIFO data. On failure, the
+    /// driver is returned in the error in its previous mode.
+    pub fn into_heart_rate_with_policy(
         mut self,
-    ) -> Result<Max3010x<I2C, marker::ic::Max30102, marker::mode::HeartRate>, Error<E>> {
+        policy: ModeChangePolicy,
+    ) -> Result<
+        Max3010x<I2C, marker::ic::Max30102, marker::mode::HeartRate>,
+        ModeChangeError<E, Self>,
+    > {
         let mode = self.mode.with_low(0b0000_0101).with_high(0b0000_0010);
-        self.change_mode(mode)?;
-        self.clear_fifo()?;
-        let dev = Max3010x {
-            i2c: self.i2c,
-            temperature_measurement_started: self.temperature_measurement_started,
-            mode: self.mode,
-            fifo_config: self.fifo_config,
-            spo2_config: self.spo2_config,
-            int_en1: self.int_en1,
-            int_en2: self.int_en2,
-            _ic: PhantomData,
-            _mode: PhantomData,
-        };
-        Ok(dev)
+        match self.change_mode_with_policy::<marker::mode::HeartRate>(mode, policy) {
+            Ok(()) => Ok(self.with_mode_marker()),
+            Err(error) => Err(ModeChangeError { error, dev: self }),
+        }
     }
 
     /// Change into SpO2 (oximeter) mode.
     ///
     /// This changes the mode and clears the FIFO data.
+    ///
+    /// If the configured pulse width and sampling rate are not supported in
+    /// SpO2 mode, `Error::UnsupportedSamplingConfiguration` is returned
+    /// together with the unchanged driver in a [`ModeChangeError`]. See
+    /// [`into_oximeter_with_policy()`](Max3010x::into_oximeter_with_policy).
     pub fn into_oximeter(
+        self,
+    ) -> Result<Max3010x<I2C, marker::ic::Max30102, marker::mode::Oximeter>, ModeChangeError<E, Self>>
+    {
+        self.into_oximeter_with_policy(ModeChangePolicy::Fail)
+    }
+
+    /// Change into SpO2 (oximeter) mode handling an unsupported pulse width
+    /// and sampling rate combination according to `policy`.
+    ///
+    /// This changes the mode and clears the FIFO data. On failure, the
+    /// driver is returned in the error in its previous mode.
+    pub fn into_oximeter_with_policy(
         mut self,
-    ) -> Result<Max3010x<I2C, marker::ic::Max30102, marker::mode::Oximeter>, Error<E>> {
+        policy: ModeChangePolicy,
+    ) -> Result<Max3010x<I2C, marker::ic::Max30102, marker::mode::Oximeter>, ModeChangeError<E, Self>>
+    {
         let mode = self.mode.with_low(0b0000_0100).with_high(0b0000_0011);
-        self.change_mode(mode)?;
-        self.clear_fifo()?;
-        let dev = Max3010x {
-            i2c: self.i2c,
-            temperature_measurement_started: self.temperature_measurement_started,
-            mode: self.mode,
-            fifo_config: self.fifo_config,
-            spo2_config: self.spo2_config,
-            int_en1: self.int_en1,
-            int_en2: self.int_en2,
-            _ic: PhantomData,
-            _mode: PhantomData,
-        };
-        Ok(dev)
+        match self.change_mode_with_policy::<marker::mode::Oximeter>(mode, policy) {
+            Ok(()) => Ok(self.with_mode_marker()),
+            Err(error) => Err(ModeChangeError { error, dev: self }),
+        }
     }
 
     /// Change into multi-LED mode.
     ///
     /// This changes the mode and clears the FIFO data.
+    ///
+    /// If the configured pulse width and sampling rate are not supported
+    /// with the configured time slots, `Error::UnsupportedSamplingConfiguration`
+    /// is returned together with the unchanged driver in a
+    /// [`ModeChangeError`]. See
+    /// [`into_multi_led_with_policy()`](Max3010x::into_multi_led_with_policy).
     pub fn into_multi_led(
+        self,
+    ) -> Result<Max3010x<I2C, marker::ic::Max30102, marker::mode::MultiLed>, ModeChangeError<E, Self>>
+    {
+        self.into_multi_led_with_policy(ModeChangePolicy::Fail)
+    }
+
+    /// Change into multi-LED mode handling an unsupported pulse width and
+    /// sampling rate combination according to `policy`.
+    ///
+    /// This changes the mode and clears the FIFO data. On failure, the
+    /// driver is returned in the error in its previous mode.
+    pub fn into_multi_led_with_policy(
         mut self,
-    ) -> Result<Max3010x<I2C, marker::ic::Max30102, marker::mode::MultiLed>, Error<E>> {
+        policy: ModeChangePolicy,
+    ) -> Result<Max3010x<I2C, marker::ic::Max30102, marker::mode::MultiLed>, ModeChangeError<E, Self>>
+    {
         let mode = self.mode.with_high(0b0000_0111);
-        self.change_mode(mode)?;
-        self.clear_fifo()?;
-        let dev = Max3010x {
-            i2c: self.i2c,
-            temperature_measurement_started: self.temperature_measurement_started,
-            mode: self.mode,
-            fifo_config: self.fifo_config,
-            spo2_config: self.spo2_config,
-            int_en1: self.int_en1,
-            int_en2: self.int_en2,
-            _ic: PhantomData,
-            _mode: PhantomData,
-        };
-        Ok(dev)
+        match self.change_mode_with_policy::<marker::mode::MultiLed>(mode, policy) {
+            Ok(()) => Ok(self.with_mode_marker()),
+            Err(error) => Err(ModeChangeError { error, dev: self }),
+        }
     }
 
     /// Set the LED pulse amplitude
@@ -81,16 +116,28 @@ where
     /// The amplitude value corresponds to a typical current of 0.0 mA for 0
     /// up to 51.0 mA for 255.
     pub fn set_pulse_amplitude(&mut self, led: Led, amplitude: u8) -> Result<(), Error<E>> {
+        let config = Config { bits: amplitude };
         match led {
-            Led::Led1 => self.write_data(&[Reg::LED1_PA, amplitude]),
-            Led::Led2 => self.write_data(&[Reg::LED2_PA, amplitude]),
-            Led::All => self.write_data(&[Reg::LED1_PA, amplitude, amplitude]),
+            Led::Led1 => {
+                self.write_config(&[Reg::LED1_PA, amplitude])?;
+                self.led1_pa = config;
+            }
+            Led::Led2 => {
+                self.write_config(&[Reg::LED2_PA, amplitude])?;
+                self.led2_pa = config;
+            }
+            Led::All => {
+                self.write_config(&[Reg::LED1_PA, amplitude, amplitude])?;
+                self.led1_pa = config.clone();
+                self.led2_pa = config;
+            }
         }
+        Ok(())
     }
 }
 
 impl TimeSlot {
-    fn get_mask(self) -> u8 {
+    pub(crate) fn get_mask(self) -> u8 {
         match self {
             TimeSlot::Disabled => 0,
             TimeSlot::Led1 => 1,
@@ -108,6 +155,11 @@ where
     /// The slots should be activated in order. i.e. slot 2 cannot be
     /// activated if slot 1 is disabled.
     /// Failing to do so will return `Error::InvalidArguments`.
+    ///
+    /// The configured pulse width and sampling rate must be valid for the
+    /// resulting number of LEDs, otherwise
+    /// `Error::UnsupportedSamplingConfiguration` is returned. This is only checked for one and two LEDs, the
+    /// limits for more are not specified in the datasheet.
     pub fn set_led_time_slots(&mut self, slots: [TimeSlot; 4]) -> Result<(), Error<E>> {
         use TimeSlot::Disabled;
         let mut last_slot_is_disabled = slots[0] == Disabled;
@@ -117,11 +169,21 @@ where
             }
             last_slot_is_disabled = *slot == Disabled;
         }
-        let data = [
-            Reg::SLOT_CONFIG0,
-            slots[1].get_mask() << 4 | slots[0].get_mask(),
-            slots[3].get_mask() << 4 | slots[2].get_mask(),
-        ];
-        self.write_data(&data)
+        let slot_config0 = Config {
+            bits: slots[1].get_mask() << 4 | slots[0].get_mask(),
+        };
+        let slot_config1 = Config {
+            bits: slots[3].get_mask() << 4 | slots[2].get_mask(),
+        };
+        marker::mode::MultiLed::check::<E>(
+            slot_config0.bits,
+            slot_config1.bits,
+            self.get_pulse_width(),
+            self.get_sampling_rate(),
+        )?;
+        self.write_config(&[Reg::SLOT_CONFIG0, slot_config0.bits, slot_config1.bits])?;
+        self.slot_config0 = slot_config0;
+        self.slot_config1 = slot_config1;
+        Ok(())
     }
 }
@@ -0,0 +1,282 @@
+//! Register dump and comparison against the cached configuration.
+
+use crate::{
+    reading::{
+        convert_adc_range, convert_interrupt_status, convert_pulse_width, convert_sample_averaging,
+        convert_sampling_rate,
+    },
+    AdcRange, BitFlags as BF, Error, FifoAlmostFullLevelInterrupt, InterruptStatus, LedPulseWidth,
+    Max3010x, Register as Reg, SampleAveraging, SamplingRate, TimeSlot,
+};
+use hal::i2c;
+
+/// Contents of all documented device registers.
+///
+/// Besides the raw register contents, the decoded fields are provided.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegisterDump {
+    /// Raw contents of the registers `0x00` to `0x21` indexed by address.
+    ///
+    /// The FIFO data register (`0x07`) is not read since that would
+    /// consume FIFO samples, so its value is always 0.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_array"))]
+    pub registers: [u8; 0x22],
+    /// Proximity interrupt threshold register (`0x30`)
+    pub proximity_interrupt_threshold: u8,
+    /// Revision ID
+    pub revision_id: u8,
+    /// Part ID
+    pub part_id: u8,
+    /// Interrupt status flags at the time of reading.
+    ///
+    /// Note that reading the interrupt status clears it on the device.
+    pub interrupt_status: InterruptStatus,
+    /// FIFO-almost-full interrupt enabled
+    pub fifo_almost_full_interrupt_enabled: bool,
+    /// New-FIFO-data-ready interrupt enabled
+    pub new_fifo_data_ready_interrupt_enabled: bool,
+    /// Ambient-light-cancellation overflow interrupt enabled
+    pub alc_overflow_interrupt_enabled: bool,
+    /// Temperature-ready interrupt enabled
+    pub temperature_ready_interrupt_enabled: bool,
+    /// FIFO write pointer
+    pub fifo_write_pointer: u8,
+    /// FIFO overflow counter
+    pub fifo_overflow_counter: u8,
+    /// FIFO read pointer
+    pub fifo_read_pointer: u8,
+    /// Sample averaging
+    pub sample_averaging: SampleAveraging,
+    /// FIFO rollover enabled
+    pub fifo_rollover_enabled: bool,
+    /// FIFO-almost-full interrupt level
+    pub fifo_almost_full_level: FifoAlmostFullLevelInterrupt,
+    /// Device is shut down
+    pub shutdown: bool,
+    /// Mode control bits (`0b010`: heart-rate, `0b011`: SpO2, `0b111`: multi-LED)
+    pub mode: u8,
+    /// ADC range
+    pub adc_range: AdcRange,
+    /// Sampling rate
+    pub sampling_rate: SamplingRate,
+    /// LED pulse width
+    pub pulse_width: LedPulseWidth,
+    /// LED1 pulse amplitude
+    pub led1_pulse_amplitude: u8,
+    /// LED2 pulse amplitude
+    pub led2_pulse_amplitude: u8,
+    /// Multi-LED mode time slots.
+    ///
+    /// `None` if the slot contains a value not supported by the device.
+    pub time_slots: [Option<TimeSlot>; 4],
+    /// Result of the last die temperature conversion in °C
+    pub temperature: f32,
+    /// Temperature conversion ongoing
+    pub temperature_conversion_ongoing: bool,
+}
+
+/// Register whose contents differ from the driver's cached configuration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegisterMismatch {
+    /// Register address
+    pub register: u8,
+    /// Value the driver wrote (relevant bits only)
+    pub expected: u8,
+    /// Value read from the device (relevant bits only)
+    pub actual: u8,
+}
+
+const DIFF_CAPACITY: usize = 9;
+
+/// Registers whose contents differ from the driver's cached configuration.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "RegisterDiffFields")
+)]
+pub struct RegisterDiff {
+    mismatches: [RegisterMismatch; DIFF_CAPACITY],
+    len: usize,
+}
+
+/// Unvalidated contents of a deserialized [`RegisterDiff`].
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct RegisterDiffFields {
+    mismatches: [RegisterMismatch; DIFF_CAPACITY],
+    len: usize,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RegisterDiffFields> for RegisterDiff {
+    type Error = &'static str;
+
+    fn try_from(fields: RegisterDiffFields) -> Result<Self, Self::Error> {
+        if fields.len > DIFF_CAPACITY {
+            return Err("more register mismatches than registers compared");
+        }
+        Ok(RegisterDiff {
+            mismatches: fields.mismatches,
+            len: fields.len,
+        })
+    }
+}
+
+impl RegisterDiff {
+    fn new() -> Self {
+        RegisterDiff {
+            mismatches: [RegisterMismatch {
+                register: 0,
+                expected: 0,
+                actual: 0,
+            }; DIFF_CAPACITY],
+            len: 0,
+        }
+    }
+
+    fn compare(&mut self, register: u8, mask: u8, expected: u8, actual: u8) {
+        let expected = expected & mask;
+        let actual = actual & mask;
+        if expected != actual {
+            self.mismatches[self.len] = RegisterMismatch {
+                register,
+                expected,
+                actual,
+            };
+            self.len += 1;
+        }
+    }
+
+    /// Whether the device contents match the cached configuration.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Registers which do not match.
+    pub fn as_slice(&self) -> &[RegisterMismatch] {
+        &self.mismatches[..self.len]
+    }
+}
+
+fn convert_fifo_almost_full_level(fifo_config: u8) -> FifoAlmostFullLevelInterrupt {
+    use FifoAlmostFullLevelInterrupt as L;
+    match fifo_config & 0b0000_1111 {
+        0 => L::L0,
+        1 => L::L1,
+        2 => L::L2,
+        3 => L::L3,
+        4 => L::L4,
+        5 => L::L5,
+        6 => L::L6,
+        7 => L::L7,
+        8 => L::L8,
+        9 => L::L9,
+        10 => L::L10,
+        11 => L::L11,
+        12 => L::L12,
+        13 => L::L13,
+        14 => L::L14,
+        _ => L::L15,
+    }
+}
+
+pub(crate) fn convert_time_slot(mask: u8) -> Option<TimeSlot> {
+    match mask & 0b111 {
+        0 => Some(TimeSlot::Disabled),
+        1 => Some(TimeSlot::Led1),
+        2 => Some(TimeSlot::Led2),
+        _ => None,
+    }
+}
+
+impl RegisterDump {
+    fn decode(registers: [u8; 0x22], proximity: u8, revision_id: u8, part_id: u8) -> Self {
+        let r = |reg: u8| registers[usize::from(reg)];
+        let int_en1 = r(Reg::INT_EN1);
+        let fifo_config = r(Reg::FIFO_CONFIG);
+        let spo2_config = r(Reg::SPO2_CONFIG);
+        let temp_int = r(Reg::TEMP_INT) as i8;
+        let temp_frac = f32::from(r(Reg::TEMP_FRAC) & 0x0F) * 0.0625;
+        RegisterDump {
+            registers,
+            proximity_interrupt_threshold: proximity,
+            revision_id,
+            part_id,
+            interrupt_status: convert_interrupt_status(r(Reg::INT_STATUS), r(Reg::INT_STATUS2)),
+            fifo_almost_full_interrupt_enabled: int_en1 & BF::FIFO_A_FULL_INT != 0,
+            new_fifo_data_ready_interrupt_enabled: int_en1 & BF::PPG_RDY_INT != 0,
+            alc_overflow_interrupt_enabled: int_en1 & BF::ALC_OVF_INT != 0,
+            temperature_ready_interrupt_enabled: r(Reg::INT_EN2) & BF::DIE_TEMP_RDY_INT != 0,
+            fifo_write_pointer: r(Reg::FIFO_WR_PTR) & 0x1F,
+            fifo_overflow_counter: r(Reg::OVF_COUNTER) & 0x1F,
+            fifo_read_pointer: r(Reg::FIFO_RD_PTR) & 0x1F,
+            sample_averaging: convert_sample_averaging(fifo_config),
+            fifo_rollover_enabled: fifo_config & BF::FIFO_ROLLOVER_EN != 0,
+            fifo_almost_full_level: convert_fifo_almost_full_level(fifo_config),
+            shutdown: r(Reg::MODE) & BF::SHUTDOWN != 0,
+            mode: r(Reg::MODE) & 0b0000_0111,
+            adc_range: convert_adc_range(spo2_config),
+            sampling_rate: convert_sampling_rate(spo2_config),
+            pulse_width: convert_pulse_width(spo2_config),
+            led1_pulse_amplitude: r(Reg::LED1_PA),
+            led2_pulse_amplitude: r(Reg::LED2_PA),
+            time_slots: [
+                convert_time_slot(r(Reg::SLOT_CONFIG0)),
+                convert_time_slot(r(Reg::SLOT_CONFIG0) >> 4),
+                convert_time_slot(r(Reg::SLOT_CONFIG1)),
+                convert_time_slot(r(Reg::SLOT_CONFIG1) >> 4),
+            ],
+            temperature: f32::from(temp_int) + temp_frac,
+            temperature_conversion_ongoing: r(Reg::TEMP_CONFIG) & BF::TEMP_EN != 0,
+        }
+    }
+}
+
+impl<I2C, E, IC, MODE> Max3010x<I2C, IC, MODE>
+where
+    I2C: i2c::I2c<Error = E>,
+{
+    /// Read all documented registers.
+    ///
+    /// The FIFO data register is skipped so that no samples are lost.
+    /// Note that reading the interrupt status registers clears them.
+    pub fn dump_registers(&mut self) -> Result<RegisterDump, Error<E>> {
+        let mut registers = [0; 0x22];
+        let fifo_data = usize::from(Reg::FIFO_DATA);
+        self.read_data(Reg::INT_STATUS, &mut registers[..fifo_data])?;
+        self.read_data(Reg::FIFO_CONFIG, &mut registers[fifo_data + 1..])?;
+        let proximity = self.read_register(Reg::PROX_INT_THRESH)?;
+        let mut ids = [0; 2];
+        self.read_data(Reg::REV_ID, &mut ids)?;
+        Ok(RegisterDump::decode(registers, proximity, ids[0], ids[1]))
+    }
+
+    /// Compare a register dump with the configuration the driver wrote.
+    ///
+    /// Only the bits under control of the driver are compared.
+    pub fn diff_registers(&self, dump: &RegisterDump) -> RegisterDiff {
+        let mut diff = RegisterDiff::new();
+        let actual = |reg: u8| dump.registers[usize::from(reg)];
+        let shadows = [
+            (Reg::INT_EN1, 0b1110_0000, &self.int_en1),
+            (Reg::INT_EN2, BF::DIE_TEMP_RDY_INT, &self.int_en2),
+            (Reg::FIFO_CONFIG, 0b1111_1111, &self.fifo_config),
+            (Reg::MODE, BF::SHUTDOWN | 0b0000_0111, &self.mode),
+            (Reg::SPO2_CONFIG, 0b0111_1111, &self.spo2_config),
+            (Reg::LED1_PA, 0b1111_1111, &self.led1_pa),
+            (Reg::LED2_PA, 0b1111_1111, &self.led2_pa),
+            (Reg::SLOT_CONFIG0, 0b0111_0111, &self.slot_config0),
+            (Reg::SLOT_CONFIG1, 0b0111_0111, &self.slot_config1),
+        ];
+        for (reg, mask, config) in shadows.iter() {
+            diff.compare(*reg, *mask, config.bits, actual(*reg));
+        }
+        diff
+    }
+}
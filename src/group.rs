@@ -0,0 +1,199 @@
+//! Synchronized acquisition from several devices.
+
+use crate::{reading::ChannelCount, BitFlags as BF, Error, Max3010x};
+use hal::i2c;
+
+/// Result of a synchronized FIFO read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GroupRead<const N: usize> {
+    /// Number of samples read from each device.
+    pub sample_count: u8,
+    /// Timestamp of the first sample read from each device in µs.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_array"))]
+    pub timestamps_us: [u64; N],
+    /// Time offset of the first sample of each device relative to the
+    /// first device in µs.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_array"))]
+    pub skew_us: [i64; N],
+    /// Number of samples each device lost because its FIFO was full, as
+    /// reported by its overflow counter.
+    ///
+    /// The timestamps take the lost samples into account. With FIFO rollover
+    /// enabled the lost samples precede the samples in the FIFO, otherwise
+    /// they follow them.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_array"))]
+    pub lost_sample_counts: [u8; N],
+}
+
+/// Group of devices whose FIFOs are started and read in lockstep.
+///
+/// This is useful for example to measure the pulse transit time between
+/// two measurement sites. The devices can share the bus through
+/// [`embedded-hal-bus`](https://crates.io/crates/embedded-hal-bus) devices
+/// or sit behind an I²C multiplexer.
+///
+/// All devices should be configured with the same sampling rate and
+/// sample averaging before calling [`start()`](SensorGroup::start).
+#[derive(Debug)]
+pub struct SensorGroup<I2C, IC, MODE, const N: usize> {
+    sensors: [Max3010x<I2C, IC, MODE>; N],
+    start_times_us: [u64; N],
+    samples_read: [u64; N],
+    pending_discard: [u8; N],
+    /// Lost samples not yet accounted in `samples_read` because they follow
+    /// samples still in the FIFO.
+    pending_lost: [u8; N],
+    samples_before_lost: [u8; N],
+}
+
+impl<I2C, E, IC, MODE, const N: usize> SensorGroup<I2C, IC, MODE, N>
+where
+    I2C: i2c::I2c<Error = E>,
+    MODE: ChannelCount<IC, MODE>,
+{
+    /// Create a new group from already configured devices.
+    pub fn new(sensors: [Max3010x<I2C, IC, MODE>; N]) -> Self {
+        SensorGroup {
+            sensors,
+            start_times_us: [0; N],
+            samples_read: [0; N],
+            pending_discard: [0; N],
+            pending_lost: [0; N],
+            samples_before_lost: [0; N],
+        }
+    }
+
+    /// Destroy the group and return the devices.
+    pub fn destroy(self) -> [Max3010x<I2C, IC, MODE>; N] {
+        self.sensors
+    }
+
+    /// Access the devices, for example for reconfiguration.
+    ///
+    /// Call [`start()`](SensorGroup::start) again afterwards.
+    pub fn sensors_mut(&mut self) -> &mut [Max3010x<I2C, IC, MODE>; N] {
+        &mut self.sensors
+    }
+
+    /// Clear the FIFO of all devices as simultaneously as possible.
+    ///
+    /// `now_us` must return a monotonic timestamp in µs. It is called around
+    /// each FIFO clear so that the start time of every device is known.
+    /// If a device starts more than half a sample period after another one,
+    /// the first samples of the earlier device are discarded so that the
+    /// samples returned by [`read()`](SensorGroup::read) are aligned.
+    pub fn start<C>(&mut self, mut now_us: C) -> Result<(), Error<E>>
+    where
+        C: FnMut() -> u64,
+    {
+        for (sensor, start) in self.sensors.iter_mut().zip(self.start_times_us.iter_mut()) {
+            let before = now_us();
+            sensor.clear_fifo()?;
+            let after = now_us();
+            *start = before + (after.saturating_sub(before)) / 2;
+        }
+        self.samples_read = [0; N];
+        self.pending_lost = [0; N];
+        let latest = self.start_times_us.iter().copied().max().unwrap_or(0);
+        for i in 0..N {
+            let behind = latest - self.start_times_us[i];
+            let period = self.sensors[i].sample_time_us(1).max(1);
+            self.pending_discard[i] = ((behind + period / 2) / period).min(32) as u8;
+        }
+        Ok(())
+    }
+
+    /// Read the same number of samples from every device.
+    ///
+    /// Each output buffer must contain one element per channel per sample.
+    /// The number of samples read is limited by the device with the fewest
+    /// available samples and by the smallest buffer. Only the first
+    /// `sample_count` samples of each buffer are valid.
+    ///
+    /// Samples lost because a FIFO overflowed are reported in the result and
+    /// the timestamps of the device are advanced accordingly.
+    pub fn read(&mut self, output: [&mut [u32]; N]) -> Result<GroupRead<N>, Error<E>> {
+        let mut channels = [0; N];
+        let mut available = [0; N];
+        let mut overflow = [0; N];
+        for i in 0..N {
+            let sensor = &mut self.sensors[i];
+            channels[i] = usize::from(sensor.channel_count());
+            let pointers = sensor.read_fifo_pointers()?;
+            available[i] = usize::from(pointers.available());
+            overflow[i] = pointers.overflow;
+        }
+        let mut discarded = [0; N];
+        for i in 0..N {
+            let capacity = output[i].len().checked_div(channels[i]).unwrap_or(0);
+            let discard = usize::from(self.pending_discard[i])
+                .min(available[i])
+                .min(capacity);
+            if discard != 0 {
+                self.sensors[i].read_samples(discard, &mut output[i][..discard * channels[i]])?;
+                self.pending_discard[i] -= discard as u8;
+                discarded[i] = discard;
+            }
+        }
+        let mut count = 0;
+        if self.pending_discard.iter().all(|discard| *discard == 0) {
+            count = (0..N)
+                .map(|i| {
+                    let capacity = output[i].len().checked_div(channels[i]).unwrap_or(0);
+                    (available[i] - discarded[i]).min(capacity)
+                })
+                .min()
+                .unwrap_or(0);
+        }
+        let mut result = GroupRead {
+            sample_count: count as u8,
+            timestamps_us: [0; N],
+            skew_us: [0; N],
+            lost_sample_counts: [0; N],
+        };
+        for (i, out) in output.into_iter().enumerate() {
+            // The overflow counter is only reset once a sample is popped so
+            // it is read again next time otherwise.
+            if discarded[i] + count != 0 && overflow[i] != 0 {
+                result.lost_sample_counts[i] = overflow[i];
+                self.account_lost_samples(i, overflow[i], available[i] as u8);
+            }
+            self.advance(i, discarded[i]);
+            result.timestamps_us[i] =
+                self.start_times_us[i] + self.sensors[i].sample_time_us(self.samples_read[i] + 1);
+            if count != 0 {
+                self.sensors[i].read_samples(count, &mut out[..count * channels[i]])?;
+                self.advance(i, count);
+            }
+        }
+        for i in 0..N {
+            result.skew_us[i] = result.timestamps_us[i] as i64 - result.timestamps_us[0] as i64;
+        }
+        Ok(result)
+    }
+
+    fn account_lost_samples(&mut self, i: usize, lost: u8, fifo_samples: u8) {
+        if self.sensors[i].fifo_config.bits & BF::FIFO_ROLLOVER_EN != 0 {
+            // The oldest samples were overwritten.
+            self.samples_read[i] += u64::from(lost);
+        } else {
+            // The newest samples were not stored.
+            self.pending_lost[i] = self.pending_lost[i].saturating_add(lost);
+            self.samples_before_lost[i] = fifo_samples;
+        }
+    }
+
+    fn advance(&mut self, i: usize, samples: usize) {
+        self.samples_read[i] += samples as u64;
+        if self.pending_lost[i] != 0 {
+            if samples >= usize::from(self.samples_before_lost[i]) {
+                self.samples_read[i] += u64::from(self.pending_lost[i]);
+                self.pending_lost[i] = 0;
+            } else {
+                self.samples_before_lost[i] -= samples as u8;
+            }
+        }
+    }
+}
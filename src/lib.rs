@@ -8,12 +8,17 @@
 //! - Get the number of samples available on the FIFO. See [`get_available_sample_count()`].
 //! - Get the number of samples lost from the FIFO. See [`get_overflow_sample_count()`].
 //! - Read samples from the FIFO. See [`read_fifo()`].
+//! - Read raw FIFO bytes into a caller buffer and decode them. See [`read_fifo_raw()`] and [`decode_fifo_bytes()`].
+//! - Read the FIFO in split phases with the transfer done elsewhere, for example by DMA. See [`begin_fifo_read()`].
 //! - Perform a temperature measurement. See [`read_temperature()`].
 //! - Change into heart-rate, oximeter or multi-LED modes. See [`into_multi_led()`].
+//! - Adjust or reject an unsupported pulse width and sampling rate when changing modes. See [`ModeChangePolicy`].
 //! - Set the sample averaging. See [`set_sample_averaging()`].
 //! - Set the LED pulse amplitude. See [`set_pulse_amplitude()`].
 //! - Set the LED pulse width. See [`set_pulse_width()`].
 //! - Set the sampling rate. See [`set_sampling_rate()`].
+//! - Get the effective sampling rate including averaging. See [`get_effective_sampling_rate()`].
+//! - Get the LED pulse width. See [`get_pulse_width()`].
 //! - Set the ADC range. See [`set_adc_range()`].
 //! - Set the LED time slots in multi-LED mode. [`set_led_time_slots()`].
 //! - Enable/disable the FIFO rollover. See [`enable_fifo_rollover()`].
@@ -21,6 +26,15 @@
 //! - Wake-up and shutdown the device. See [`shutdown()`].
 //! - Perform a software reset. See [`reset()`].
 //! - Get the device part and revision id. See [`get_part_id()`].
+//! - Dump all registers and compare them with the driver configuration. See [`dump_registers()`].
+//! - Start and read several devices in lockstep. See [`SensorGroup`].
+//! - Read samples handling ambient light cancellation overflows. See [`read_fifo_checked()`].
+//! - Run duty-cycled measurements and estimate their average current. See [`DutyCycleScheduler`].
+//! - Estimate the average supply current of a configuration. See [`estimate_supply_current_ua()`].
+//! - Restore the configuration after a supply brown-out. See [`check_and_recover()`].
+//! - Record FIFO reads and replay them to the driver. See [`recording`].
+//! - Trace the I²C transactions decoded against the register map. See [`I2cTracer`].
+//! - Batch configuration changes into burst writes. See [`begin_batch()`].
 //! - Interrupts:
 //!   - Read the status of all interrupts. See [`read_interrupt_status()`].
 //!   - Set FIFO-almost-full level interrupt. See [`set_fifo_almost_full_level_interrupt()`].
@@ -28,6 +42,15 @@
 //!   - Enable/disable the ambient-light-cancellation overflow interrupt. See [`enable_alc_overflow_interrupt()`].
 //!   - Enable/disable the temperature-ready interrupt. See [`enable_temperature_ready_interrupt()`].
 //!   - Enable/disable the new-FIFO-data-ready interrupt. See [`enable_new_fifo_data_ready_interrupt()`].
+//! - Signal processing:
+//!   - Estimate the temperature-compensated SpO2. See [`Spo2Estimator`].
+//!   - Detect beats and calculate the heart rate. See [`find_beats()`].
+//!   - Score the signal quality of a window of samples. See [`assess()`].
+//!   - Calculate the perfusion index per beat and on average. See [`PerfusionIndexCalculator`].
+//!   - Calculate heart rate variability metrics (RMSSD, SDNN, pNN50, SD1/SD2). See [`HrvMetrics`].
+//!   - Estimate the respiratory rate from the PPG modulations. See [`RespirationEstimator`].
+//!   - Remove motion artifacts using an accelerometer reference. See [`NlmsFilter`].
+//!   - Filter the samples with fixed-point arithmetic on targets without FPU. See [`signal::fixed`].
 //!
 //! [`get_available_sample_count()`]: struct.Max3010x.html#method.get_available_sample_count
 //! [`get_overflow_sample_count()`]: struct.Max3010x.html#method.get_overflow_sample_count
@@ -51,6 +74,35 @@
 //! [`enable_temperature_ready_interrupt()`]: struct.Max3010x.html#method.enable_temperature_ready_interrupt
 //! [`enable_new_fifo_data_ready_interrupt()`]: struct.Max3010x.html#method.enable_new_fifo_data_ready_interrupt
 //! [`get_part_id()`]: struct.Max3010x.html#method.get_part_id
+//! [`Spo2Estimator`]: signal::spo2::Spo2Estimator
+//! [`dump_registers()`]: struct.Max3010x.html#method.dump_registers
+//! [`get_effective_sampling_rate()`]: struct.Max3010x.html#method.get_effective_sampling_rate
+//! [`get_pulse_width()`]: struct.Max3010x.html#method.get_pulse_width
+//! [`SensorGroup`]: SensorGroup
+//! [`find_beats()`]: signal::beat::find_beats
+//! [`assess()`]: signal::quality::assess
+//! [`PerfusionIndexCalculator`]: signal::perfusion::PerfusionIndexCalculator
+//! [`HrvMetrics`]: signal::hrv::HrvMetrics
+//! [`RespirationEstimator`]: signal::respiration::RespirationEstimator
+//! [`NlmsFilter`]: signal::motion::NlmsFilter
+//! [`read_fifo_checked()`]: struct.Max3010x.html#method.read_fifo_checked
+//! [`DutyCycleScheduler`]: DutyCycleScheduler
+//! [`estimate_supply_current_ua()`]: struct.Max3010x.html#method.estimate_supply_current_ua
+//! [`check_and_recover()`]: struct.Max3010x.html#method.check_and_recover
+//! [`begin_batch()`]: struct.Max3010x.html#method.begin_batch
+//! [`read_fifo_raw()`]: struct.Max3010x.html#method.read_fifo_raw
+//! [`begin_fifo_read()`]: struct.Max3010x.html#method.begin_fifo_read
+//!
+//! ## Optional features
+//! - `defmt`: Implement `defmt::Format` for all public types and provide
+//!   `DefmtSink` logging the I²C transactions traced by `I2cTracer`.
+//! - `serde`: Implement `serde::Serialize` and `serde::Deserialize` for the
+//!   public configuration, status and measurement types.
+//! - `log`: Provide `LogSink` logging the I²C transactions traced by
+//!   `I2cTracer` with `log::debug!`.
+//! - `cli`: Build the `max3010x-cli` command-line tool streaming samples from a
+//!   device on a Linux I²C bus as CSV or JSON lines, for example with
+//!   `cargo run --features cli -- --mode spo2 --sample-rate 100 --format json`.
 //!
 //! ## The device
 //! The `MAX30102` is an integrated pulse oximetry and heart-rate monitor module.
@@ -118,7 +170,7 @@
 //!     TimeSlot::Disabled
 //! ]).unwrap();
 //! max30102.enable_fifo_rollover().unwrap();
-//! let mut data = [0; 2];
+//! let mut data = [0; 3]; // one value per enabled slot
 //! let samples_read = max30102.read_fifo(&mut data).unwrap();
 //!
 //! // get the I2C device back
@@ -126,6 +178,29 @@
 //! # }
 //! ```
 //!
+//! ### Share the I²C bus among several sensors
+//!
+//! Any [`embedded-hal-bus`] I²C device can be used. Sensors behind an
+//! address translator can be given their address at construction.
+//!
+//! [`embedded-hal-bus`]: https://crates.io/crates/embedded-hal-bus
+//!
+//! ```no_run
+//! use core::cell::RefCell;
+//! use embedded_hal_bus::i2c::RefCellDevice;
+//! use linux_embedded_hal::I2cdev;
+//! use max3010x::Max3010x;
+//!
+//! let bus = RefCell::new(I2cdev::new("/dev/i2c-1").unwrap());
+//! let finger = Max3010x::new_max30102(RefCellDevice::new(&bus));
+//! let earlobe = Max3010x::new_max30102_with_address(RefCellDevice::new(&bus), 0x58);
+//! let mut finger = finger.into_oximeter().unwrap();
+//! let mut earlobe = earlobe.into_oximeter().unwrap();
+//! let mut data = [0; 2];
+//! finger.read_fifo(&mut data).unwrap();
+//! earlobe.read_fifo(&mut data).unwrap();
+//! ```
+//!
 
 #![deny(missing_docs, unsafe_code)]
 #![no_std]
@@ -137,15 +212,50 @@ use core::marker::PhantomData;
 
 /// All possible errors in this crate
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Error<E> {
     /// I²C bus error
     I2C(E),
     /// Invalid arguments provided
     InvalidArguments,
+    /// The pulse width and sampling rate combination is not supported in the
+    /// current mode or in the mode being changed into
+    UnsupportedSamplingConfiguration {
+        /// Configured LED pulse width
+        pulse_width: LedPulseWidth,
+        /// Configured sampling rate
+        sampling_rate: SamplingRate,
+    },
+    /// The device did not provide the expected samples in time
+    Timeout,
+}
+
+/// Error of a mode change, returning the driver in its previous mode
+pub struct ModeChangeError<E, DEV> {
+    /// Reason of the failure
+    pub error: Error<E>,
+    /// Driver in its previous mode
+    pub dev: DEV,
+}
+
+impl<E: core::fmt::Debug, DEV> core::fmt::Debug for ModeChangeError<E, DEV> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ModeChangeError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E, DEV> From<ModeChangeError<E, DEV>> for Error<E> {
+    fn from(error: ModeChangeError<E, DEV>) -> Self {
+        error.error
+    }
 }
 
 /// LEDs
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Led {
     /// LED1 corresponds to Red in MAX30102
     Led1,
@@ -157,6 +267,8 @@ pub enum Led {
 
 /// Multi-LED mode sample time slot configuration
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TimeSlot {
     /// Time slot is disabled
     Disabled,
@@ -168,6 +280,8 @@ pub enum TimeSlot {
 
 /// Sample averaging
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SampleAveraging {
     /// 1 (no averaging) (default)
     Sa1,
@@ -185,6 +299,8 @@ pub enum SampleAveraging {
 
 /// Number of empty data samples when the FIFO almost full interrupt is issued.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FifoAlmostFullLevelInterrupt {
     /// Interrupt issue when 0 spaces are left in FIFO. (default)
     L0,
@@ -224,6 +340,8 @@ pub enum FifoAlmostFullLevelInterrupt {
 ///
 /// This is limited by the current mode and the selected sample rate.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LedPulseWidth {
     /// 69 μs pulse width (15-bit ADC resolution)
     Pw69,
@@ -239,6 +357,8 @@ pub enum LedPulseWidth {
 ///
 /// This is limited by the current mode and the selected LED pulse width.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SamplingRate {
     /// 50 samples per second
     Sps50,
@@ -258,8 +378,29 @@ pub enum SamplingRate {
     Sps3200,
 }
 
+/// Handling of a pulse width and sampling rate combination that is not
+/// supported in the mode being changed into
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ModeChangePolicy {
+    /// Return `Error::UnsupportedSamplingConfiguration` without changing
+    /// anything. (default)
+    #[default]
+    Fail,
+    /// Reduce the sampling rate to the highest one supported with the
+    /// configured pulse width.
+    ReduceSamplingRate,
+    /// Reduce the pulse width to the longest one supported with the
+    /// configured sampling rate. If none is, use the shortest pulse width and
+    /// reduce the sampling rate.
+    ReducePulseWidth,
+}
+
 /// ADC range
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AdcRange {
     /// Full scale 2048 nA
     Fs2k,
@@ -273,6 +414,8 @@ pub enum AdcRange {
 
 /// Interrupt status flags
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InterruptStatus {
     /// Power ready interrupt
     pub power_ready: bool,
@@ -292,10 +435,12 @@ struct Register;
 
 impl Register {
     const INT_STATUS: u8 = 0x0;
+    const INT_STATUS2: u8 = 0x01;
     const INT_EN1: u8 = 0x02;
     const INT_EN2: u8 = 0x03;
     const FIFO_WR_PTR: u8 = 0x04;
     const OVF_COUNTER: u8 = 0x05;
+    const FIFO_RD_PTR: u8 = 0x06;
     const FIFO_DATA: u8 = 0x07;
     const FIFO_CONFIG: u8 = 0x08;
     const MODE: u8 = 0x09;
@@ -303,8 +448,11 @@ impl Register {
     const LED1_PA: u8 = 0x0C;
     const LED2_PA: u8 = 0x0D;
     const SLOT_CONFIG0: u8 = 0x11;
+    const SLOT_CONFIG1: u8 = 0x12;
     const TEMP_INT: u8 = 0x1F;
+    const TEMP_FRAC: u8 = 0x20;
     const TEMP_CONFIG: u8 = 0x21;
+    const PROX_INT_THRESH: u8 = 0x30;
     const REV_ID: u8 = 0xFE;
     const PART_ID: u8 = 0xFF;
 }
@@ -365,12 +513,21 @@ pub mod marker {
 pub struct Max3010x<I2C, IC, MODE> {
     /// The concrete I²C device implementation.
     i2c: I2C,
+    address: u8,
     temperature_measurement_started: bool,
     mode: Config,
     fifo_config: Config,
     spo2_config: Config,
     int_en1: Config,
     int_en2: Config,
+    led1_pa: Config,
+    led2_pa: Config,
+    slot_config0: Config,
+    slot_config1: Config,
+    /// Registers with pending writes while batching, one bit per address.
+    batch: Option<u32>,
+    /// FIFO data transfer done outside of the driver pending completion.
+    fifo_transfer: Option<transfer::PendingFifoRead>,
     _ic: PhantomData<IC>,
     _mode: PhantomData<MODE>,
 }
@@ -381,14 +538,55 @@ where
 {
     /// Create new instance of the MAX3010x device.
     pub fn new_max30102(i2c: I2C) -> Self {
+        Self::new_max30102_with_address(i2c, DEVICE_ADDRESS)
+    }
+
+    /// Create new instance of the MAX3010x device using a custom I²C address.
+    ///
+    /// This is only necessary if the device is reachable under an address
+    /// other than `0x57`, for example behind an address translator.
+    pub fn new_max30102_with_address(i2c: I2C, address: u8) -> Self {
         Max3010x {
             i2c,
+            address,
             temperature_measurement_started: false,
             mode: Config { bits: 0 },
             fifo_config: Config { bits: 0 },
             spo2_config: Config { bits: 0 },
             int_en1: Config { bits: 0 },
             int_en2: Config { bits: 0 },
+            led1_pa: Config { bits: 0 },
+            led2_pa: Config { bits: 0 },
+            slot_config0: Config { bits: 0 },
+            slot_config1: Config { bits: 0 },
+            batch: None,
+            fifo_transfer: None,
+            _ic: PhantomData,
+            _mode: PhantomData,
+        }
+    }
+}
+
+impl<I2C, IC, MODE> Max3010x<I2C, IC, MODE> {
+    /// Keep the device state while changing the mode marker.
+    ///
+    /// Any pending FIFO transfer is dropped as the FIFO is cleared.
+    pub(crate) fn with_mode_marker<NEWMODE>(self) -> Max3010x<I2C, IC, NEWMODE> {
+        Max3010x {
+            i2c: self.i2c,
+            address: self.address,
+            temperature_measurement_started: self.temperature_measurement_started,
+            mode: self.mode,
+            fifo_config: self.fifo_config,
+            spo2_config: self.spo2_config,
+            int_en1: self.int_en1,
+            int_en2: self.int_en2,
+            led1_pa: self.led1_pa,
+            led2_pa: self.led2_pa,
+            slot_config0: self.slot_config0,
+            slot_config1: self.slot_config1,
+            batch: self.batch,
+            fifo_transfer: None,
             _ic: PhantomData,
             _mode: PhantomData,
         }
@@ -405,12 +603,38 @@ where
     }
 
     fn write_data(&mut self, data: &[u8]) -> Result<(), Error<E>> {
-        self.i2c.write(DEVICE_ADDRESS, data).map_err(Error::I2C)
+        self.i2c.write(self.address, data).map_err(Error::I2C)
     }
 }
 
+mod alc;
+mod batch;
+pub use alc::{AlcOverflowEvent, AlcOverflowPolicy, CheckedFifoRead, INVALID_SAMPLE};
 mod config;
+mod diagnostics;
+pub use diagnostics::{RegisterDiff, RegisterDump, RegisterMismatch};
+mod group;
+pub use group::{GroupRead, SensorGroup};
+mod power;
+pub use power::PowerConfig;
 mod reading;
+pub use reading::{decode_fifo_bytes, FifoSamples};
+pub mod recording;
+mod recovery;
+pub use recovery::RecoveryCheck;
+mod scheduler;
+#[cfg(feature = "serde")]
+mod serde_array;
+pub mod signal;
+pub use scheduler::{DutyCycle, DutyCycleScheduler};
+mod trace;
+#[cfg(feature = "defmt")]
+pub use trace::DefmtSink;
+#[cfg(feature = "log")]
+pub use trace::LogSink;
+pub use trace::{I2cTracer, TraceEvent, TraceSink};
+mod transfer;
+pub use transfer::FifoTransfer;
 
 mod private {
     use super::*;
@@ -0,0 +1,122 @@
+//! Supply current estimation.
+
+use crate::{BitFlags as BF, LedPulseWidth, Max3010x, SamplingRate};
+
+/// Typical supply current while sampling, without the LEDs, in µA.
+const ACTIVE_CURRENT_UA: f32 = 600.0;
+/// Typical supply current in shutdown in µA.
+const SHUTDOWN_CURRENT_UA: f32 = 0.7;
+/// LED current per LSB of the pulse amplitude in µA.
+const LED_CURRENT_UA_PER_LSB: f32 = 200.0;
+
+/// Configuration determining the supply current.
+///
+/// This can be obtained from the driver with
+/// [`get_power_config()`](Max3010x::get_power_config) or filled in to
+/// plan a configuration in advance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PowerConfig {
+    /// LED1 pulse amplitude as passed to `set_pulse_amplitude()` (0.2 mA/LSB).
+    pub led1_amplitude: u8,
+    /// LED2 pulse amplitude as passed to `set_pulse_amplitude()` (0.2 mA/LSB).
+    pub led2_amplitude: u8,
+    /// LED pulse width.
+    pub pulse_width: LedPulseWidth,
+    /// Sampling rate.
+    pub sampling_rate: SamplingRate,
+    /// Number of LED1 pulses per sample.
+    ///
+    /// This is 1 in heart-rate and oximeter modes and the number of LED1
+    /// time slots in multi-LED mode.
+    pub led1_pulses: u8,
+    /// Number of LED2 pulses per sample.
+    ///
+    /// This is 0 in heart-rate mode, 1 in oximeter mode and the number of
+    /// LED2 time slots in multi-LED mode.
+    pub led2_pulses: u8,
+    /// Whether the device is shut down.
+    pub shutdown: bool,
+}
+
+impl PowerConfig {
+    /// Average current drawn by the LEDs in µA.
+    ///
+    /// Each pulse draws the pulse amplitude during the pulse width once per
+    /// sample: `I_LED * PW * SR` for each LED.
+    pub fn led_current_ua(&self) -> f32 {
+        if self.shutdown {
+            return 0.0;
+        }
+        let amplitude = f32::from(self.led1_pulses) * f32::from(self.led1_amplitude)
+            + f32::from(self.led2_pulses) * f32::from(self.led2_amplitude);
+        let duty_cycle = f32::from(self.pulse_width.microseconds())
+            * 1e-6
+            * f32::from(self.sampling_rate.samples_per_second());
+        amplitude * LED_CURRENT_UA_PER_LSB * duty_cycle
+    }
+
+    /// Expected average supply current in µA.
+    ///
+    /// This is the typical supply current of the device (600 µA while
+    /// sampling, 0.7 µA in shutdown) plus the average LED current.
+    pub fn supply_current_ua(&self) -> f32 {
+        if self.shutdown {
+            SHUTDOWN_CURRENT_UA
+        } else {
+            ACTIVE_CURRENT_UA + self.led_current_ua()
+        }
+    }
+}
+
+impl LedPulseWidth {
+    /// Pulse width in µs.
+    pub fn microseconds(self) -> u16 {
+        match self {
+            LedPulseWidth::Pw69 => 69,
+            LedPulseWidth::Pw118 => 118,
+            LedPulseWidth::Pw215 => 215,
+            LedPulseWidth::Pw411 => 411,
+        }
+    }
+}
+
+impl<I2C, IC, MODE> Max3010x<I2C, IC, MODE> {
+    /// Get the configuration determining the supply current.
+    pub fn get_power_config(&self) -> PowerConfig {
+        let mut pulses = [0; 2];
+        match self.mode.bits & 0b111 {
+            0b010 => pulses[0] = 1,
+            0b011 => pulses = [1, 1],
+            0b111 => {
+                for config in [&self.slot_config0, &self.slot_config1] {
+                    for mask in [config.bits & 0b111, (config.bits >> 4) & 0b111] {
+                        match mask {
+                            1 => pulses[0] += 1,
+                            2 => pulses[1] += 1,
+                            _ => (),
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+        PowerConfig {
+            led1_amplitude: self.led1_pa.bits,
+            led2_amplitude: self.led2_pa.bits,
+            pulse_width: self.get_pulse_width(),
+            sampling_rate: self.get_sampling_rate(),
+            led1_pulses: pulses[0],
+            led2_pulses: pulses[1],
+            shutdown: (self.mode.bits & BF::SHUTDOWN) != 0,
+        }
+    }
+
+    /// Estimate the average supply current with the current configuration in µA.
+    ///
+    /// See [`PowerConfig::supply_current_ua()`].
+    pub fn estimate_supply_current_ua(&self) -> f32 {
+        self.get_power_config().supply_current_ua()
+    }
+}
@@ -1,26 +1,46 @@
 //! Reading data method implementation.
 
 use super::{
-    marker, private, BitFlags, Error, InterruptStatus, LedPulseWidth, Max3010x, Register,
-    SamplingRate, DEVICE_ADDRESS,
+    marker, private, AdcRange, BitFlags, Error, InterruptStatus, LedPulseWidth, Max3010x, Register,
+    SampleAveraging, SamplingRate,
 };
 use hal::i2c;
 
 #[doc(hidden)]
 pub trait ChannelCount<IC, MODE>: private::Sealed {
-    const CHANNEL_COUNT: u8;
+    /// Number of channels per sample for the time slot configuration
+    fn channel_count(slot_config0: u8, slot_config1: u8) -> u8;
 }
 
 impl ChannelCount<marker::ic::Max30102, marker::mode::HeartRate> for marker::mode::HeartRate {
-    const CHANNEL_COUNT: u8 = 1;
+    fn channel_count(_slot_config0: u8, _slot_config1: u8) -> u8 {
+        1
+    }
 }
 
 impl ChannelCount<marker::ic::Max30102, marker::mode::Oximeter> for marker::mode::Oximeter {
-    const CHANNEL_COUNT: u8 = 2;
+    fn channel_count(_slot_config0: u8, _slot_config1: u8) -> u8 {
+        2
+    }
 }
 
 impl ChannelCount<marker::ic::Max30102, marker::mode::MultiLed> for marker::mode::MultiLed {
-    const CHANNEL_COUNT: u8 = 2;
+    fn channel_count(slot_config0: u8, slot_config1: u8) -> u8 {
+        active_slot_count(slot_config0, slot_config1)
+    }
+}
+
+/// Number of enabled multi-LED time slots.
+pub(crate) fn active_slot_count(slot_config0: u8, slot_config1: u8) -> u8 {
+    [
+        slot_config0,
+        slot_config0 >> 4,
+        slot_config1,
+        slot_config1 >> 4,
+    ]
+    .iter()
+    .filter(|mask| *mask & 0b111 != 0)
+    .count() as u8
 }
 
 impl<I2C, E, IC, MODE> Max3010x<I2C, IC, MODE>
@@ -36,13 +56,14 @@ where
     /// Returns the number of _samples_ read.
     ///
     /// The output buffer must contain one element per channel per sample.
+    /// In multi-LED mode there is one channel per enabled time slot.
     ///
     /// Note: This method takes care of shifting the data according to the
     /// ADC resolution.
     pub fn read_fifo(&mut self, output_data: &mut [u32]) -> Result<u8, Error<E>> {
-        let mode_channels = usize::from(MODE::CHANNEL_COUNT);
+        let mode_channels = usize::from(self.channel_count());
 
-        if output_data.len() < mode_channels {
+        if mode_channels == 0 || output_data.len() < mode_channels {
             return Ok(0);
         }
         let samples = self.get_available_sample_count()?;
@@ -54,60 +75,261 @@ where
         Ok(sample_count as u8) // the maximum is 32 so this is ok
     }
 
-    fn read_samples(&mut self, sample_count: usize, output: &mut [u32]) -> Result<(), Error<E>> {
-        const BYTES_PER_SAMPLE: usize = 3;
-        const MAX_CHANNEL_COUNT: usize = 2; // for max30102
-        const FIFO_SAMPLE_SIZE: usize = 32;
-
-        let mode_channels = usize::from(MODE::CHANNEL_COUNT);
-        let sample_shift = self.get_sample_shift();
-        let byte_count = sample_count * mode_channels * BYTES_PER_SAMPLE;
-        // maximum size (could be optimized by using mode_channels but this
-        // needs https://github.com/rust-lang/rust/issues/42863)
-        let mut data = [0; FIFO_SAMPLE_SIZE * MAX_CHANNEL_COUNT * BYTES_PER_SAMPLE];
-        self.read_data(Register::FIFO_DATA, &mut data[..byte_count])?;
-        for (out_idx, out_item) in output
-            .iter_mut()
-            .enumerate()
-            .take(sample_count * mode_channels)
-        {
-            let sample_idx = out_idx * BYTES_PER_SAMPLE;
-            *out_item = (u32::from(data[sample_idx]) << 16
-                | u32::from(data[sample_idx + 1]) << 8
-                | u32::from(data[sample_idx + 2]))
-                >> sample_shift;
+    /// Reads raw samples from FIFO into a byte buffer.
+    ///
+    /// Reads the FIFO bytes directly into `output` until all the available
+    /// samples are read or the buffer is full, without any intermediate
+    /// copy. This is useful for DMA-based pipelines.
+    ///
+    /// Returns the number of _samples_ read. Each sample takes 3 bytes per
+    /// channel. The bytes can be decoded with [`decode_fifo_bytes()`].
+    pub fn read_fifo_raw(&mut self, output: &mut [u8]) -> Result<u8, Error<E>> {
+        let bytes_per_sample = usize::from(self.channel_count()) * BYTES_PER_CHANNEL;
+        if bytes_per_sample == 0 {
+            return Ok(0);
+        }
+        let samples = self.get_available_sample_count()?;
+        let sample_count = core::cmp::min(usize::from(samples), output.len() / bytes_per_sample);
+        if sample_count != 0 {
+            self.read_data(
+                Register::FIFO_DATA,
+                &mut output[..sample_count * bytes_per_sample],
+            )?;
+        }
+        Ok(sample_count as u8) // the maximum is 32 so this is ok
+    }
+
+    pub(crate) fn read_samples(
+        &mut self,
+        sample_count: usize,
+        output: &mut [u32],
+    ) -> Result<(), Error<E>> {
+        const BUFFER_SIZE: usize = 192;
+
+        let channels = self.channel_count();
+        let bytes_per_sample = usize::from(channels) * BYTES_PER_CHANNEL;
+        let pulse_width = self.get_pulse_width();
+        // Fits the whole FIFO with up to two channels. With more channels
+        // the samples are read in several transfers.
+        let mut data = [0; BUFFER_SIZE];
+        let chunk_samples = BUFFER_SIZE / bytes_per_sample;
+        let mut output = output;
+        let mut remaining = sample_count;
+        while remaining != 0 {
+            let samples = remaining.min(chunk_samples);
+            let byte_count = samples * bytes_per_sample;
+            self.read_data(Register::FIFO_DATA, &mut data[..byte_count])?;
+            let values = decode_fifo_bytes(&data[..byte_count], channels, pulse_width);
+            let (chunk, rest) = output.split_at_mut(samples * usize::from(channels));
+            for (out_item, value) in chunk.iter_mut().zip(values) {
+                *out_item = value;
+            }
+            output = rest;
+            remaining -= samples;
         }
         Ok(())
     }
 
-    fn get_sample_shift(&self) -> usize {
-        match self.get_pulse_width() {
-            LedPulseWidth::Pw69 => 3,
-            LedPulseWidth::Pw118 => 2,
-            LedPulseWidth::Pw215 => 1,
-            LedPulseWidth::Pw411 => 0,
+    /// Number of channels per sample in the current configuration.
+    pub(crate) fn channel_count(&self) -> u8 {
+        MODE::channel_count(self.slot_config0.bits, self.slot_config1.bits)
+    }
+}
+
+/// Number of bytes of each channel of a sample in the FIFO.
+pub(crate) const BYTES_PER_CHANNEL: usize = 3;
+
+/// FIFO pointers and overflow counter.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FifoPointers {
+    pub(crate) write: u8,
+    pub(crate) overflow: u8,
+    pub(crate) read: u8,
+}
+
+impl FifoPointers {
+    /// Number of samples available for reading.
+    pub(crate) fn available(&self) -> u8 {
+        if self.write == self.read && self.overflow != 0 {
+            // The pointers are also equal when the FIFO is full.
+            32
+        } else {
+            (self.write + 32 - self.read) % 32
         }
     }
 }
 
+/// Decode raw FIFO bytes into channel values.
+///
+/// The bytes must be laid out as read from the FIFO data register, for
+/// example with [`read_fifo_raw()`](Max3010x::read_fifo_raw), for a mode
+/// with the given number of `channels`. The values are shifted according to
+/// the ADC resolution for the `pulse_width` and returned in the same order
+/// as [`read_fifo()`](Max3010x::read_fifo) fills its output. Trailing bytes
+/// not forming a complete sample are ignored.
+///
+/// This does not need the driver so it can be used to process data acquired
+/// elsewhere.
+///
+/// ```
+/// use max3010x::{decode_fifo_bytes, LedPulseWidth};
+///
+/// let bytes = [0x01, 0x23, 0x45, 0x00, 0x00, 0x08];
+/// let mut values = decode_fifo_bytes(&bytes, 2, LedPulseWidth::Pw411);
+/// assert_eq!(Some(0x1_2345), values.next());
+/// assert_eq!(Some(8), values.next());
+/// assert_eq!(None, values.next());
+/// ```
+pub fn decode_fifo_bytes(
+    bytes: &[u8],
+    channels: u8,
+    pulse_width: LedPulseWidth,
+) -> FifoSamples<'_> {
+    let bytes_per_sample = usize::from(channels) * BYTES_PER_CHANNEL;
+    let len = bytes.len().checked_div(bytes_per_sample).unwrap_or(0) * bytes_per_sample;
+    FifoSamples {
+        chunks: bytes[..len].chunks_exact(BYTES_PER_CHANNEL),
+        shift: 18 - pulse_width.adc_resolution(),
+    }
+}
+
+/// Iterator over the channel values decoded from raw FIFO bytes.
+///
+/// Created by [`decode_fifo_bytes()`].
+#[derive(Debug, Clone)]
+pub struct FifoSamples<'a> {
+    chunks: core::slice::ChunksExact<'a, u8>,
+    shift: u8,
+}
+
+impl Iterator for FifoSamples<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        self.chunks.next().map(|bytes| {
+            (u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]))
+                >> self.shift
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+}
+
+impl ExactSizeIterator for FifoSamples<'_> {}
+
 impl<I2C, IC, MODE> Max3010x<I2C, IC, MODE> {
-    pub(crate) fn get_pulse_width(&self) -> LedPulseWidth {
-        let pw_bits = self.spo2_config.bits & (BitFlags::LED_PW0 | BitFlags::LED_PW1);
-        match pw_bits {
-            0 => LedPulseWidth::Pw69,
-            1 => LedPulseWidth::Pw118,
-            2 => LedPulseWidth::Pw215,
-            3 => LedPulseWidth::Pw411,
-            _ => unreachable!(),
-        }
+    /// Get the configured LED pulse width.
+    ///
+    /// This determines the ADC resolution.
+    pub fn get_pulse_width(&self) -> LedPulseWidth {
+        convert_pulse_width(self.spo2_config.bits)
+    }
+
+    pub(crate) fn get_adc_range(&self) -> AdcRange {
+        convert_adc_range(self.spo2_config.bits)
     }
 
     pub(crate) fn get_sampling_rate(&self) -> SamplingRate {
         convert_sampling_rate(self.spo2_config.bits)
     }
+
+    pub(crate) fn get_sample_averaging(&self) -> SampleAveraging {
+        convert_sample_averaging(self.fifo_config.bits)
+    }
+
+    /// Get the rate at which samples are stored in the FIFO in samples per second.
+    ///
+    /// This is the configured sampling rate divided by the sample averaging.
+    pub fn get_effective_sampling_rate(&self) -> f32 {
+        self.get_sampling_rate().samples_per_second() as f32
+            / f32::from(self.get_sample_averaging().sample_count())
+    }
+
+    /// Time of the FIFO sample with the given index relative to the first one in µs.
+    pub(crate) fn sample_time_us(&self, sample_index: u64) -> u64 {
+        sample_index * 1_000_000 * u64::from(self.get_sample_averaging().sample_count())
+            / u64::from(self.get_sampling_rate().samples_per_second())
+    }
+}
+
+impl LedPulseWidth {
+    /// ADC resolution in bits.
+    pub fn adc_resolution(self) -> u8 {
+        match self {
+            LedPulseWidth::Pw69 => 15,
+            LedPulseWidth::Pw118 => 16,
+            LedPulseWidth::Pw215 => 17,
+            LedPulseWidth::Pw411 => 18,
+        }
+    }
+}
+
+impl SamplingRate {
+    /// Number of samples per second.
+    pub fn samples_per_second(self) -> u16 {
+        match self {
+            SamplingRate::Sps50 => 50,
+            SamplingRate::Sps100 => 100,
+            SamplingRate::Sps200 => 200,
+            SamplingRate::Sps400 => 400,
+            SamplingRate::Sps800 => 800,
+            SamplingRate::Sps1000 => 1000,
+            SamplingRate::Sps1600 => 1600,
+            SamplingRate::Sps3200 => 3200,
+        }
+    }
+}
+
+impl SampleAveraging {
+    /// Number of samples averaged into one FIFO sample.
+    pub fn sample_count(self) -> u8 {
+        match self {
+            SampleAveraging::Sa1 => 1,
+            SampleAveraging::Sa2 => 2,
+            SampleAveraging::Sa4 => 4,
+            SampleAveraging::Sa8 => 8,
+            SampleAveraging::Sa16 => 16,
+            SampleAveraging::Sa32 => 32,
+        }
+    }
+}
+
+pub(crate) fn convert_sample_averaging(fifo_config: u8) -> SampleAveraging {
+    match fifo_config >> 5 {
+        0 => SampleAveraging::Sa1,
+        1 => SampleAveraging::Sa2,
+        2 => SampleAveraging::Sa4,
+        3 => SampleAveraging::Sa8,
+        4 => SampleAveraging::Sa16,
+        _ => SampleAveraging::Sa32,
+    }
+}
+
+pub(crate) fn convert_pulse_width(spo2_config: u8) -> LedPulseWidth {
+    let pw_bits = spo2_config & (BitFlags::LED_PW0 | BitFlags::LED_PW1);
+    match pw_bits {
+        0 => LedPulseWidth::Pw69,
+        1 => LedPulseWidth::Pw118,
+        2 => LedPulseWidth::Pw215,
+        3 => LedPulseWidth::Pw411,
+        _ => unreachable!(),
+    }
+}
+
+pub(crate) fn convert_adc_range(spo2_config: u8) -> AdcRange {
+    let range_bits = (spo2_config & (BitFlags::ADC_RGE0 | BitFlags::ADC_RGE1)) >> 5;
+    match range_bits {
+        0 => AdcRange::Fs2k,
+        1 => AdcRange::Fs4k,
+        2 => AdcRange::Fs8k,
+        3 => AdcRange::Fs16k,
+        _ => unreachable!(),
+    }
 }
 
-fn convert_sampling_rate(spo2_config: u8) -> SamplingRate {
+pub(crate) fn convert_sampling_rate(spo2_config: u8) -> SamplingRate {
     let sr_bits =
         (spo2_config & (BitFlags::SPO2_SR0 | BitFlags::SPO2_SR1 | BitFlags::SPO2_SR2)) >> 2;
     match sr_bits {
@@ -128,17 +350,27 @@ where
     I2C: i2c::I2c<Error = E>,
 {
     /// Get number of samples available for reading from FIFO.
+    ///
+    /// A full FIFO, where the write and read pointers are equal after
+    /// samples were lost, is reported as 32 samples.
     pub fn get_available_sample_count(&mut self) -> Result<u8, Error<E>> {
+        Ok(self.read_fifo_pointers()?.available())
+    }
+
+    /// Read the FIFO write pointer, overflow counter and read pointer in a
+    /// single transfer.
+    ///
+    /// Pending batched changes are flushed first so that the FIFO contents
+    /// match the cached configuration.
+    pub(crate) fn read_fifo_pointers(&mut self) -> Result<FifoPointers, Error<E>> {
+        self.flush_batch()?;
         let mut data = [0; 3];
         self.read_data(Register::FIFO_WR_PTR, &mut data)?;
-        let wr_ptr = data[0] & 0x1F;
-        let rd_ptr = data[2] & 0x1F;
-        let has_rolled_over = rd_ptr > wr_ptr;
-        if has_rolled_over {
-            Ok(32 - rd_ptr + wr_ptr)
-        } else {
-            Ok(wr_ptr - rd_ptr)
-        }
+        Ok(FifoPointers {
+            write: data[0] & 0x1F,
+            overflow: data[1] & 0x1F,
+            read: data[2] & 0x1F,
+        })
     }
 
     /// Get number of samples lost from FIFO.
@@ -182,14 +414,7 @@ where
     pub fn read_interrupt_status(&mut self) -> Result<InterruptStatus, Error<E>> {
         let mut data = [0; 2];
         self.read_data(Register::INT_STATUS, &mut data)?;
-        let status = InterruptStatus {
-            power_ready: (data[0] & BitFlags::PWR_RDY_INT) != 0,
-            fifo_almost_full: (data[0] & BitFlags::FIFO_A_FULL_INT) != 0,
-            new_fifo_data_ready: (data[0] & BitFlags::PPG_RDY_INT) != 0,
-            alc_overflow: (data[0] & BitFlags::ALC_OVF_INT) != 0,
-            temperature_ready: (data[1] & BitFlags::DIE_TEMP_RDY_INT) != 0,
-        };
-        Ok(status)
+        Ok(convert_interrupt_status(data[0], data[1]))
     }
 
     /// Get revision ID
@@ -202,19 +427,29 @@ where
         self.read_register(Register::PART_ID)
     }
 
-    fn read_register(&mut self, register: u8) -> Result<u8, Error<E>> {
+    pub(crate) fn read_register(&mut self, register: u8) -> Result<u8, Error<E>> {
         let mut data = [0];
         self.read_data(register, &mut data)?;
         Ok(data[0])
     }
 
-    fn read_data(&mut self, register: u8, data: &mut [u8]) -> Result<(), Error<E>> {
+    pub(crate) fn read_data(&mut self, register: u8, data: &mut [u8]) -> Result<(), Error<E>> {
         self.i2c
-            .write_read(DEVICE_ADDRESS, &[register], data)
+            .write_read(self.address, &[register], data)
             .map_err(Error::I2C)
     }
 }
 
+pub(crate) fn convert_interrupt_status(status1: u8, status2: u8) -> InterruptStatus {
+    InterruptStatus {
+        power_ready: (status1 & BitFlags::PWR_RDY_INT) != 0,
+        fifo_almost_full: (status1 & BitFlags::FIFO_A_FULL_INT) != 0,
+        new_fifo_data_ready: (status1 & BitFlags::PPG_RDY_INT) != 0,
+        alc_overflow: (status1 & BitFlags::ALC_OVF_INT) != 0,
+        temperature_ready: (status2 & BitFlags::DIE_TEMP_RDY_INT) != 0,
+    }
+}
+
 #[cfg(test)]
 mod convert_sampling_rate_tests {
     use super::{convert_sampling_rate, SamplingRate};
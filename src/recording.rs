@@ -0,0 +1,651 @@
+//! Recording of FIFO reads and replay through an I²C bus implementation.
+//!
+//! Sessions captured in the field can be replayed later against an
+//! unmodified driver, for example to test algorithms against real data
+//! in CI.
+//!
+//! A recording starts with a header holding the device configuration
+//! followed by one record per FIFO read:
+//!
+//! | Field           | Size            | Contents                                        |
+//! |-----------------|-----------------|-------------------------------------------------|
+//! | Magic           | 4 bytes         | `MXRC`                                          |
+//! | Version         | 1 byte          | `1`                                             |
+//! | Configuration   | 7 bytes         | MODE, FIFO_CONFIG, SPO2_CONFIG, LED1_PA, LED2_PA, SLOT_CONFIG0 and SLOT_CONFIG1 registers |
+//! | Time delta      | 4 bytes (LE)    | µs since the previous read (or since 0)         |
+//! | Overflow count  | 1 byte          | Samples lost before this read                   |
+//! | Length          | 2 bytes (LE)    | Number of FIFO bytes that follow                |
+//! | FIFO bytes      | Length          | Raw FIFO data as read from the device           |
+//!
+//! The last four fields are repeated for each read.
+//!
+//! Recording:
+//!
+//! ```no_run
+//! use linux_embedded_hal::I2cdev;
+//! use max3010x::{
+//!     recording::{Recorder, RecordingConfig},
+//!     Max3010x,
+//! };
+//! use std::{fs::File, io::Write, time::Instant};
+//!
+//! let dev = I2cdev::new("/dev/i2c-1").unwrap();
+//! let mut sensor = Max3010x::new_max30102(dev).into_oximeter().unwrap();
+//! let mut file = File::create("session.mxrc").unwrap();
+//! let mut recorder = Recorder::new();
+//! let mut buffer = [0; 256];
+//! let len = recorder
+//!     .write_header(&RecordingConfig::from_sensor(&sensor), &mut buffer)
+//!     .unwrap();
+//! file.write_all(&buffer[..len]).unwrap();
+//! let start = Instant::now();
+//! loop {
+//!     let timestamp_us = start.elapsed().as_micros() as u64;
+//!     let len = sensor.record_fifo(&mut recorder, timestamp_us, &mut buffer).unwrap();
+//!     file.write_all(&buffer[..len]).unwrap();
+//! }
+//! ```
+//!
+//! Replay:
+//!
+//! ```no_run
+//! use max3010x::{recording::ReplayI2c, Max3010x};
+//!
+//! let recording = std::fs::read("session.mxrc").unwrap();
+//! let replay = ReplayI2c::new(&recording).unwrap();
+//! let config = replay.config();
+//! let mut sensor = Max3010x::new_max30102(replay).into_oximeter().unwrap();
+//! sensor.apply_recording_config(&config).unwrap();
+//! let mut data = [0; 64];
+//! while sensor.read_fifo(&mut data).unwrap() != 0 {
+//!     // run the algorithms
+//! }
+//! ```
+
+use crate::{
+    diagnostics::convert_time_slot,
+    reading::{
+        convert_adc_range, convert_pulse_width, convert_sample_averaging, convert_sampling_rate,
+        ChannelCount, BYTES_PER_CHANNEL,
+    },
+    AdcRange, BitFlags as BF, Error, LedPulseWidth, Max3010x, Register as Reg, SampleAveraging,
+    SamplingRate, TimeSlot, DEVICE_ADDRESS,
+};
+use hal::i2c;
+
+const MAGIC: [u8; 4] = *b"MXRC";
+const VERSION: u8 = 1;
+/// Length of the recording header in bytes.
+pub const HEADER_LEN: usize = 12;
+/// Length of the header of each record in bytes.
+pub const RECORD_HEADER_LEN: usize = 7;
+/// Maximum number of samples the FIFO pointers can report.
+const MAX_AVAILABLE_SAMPLES: usize = 31;
+const PART_ID: u8 = 0x15;
+
+/// Recording errors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RecordingError {
+    /// The output buffer is too small.
+    BufferTooSmall,
+    /// The recording does not start with a valid header.
+    InvalidHeader,
+    /// The recording was written with an unsupported format version.
+    UnsupportedVersion(u8),
+    /// The recording ends in the middle of a record.
+    Truncated,
+    /// The time between two reads does not fit in a record.
+    TimeGapTooLarge,
+    /// The configuration cannot be recorded, for example because the device
+    /// is not in a sampling mode.
+    InvalidConfig,
+}
+
+/// Errors of [`record_fifo()`](Max3010x::record_fifo).
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RecordError<E> {
+    /// Error communicating with the device
+    Device(Error<E>),
+    /// The read cannot be recorded
+    Recording(RecordingError),
+}
+
+impl<E> From<Error<E>> for RecordError<E> {
+    fn from(error: Error<E>) -> Self {
+        RecordError::Device(error)
+    }
+}
+
+/// Device configuration stored in a recording.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecordingConfig {
+    /// Mode control bits (`0b010`: heart-rate, `0b011`: SpO2, `0b111`: multi-LED)
+    pub mode: u8,
+    /// Sampling rate
+    pub sampling_rate: SamplingRate,
+    /// LED pulse width
+    pub pulse_width: LedPulseWidth,
+    /// ADC range
+    pub adc_range: AdcRange,
+    /// Sample averaging
+    pub sample_averaging: SampleAveraging,
+    /// Multi-LED mode time slots
+    pub slots: [TimeSlot; 4],
+    /// LED1 pulse amplitude
+    pub led1_amplitude: u8,
+    /// LED2 pulse amplitude
+    pub led2_amplitude: u8,
+}
+
+impl RecordingConfig {
+    /// Get the current configuration of the device.
+    pub fn from_sensor<I2C, IC, MODE>(sensor: &Max3010x<I2C, IC, MODE>) -> Self {
+        let slot = |config: u8| convert_time_slot(config).unwrap_or(TimeSlot::Disabled);
+        let (slots0, slots1) = (sensor.slot_config0.bits, sensor.slot_config1.bits);
+        RecordingConfig {
+            mode: sensor.mode.bits & 0b111,
+            sampling_rate: sensor.get_sampling_rate(),
+            pulse_width: sensor.get_pulse_width(),
+            adc_range: sensor.get_adc_range(),
+            sample_averaging: sensor.get_sample_averaging(),
+            slots: [
+                slot(slots0),
+                slot(slots0 >> 4),
+                slot(slots1),
+                slot(slots1 >> 4),
+            ],
+            led1_amplitude: sensor.led1_pa.bits,
+            led2_amplitude: sensor.led2_pa.bits,
+        }
+    }
+
+    /// Number of channels stored per sample in the FIFO.
+    pub fn channel_count(&self) -> u8 {
+        match self.mode {
+            0b010 => 1,
+            0b011 => 2,
+            _ => self
+                .slots
+                .iter()
+                .filter(|slot| **slot != TimeSlot::Disabled)
+                .count() as u8,
+        }
+    }
+
+    // The enumerations are declared in the order of their register values.
+    fn encode(&self) -> [u8; 7] {
+        [
+            self.mode,
+            (self.sample_averaging as u8) << 5,
+            (self.adc_range as u8) << 5 | (self.sampling_rate as u8) << 2 | self.pulse_width as u8,
+            self.led1_amplitude,
+            self.led2_amplitude,
+            self.slots[1].get_mask() << 4 | self.slots[0].get_mask(),
+            self.slots[3].get_mask() << 4 | self.slots[2].get_mask(),
+        ]
+    }
+
+    fn decode(registers: &[u8; 7]) -> Option<Self> {
+        let [mode, fifo_config, spo2_config, led1, led2, slots0, slots1] = *registers;
+        if ![0b010, 0b011, 0b111].contains(&mode) {
+            return None;
+        }
+        Some(RecordingConfig {
+            mode,
+            sampling_rate: convert_sampling_rate(spo2_config),
+            pulse_width: convert_pulse_width(spo2_config),
+            adc_range: convert_adc_range(spo2_config),
+            sample_averaging: convert_sample_averaging(fifo_config),
+            slots: [
+                convert_time_slot(slots0)?,
+                convert_time_slot(slots0 >> 4)?,
+                convert_time_slot(slots1)?,
+                convert_time_slot(slots1 >> 4)?,
+            ],
+            led1_amplitude: led1,
+            led2_amplitude: led2,
+        })
+    }
+}
+
+/// Writes recordings into caller-provided buffers.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Recorder {
+    last_timestamp_us: u64,
+}
+
+impl Recorder {
+    /// Create a new recorder.
+    pub fn new() -> Self {
+        Recorder::default()
+    }
+
+    /// Write the recording header into `output` and return its length.
+    ///
+    /// `RecordingError::InvalidConfig` is returned if the configuration
+    /// could not be read back, for example if it was taken from a device
+    /// which is not in a sampling mode.
+    pub fn write_header(
+        &mut self,
+        config: &RecordingConfig,
+        output: &mut [u8],
+    ) -> Result<usize, RecordingError> {
+        let registers = config.encode();
+        if RecordingConfig::decode(&registers).is_none() {
+            return Err(RecordingError::InvalidConfig);
+        }
+        let output = output
+            .get_mut(..HEADER_LEN)
+            .ok_or(RecordingError::BufferTooSmall)?;
+        output[..4].copy_from_slice(&MAGIC);
+        output[4] = VERSION;
+        output[5..].copy_from_slice(&registers);
+        self.last_timestamp_us = 0;
+        Ok(HEADER_LEN)
+    }
+
+    /// Write a record of a FIFO read into `output` and return its length.
+    ///
+    /// `timestamp_us` is the time of the read in µs.
+    pub fn write_read(
+        &mut self,
+        timestamp_us: u64,
+        overflow_count: u8,
+        fifo_bytes: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize, RecordingError> {
+        let len = RECORD_HEADER_LEN + fifo_bytes.len();
+        if fifo_bytes.len() > usize::from(u16::MAX) || output.len() < len {
+            return Err(RecordingError::BufferTooSmall);
+        }
+        let delta_us = self.time_delta_us(timestamp_us)?;
+        self.write_record_header(
+            timestamp_us,
+            delta_us,
+            overflow_count,
+            fifo_bytes.len(),
+            output,
+        );
+        output[RECORD_HEADER_LEN..len].copy_from_slice(fifo_bytes);
+        Ok(len)
+    }
+
+    /// Time since the previous record as stored in a record header.
+    fn time_delta_us(&self, timestamp_us: u64) -> Result<u32, RecordingError> {
+        let delta = timestamp_us.saturating_sub(self.last_timestamp_us);
+        u32::try_from(delta).map_err(|_| RecordingError::TimeGapTooLarge)
+    }
+
+    fn write_record_header(
+        &mut self,
+        timestamp_us: u64,
+        delta_us: u32,
+        overflow_count: u8,
+        len: usize,
+        output: &mut [u8],
+    ) {
+        output[..4].copy_from_slice(&delta_us.to_le_bytes());
+        output[4] = overflow_count;
+        output[5..RECORD_HEADER_LEN].copy_from_slice(&(len as u16).to_le_bytes());
+        self.last_timestamp_us = timestamp_us;
+    }
+}
+
+/// A FIFO read stored in a recording.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RecordedRead<'a> {
+    /// Time of the read in µs.
+    pub timestamp_us: u64,
+    /// Number of samples lost before this read.
+    pub overflow_count: u8,
+    /// Raw FIFO data.
+    pub fifo_bytes: &'a [u8],
+}
+
+/// Iterator over the reads stored in a recording.
+#[derive(Debug, Clone)]
+pub struct RecordingReader<'a> {
+    config: RecordingConfig,
+    bytes: &'a [u8],
+    timestamp_us: u64,
+}
+
+impl<'a> RecordingReader<'a> {
+    /// Parse the header of a recording.
+    pub fn new(recording: &'a [u8]) -> Result<Self, RecordingError> {
+        if recording.len() < HEADER_LEN || recording[..4] != MAGIC {
+            return Err(RecordingError::InvalidHeader);
+        }
+        if recording[4] != VERSION {
+            return Err(RecordingError::UnsupportedVersion(recording[4]));
+        }
+        let mut registers = [0; 7];
+        registers.copy_from_slice(&recording[5..HEADER_LEN]);
+        let config = RecordingConfig::decode(&registers).ok_or(RecordingError::InvalidHeader)?;
+        Ok(RecordingReader {
+            config,
+            bytes: &recording[HEADER_LEN..],
+            timestamp_us: 0,
+        })
+    }
+
+    /// Get the device configuration of the recording.
+    pub fn config(&self) -> RecordingConfig {
+        self.config
+    }
+}
+
+impl<'a> Iterator for RecordingReader<'a> {
+    type Item = Result<RecordedRead<'a>, RecordingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        if self.bytes.len() < RECORD_HEADER_LEN {
+            self.bytes = &[];
+            return Some(Err(RecordingError::Truncated));
+        }
+        let delta =
+            u32::from_le_bytes([self.bytes[0], self.bytes[1], self.bytes[2], self.bytes[3]]);
+        let overflow_count = self.bytes[4];
+        let len = usize::from(u16::from_le_bytes([self.bytes[5], self.bytes[6]]));
+        let end = RECORD_HEADER_LEN + len;
+        if self.bytes.len() < end {
+            self.bytes = &[];
+            return Some(Err(RecordingError::Truncated));
+        }
+        let fifo_bytes = &self.bytes[RECORD_HEADER_LEN..end];
+        self.bytes = &self.bytes[end..];
+        self.timestamp_us += u64::from(delta);
+        Some(Ok(RecordedRead {
+            timestamp_us: self.timestamp_us,
+            overflow_count,
+            fifo_bytes,
+        }))
+    }
+}
+
+/// Error of the replay I²C bus.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReplayError {
+    /// A device address other than the one of the recorded device was used.
+    Address(u8),
+    /// The recording is invalid.
+    Recording(RecordingError),
+}
+
+impl i2c::Error for ReplayError {
+    fn kind(&self) -> i2c::ErrorKind {
+        match self {
+            ReplayError::Address(_) => {
+                i2c::ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Address)
+            }
+            ReplayError::Recording(_) => i2c::ErrorKind::Other,
+        }
+    }
+}
+
+/// I²C bus implementation replaying a recording to the driver.
+///
+/// The device is emulated with a register file initialized from the
+/// recorded configuration. Each time the driver reads the FIFO pointers,
+/// the samples of the current recorded read which have not been read yet
+/// are reported as available. Once all of them have been read, the next
+/// recorded read follows. Writes to the FIFO pointers are ignored so that
+/// clearing the FIFO does not drop recorded samples. Other registers read
+/// back the last value written and the interrupt status is always clear.
+/// Temperature measurements complete immediately and read 0 °C.
+#[derive(Debug, Clone)]
+pub struct ReplayI2c<'a> {
+    reader: RecordingReader<'a>,
+    address: u8,
+    registers: [u8; 0x100],
+    pointer: u8,
+    current: Option<RecordedRead<'a>>,
+    offset: usize,
+    timestamp_us: u64,
+}
+
+impl<'a> ReplayI2c<'a> {
+    /// Create a replay bus from a recording of a device at the default
+    /// address.
+    pub fn new(recording: &'a [u8]) -> Result<Self, RecordingError> {
+        Self::with_address(recording, DEVICE_ADDRESS)
+    }
+
+    /// Create a replay bus from a recording of a device at a custom address,
+    /// as created with
+    /// [`new_max30102_with_address()`](Max3010x::new_max30102_with_address).
+    pub fn with_address(recording: &'a [u8], address: u8) -> Result<Self, RecordingError> {
+        let reader = RecordingReader::new(recording)?;
+        let mut registers = [0; 0x100];
+        let config = reader.config().encode();
+        let addresses = [
+            Reg::MODE,
+            Reg::FIFO_CONFIG,
+            Reg::SPO2_CONFIG,
+            Reg::LED1_PA,
+            Reg::LED2_PA,
+            Reg::SLOT_CONFIG0,
+            Reg::SLOT_CONFIG1,
+        ];
+        for (address, value) in addresses.iter().zip(config) {
+            registers[usize::from(*address)] = value;
+        }
+        registers[usize::from(Reg::PART_ID)] = PART_ID;
+        let mut replay = ReplayI2c {
+            reader,
+            address,
+            registers,
+            pointer: 0,
+            current: None,
+            offset: 0,
+            timestamp_us: 0,
+        };
+        replay.load_next()?;
+        Ok(replay)
+    }
+
+    /// Get the device configuration of the recording.
+    pub fn config(&self) -> RecordingConfig {
+        self.reader.config()
+    }
+
+    /// Time in µs of the recorded read from which the driver last read
+    /// FIFO data.
+    pub fn timestamp_us(&self) -> u64 {
+        self.timestamp_us
+    }
+
+    /// Return whether all recorded samples have been read.
+    pub fn is_finished(&self) -> bool {
+        self.current.is_none()
+    }
+
+    /// Load the next recorded read with data, accumulating the overflow
+    /// count of the empty ones.
+    fn load_next(&mut self) -> Result<(), RecordingError> {
+        let mut overflow_count = 0u8;
+        self.offset = 0;
+        self.current = None;
+        for read in &mut self.reader {
+            let mut read = read?;
+            overflow_count = overflow_count.saturating_add(read.overflow_count);
+            if !read.fifo_bytes.is_empty() {
+                read.overflow_count = overflow_count;
+                self.current = Some(read);
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn available_sample_count(&self) -> u8 {
+        let bytes_per_sample = usize::from(self.config().channel_count()) * BYTES_PER_CHANNEL;
+        match self.current {
+            Some(read) if bytes_per_sample != 0 => {
+                let samples = (read.fifo_bytes.len() - self.offset) / bytes_per_sample;
+                samples.min(MAX_AVAILABLE_SAMPLES) as u8
+            }
+            _ => 0,
+        }
+    }
+
+    fn overflow_count(&self) -> u8 {
+        match self.current {
+            Some(read) if self.offset == 0 => read.overflow_count.min(0x1F),
+            _ => 0,
+        }
+    }
+
+    fn read_register(&mut self) -> Result<u8, RecordingError> {
+        let register = self.pointer;
+        if register != Reg::FIFO_DATA {
+            self.pointer = self.pointer.wrapping_add(1);
+        }
+        let value = match register {
+            Reg::INT_STATUS | Reg::INT_STATUS2 => 0,
+            Reg::FIFO_WR_PTR => self.available_sample_count(),
+            Reg::OVF_COUNTER => self.overflow_count(),
+            Reg::FIFO_RD_PTR => 0,
+            Reg::FIFO_DATA => match self.current {
+                Some(read) => {
+                    let value = read.fifo_bytes[self.offset];
+                    self.offset += 1;
+                    self.timestamp_us = read.timestamp_us;
+                    if self.offset == read.fifo_bytes.len() {
+                        self.load_next()?;
+                    }
+                    value
+                }
+                None => 0,
+            },
+            _ => self.registers[usize::from(register)],
+        };
+        Ok(value)
+    }
+
+    fn write_register(&mut self, value: u8) {
+        let register = self.pointer;
+        if register != Reg::FIFO_DATA {
+            self.pointer = self.pointer.wrapping_add(1);
+        }
+        match register {
+            Reg::FIFO_WR_PTR..=Reg::FIFO_DATA => (),
+            Reg::TEMP_CONFIG => self.registers[usize::from(register)] = value & !BF::TEMP_EN,
+            _ => self.registers[usize::from(register)] = value,
+        }
+    }
+}
+
+impl i2c::ErrorType for ReplayI2c<'_> {
+    type Error = ReplayError;
+}
+
+impl i2c::I2c for ReplayI2c<'_> {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [i2c::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        if address != self.address {
+            return Err(ReplayError::Address(address));
+        }
+        for operation in operations {
+            match operation {
+                i2c::Operation::Write(bytes) => {
+                    if let Some((register, values)) = bytes.split_first() {
+                        self.pointer = *register;
+                        for value in values {
+                            self.write_register(*value);
+                        }
+                    }
+                }
+                i2c::Operation::Read(buffer) => {
+                    for value in buffer.iter_mut() {
+                        *value = self.read_register().map_err(ReplayError::Recording)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<I2C, E, IC, MODE> Max3010x<I2C, IC, MODE>
+where
+    I2C: i2c::I2c<Error = E>,
+{
+    /// Apply the configuration of a recording, for example to replay it.
+    ///
+    /// The configuration is written as is without validation. The device
+    /// must already be in the mode of the recording, otherwise
+    /// `Error::InvalidArguments` is returned.
+    pub fn apply_recording_config(&mut self, config: &RecordingConfig) -> Result<(), Error<E>> {
+        if config.mode != self.mode.bits & 0b111 {
+            return Err(Error::InvalidArguments);
+        }
+        let [_, fifo_config, spo2_config, led1, led2, slots0, slots1] = config.encode();
+        self.fifo_config.bits = (self.fifo_config.bits & 0b0001_1111) | fifo_config;
+        self.spo2_config.bits = spo2_config;
+        self.led1_pa.bits = led1;
+        self.led2_pa.bits = led2;
+        self.slot_config0.bits = slots0;
+        self.slot_config1.bits = slots1;
+        self.restore_configuration()
+    }
+}
+
+impl<I2C, E, IC, MODE> Max3010x<I2C, IC, MODE>
+where
+    I2C: i2c::I2c<Error = E>,
+    MODE: ChannelCount<IC, MODE>,
+{
+    /// Read the available samples from the FIFO and write them as a record
+    /// into `output`.
+    ///
+    /// `timestamp_us` is the time of the read in µs. As many whole samples
+    /// as fit into `output` after the record header are read. The length of
+    /// the record is returned.
+    ///
+    /// `RecordError::Recording` is returned without reading the FIFO if
+    /// `output` cannot hold the record header or if the time since the
+    /// previous read does not fit into a record.
+    pub fn record_fifo(
+        &mut self,
+        recorder: &mut Recorder,
+        timestamp_us: u64,
+        output: &mut [u8],
+    ) -> Result<usize, RecordError<E>> {
+        if output.len() < RECORD_HEADER_LEN {
+            return Err(RecordError::Recording(RecordingError::BufferTooSmall));
+        }
+        let delta_us = recorder
+            .time_delta_us(timestamp_us)
+            .map_err(RecordError::Recording)?;
+        let pointers = self.read_fifo_pointers()?;
+        let available = usize::from(pointers.available());
+        let bytes_per_sample = usize::from(self.channel_count()) * BYTES_PER_CHANNEL;
+        let fitting = (output.len() - RECORD_HEADER_LEN)
+            .checked_div(bytes_per_sample)
+            .unwrap_or(0);
+        let len = available.min(fitting) * bytes_per_sample;
+        if len != 0 {
+            let data = &mut output[RECORD_HEADER_LEN..RECORD_HEADER_LEN + len];
+            self.read_data(Reg::FIFO_DATA, data)?;
+        }
+        recorder.write_record_header(timestamp_us, delta_us, pointers.overflow, len, output);
+        Ok(RECORD_HEADER_LEN + len)
+    }
+}
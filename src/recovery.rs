@@ -0,0 +1,70 @@
+//! Recovery from supply brown-outs.
+
+use crate::{Error, InterruptStatus, Max3010x, Register as Reg};
+use hal::i2c;
+
+/// Result of [`check_and_recover()`](Max3010x::check_and_recover).
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecoveryCheck {
+    /// Interrupt status read during the check.
+    ///
+    /// Reading the status clears the interrupts so it is returned here for
+    /// the application to handle the other interrupts.
+    pub status: InterruptStatus,
+    /// Whether the device had been reset and the configuration was
+    /// written again.
+    pub recovered: bool,
+}
+
+impl<I2C, E, IC, MODE> Max3010x<I2C, IC, MODE>
+where
+    I2C: i2c::I2c<Error = E>,
+{
+    /// Check whether the device went through a power-on reset and restore
+    /// the configuration if so.
+    ///
+    /// After a supply brown-out the device resets all registers to their
+    /// default values and sets the power-ready interrupt. If the interrupt
+    /// is set, the whole configuration cached by the driver (interrupt
+    /// enables, FIFO configuration, SpO2 configuration, LED pulse
+    /// amplitudes, multi-LED time slots and mode) is written again and the
+    /// FIFO is cleared. Any temperature measurement in progress is lost.
+    ///
+    /// This should be called regularly or when the interrupt pin is
+    /// asserted.
+    pub fn check_and_recover(&mut self) -> Result<RecoveryCheck, Error<E>> {
+        let status = self.read_interrupt_status()?;
+        if status.power_ready {
+            self.restore_configuration()?;
+        }
+        Ok(RecoveryCheck {
+            status,
+            recovered: status.power_ready,
+        })
+    }
+
+    /// Write the whole cached configuration to the device.
+    ///
+    /// The mode is written last so that the device starts sampling with
+    /// the complete configuration. This also writes any changes pending in
+    /// a batch.
+    pub(crate) fn restore_configuration(&mut self) -> Result<(), Error<E>> {
+        self.write_data(&[Reg::INT_EN1, self.int_en1.bits, self.int_en2.bits])?;
+        self.write_data(&[Reg::FIFO_CONFIG, self.fifo_config.bits])?;
+        self.write_data(&[Reg::SPO2_CONFIG, self.spo2_config.bits])?;
+        self.write_data(&[Reg::LED1_PA, self.led1_pa.bits, self.led2_pa.bits])?;
+        self.write_data(&[
+            Reg::SLOT_CONFIG0,
+            self.slot_config0.bits,
+            self.slot_config1.bits,
+        ])?;
+        self.write_data(&[Reg::MODE, self.mode.bits])?;
+        self.temperature_measurement_started = false;
+        if self.batch.is_some() {
+            self.batch = Some(0);
+        }
+        self.write_data(&[Reg::FIFO_WR_PTR, 0, 0, 0])
+    }
+}
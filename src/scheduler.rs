@@ -0,0 +1,303 @@
+//! Duty-cycled measurements.
+
+use crate::{reading::ChannelCount, Error, Max3010x, PowerConfig};
+use hal::{delay::DelayNs, i2c};
+
+/// Maximum number of samples waited for at once so that the FIFO does not
+/// overflow while waiting.
+const MAX_WAIT_SAMPLES: u64 = 16;
+
+/// Timing of a measurement duty cycle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DutyCycle {
+    /// Time to wait after waking the device up before collecting samples in ms.
+    ///
+    /// The first samples after waking up are discarded while the ambient
+    /// light cancellation and the LED drivers settle.
+    ///
+    /// The power-ready flag is not checked instead: it is only set when the
+    /// supply comes up after a brown-out, not when leaving shutdown.
+    pub settling_ms: u32,
+    /// Time during which samples are collected in ms.
+    ///
+    /// If the samples have not been collected after twice this time, for
+    /// example because the device was reset, the cycle is aborted with
+    /// [`Error::Timeout`].
+    pub acquisition_ms: u32,
+    /// Time in shutdown between the measurements in ms.
+    pub sleep_ms: u32,
+}
+
+impl Default for DutyCycle {
+    /// Settle for 100 ms, collect samples for 10 s and sleep for the rest of
+    /// the minute.
+    fn default() -> Self {
+        DutyCycle {
+            settling_ms: 100,
+            acquisition_ms: 10_000,
+            sleep_ms: 49_900,
+        }
+    }
+}
+
+impl DutyCycle {
+    /// Duration of a whole cycle in ms.
+    pub fn period_ms(&self) -> u64 {
+        u64::from(self.settling_ms) + u64::from(self.acquisition_ms) + u64::from(self.sleep_ms)
+    }
+
+    /// Time after which the acquisition is aborted in ms.
+    fn acquisition_timeout_ms(&self) -> u64 {
+        2 * u64::from(self.acquisition_ms)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum Phase {
+    Sleeping { until_ms: u64 },
+    Settling { until_ms: u64 },
+    Acquiring { sample_count: usize, until_ms: u64 },
+}
+
+/// Scheduler waking the device up, collecting samples and shutting it down
+/// again periodically.
+///
+/// The scheduler can be driven either by a [`DelayNs`] implementation with
+/// [`run_cycle()`](DutyCycleScheduler::run_cycle) or by calling
+/// [`poll()`](DutyCycleScheduler::poll) regularly with a timestamp, for
+/// example from a timer interrupt or an event loop.
+///
+/// The device must be configured before the first cycle. The configuration
+/// is kept while the device is shut down.
+///
+/// ```no_run
+/// use linux_embedded_hal::{Delay, I2cdev};
+/// use max3010x::{
+///     signal::beat::{find_beats, MAX_BEATS},
+///     DutyCycle, DutyCycleScheduler, Max3010x,
+/// };
+///
+/// let dev = I2cdev::new("/dev/i2c-1").unwrap();
+/// let mut sensor = Max3010x::new_max30102(dev).into_heart_rate().unwrap();
+/// let mut scheduler = DutyCycleScheduler::new(DutyCycle::default());
+/// let mut samples = [0; 512];
+/// loop {
+///     let beat_count = scheduler
+///         .run_cycle(&mut sensor, &mut Delay, &mut samples, |samples| {
+///             let mut beats = [0; MAX_BEATS];
+///             find_beats(samples, 50.0, &mut beats)
+///         })
+///         .unwrap();
+///     println!("Beats: {}", beat_count);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DutyCycleScheduler {
+    duty_cycle: DutyCycle,
+    phase: Phase,
+}
+
+impl DutyCycleScheduler {
+    /// Create a new scheduler. The first cycle starts at the next poll.
+    pub fn new(duty_cycle: DutyCycle) -> Self {
+        DutyCycleScheduler {
+            duty_cycle,
+            phase: Phase::Sleeping { until_ms: 0 },
+        }
+    }
+
+    /// Get the duty cycle timing.
+    pub fn duty_cycle(&self) -> DutyCycle {
+        self.duty_cycle
+    }
+
+    /// Return whether the device is shut down waiting for the next cycle.
+    pub fn is_sleeping(&self) -> bool {
+        matches!(self.phase, Phase::Sleeping { .. })
+    }
+
+    /// Start a new cycle at the next poll.
+    ///
+    /// The device is not shut down by this.
+    pub fn reset(&mut self) {
+        self.phase = Phase::Sleeping { until_ms: 0 };
+    }
+
+    /// Number of samples collected per cycle with the current device
+    /// configuration.
+    pub fn sample_count<I2C, IC, MODE>(&self, sensor: &Max3010x<I2C, IC, MODE>) -> usize {
+        let rate = sensor.get_effective_sampling_rate();
+        (self.duty_cycle.acquisition_ms as f32 * rate / 1000.0 + 0.5) as usize
+    }
+
+    /// Estimate the average supply current over a whole cycle in µA.
+    ///
+    /// The current while sampling is estimated from the current
+    /// configuration of the device. See [`PowerConfig`].
+    pub fn average_current_ua<I2C, IC, MODE>(&self, sensor: &Max3010x<I2C, IC, MODE>) -> f32 {
+        let active = PowerConfig {
+            shutdown: false,
+            ..sensor.get_power_config()
+        };
+        let active_ua = active.supply_current_ua();
+        let sleeping_ua = PowerConfig {
+            shutdown: true,
+            ..active
+        }
+        .supply_current_ua();
+        let active_ms =
+            u64::from(self.duty_cycle.settling_ms) + u64::from(self.duty_cycle.acquisition_ms);
+        let period_ms = self.duty_cycle.period_ms();
+        if period_ms == 0 {
+            return active_ua;
+        }
+        (active_ua * active_ms as f32 + sleeping_ua * self.duty_cycle.sleep_ms as f32)
+            / period_ms as f32
+    }
+
+    /// Advance the cycle at the time `now_ms`.
+    ///
+    /// `now_ms` must be a monotonic timestamp in ms. Once all samples of the
+    /// cycle have been collected into `buffer`, the device is shut down and
+    /// the number of samples is returned. Otherwise `None` is returned.
+    ///
+    /// The buffer must contain one element per channel per sample and must
+    /// not be modified until the cycle completes. Fewer samples are
+    /// collected if it cannot hold all of them. If a smaller buffer is
+    /// passed during the cycle, the cycle completes once it is full. The FIFO must be read before
+    /// it overflows, so this should be polled at least every 16 sample
+    /// periods while not sleeping.
+    ///
+    /// If the samples have not been collected after twice the acquisition
+    /// time, the device is shut down, the next cycle is scheduled and
+    /// `Error::Timeout` is returned.
+    pub fn poll<I2C, E, IC, MODE>(
+        &mut self,
+        sensor: &mut Max3010x<I2C, IC, MODE>,
+        now_ms: u64,
+        buffer: &mut [u32],
+    ) -> Result<Option<usize>, Error<E>>
+    where
+        I2C: i2c::I2c<Error = E>,
+        MODE: ChannelCount<IC, MODE>,
+    {
+        loop {
+            match self.phase {
+                Phase::Sleeping { until_ms } => {
+                    if now_ms < until_ms {
+                        return Ok(None);
+                    }
+                    sensor.wake_up()?;
+                    let until_ms = now_ms + u64::from(self.duty_cycle.settling_ms);
+                    self.phase = Phase::Settling { until_ms };
+                }
+                Phase::Settling { until_ms } => {
+                    if now_ms < until_ms {
+                        return Ok(None);
+                    }
+                    sensor.clear_fifo()?;
+                    let until_ms = now_ms + self.duty_cycle.acquisition_timeout_ms();
+                    self.phase = Phase::Acquiring {
+                        sample_count: 0,
+                        until_ms,
+                    };
+                    return Ok(None);
+                }
+                Phase::Acquiring {
+                    sample_count,
+                    until_ms,
+                } => {
+                    let channels = usize::from(sensor.channel_count());
+                    let fitting = buffer.len().checked_div(channels).unwrap_or(0);
+                    let target = self.sample_count(sensor).min(fitting);
+                    // The buffer or the configuration may have changed since
+                    // the last poll.
+                    let sample_count = sample_count.min(target);
+                    let output = &mut buffer[sample_count * channels..target * channels];
+                    let sample_count = sample_count + usize::from(sensor.read_fifo(output)?);
+                    if sample_count < target && now_ms < until_ms {
+                        self.phase = Phase::Acquiring {
+                            sample_count,
+                            until_ms,
+                        };
+                        return Ok(None);
+                    }
+                    sensor.shutdown()?;
+                    let until_ms = now_ms + u64::from(self.duty_cycle.sleep_ms);
+                    self.phase = Phase::Sleeping { until_ms };
+                    if sample_count < target {
+                        return Err(Error::Timeout);
+                    }
+                    return Ok(Some(sample_count));
+                }
+            }
+        }
+    }
+
+    /// Run a whole cycle blocking with the given delay.
+    ///
+    /// The device is woken up, the samples are collected into `buffer` and
+    /// the device is shut down. Then `measure` is called with the samples
+    /// collected and the delay waits for the sleep time. The result of
+    /// `measure` is returned.
+    ///
+    /// The buffer must contain one element per channel per sample. Fewer
+    /// samples are collected if it cannot hold all of them.
+    ///
+    /// If the samples have not been collected after twice the acquisition
+    /// time as counted by the delay, the device is shut down and
+    /// `Error::Timeout` is returned without calling `measure` or waiting
+    /// for the sleep time.
+    ///
+    /// This starts a new cycle regardless of any cycle in progress with
+    /// [`poll()`](DutyCycleScheduler::poll).
+    pub fn run_cycle<I2C, E, IC, MODE, D, F, R>(
+        &mut self,
+        sensor: &mut Max3010x<I2C, IC, MODE>,
+        delay: &mut D,
+        buffer: &mut [u32],
+        measure: F,
+    ) -> Result<R, Error<E>>
+    where
+        I2C: i2c::I2c<Error = E>,
+        MODE: ChannelCount<IC, MODE>,
+        D: DelayNs,
+        F: FnOnce(&[u32]) -> R,
+    {
+        self.reset();
+        sensor.wake_up()?;
+        delay.delay_ms(self.duty_cycle.settling_ms);
+        sensor.clear_fifo()?;
+        let channels = usize::from(sensor.channel_count());
+        let fitting = buffer.len().checked_div(channels).unwrap_or(0);
+        let target = self.sample_count(sensor).min(fitting);
+        let timeout_us = self.duty_cycle.acquisition_timeout_ms() * 1000;
+        let mut waited_us = 0;
+        let mut sample_count = 0;
+        loop {
+            let output = &mut buffer[sample_count.min(target) * channels..target * channels];
+            sample_count += usize::from(sensor.read_fifo(output)?);
+            if sample_count >= target {
+                break;
+            }
+            if waited_us >= timeout_us {
+                sensor.shutdown()?;
+                return Err(Error::Timeout);
+            }
+            let remaining = ((target - sample_count) as u64).min(MAX_WAIT_SAMPLES);
+            let wait_us = sensor.sample_time_us(remaining);
+            delay.delay_us(wait_us as u32);
+            waited_us += wait_us;
+        }
+        sensor.shutdown()?;
+        let result = measure(&buffer[..sample_count * channels]);
+        delay.delay_ms(self.duty_cycle.sleep_ms);
+        Ok(result)
+    }
+}
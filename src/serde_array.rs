@@ -0,0 +1,142 @@
+//! Serde support for arrays of any length.
+//!
+//! serde only implements its traits for arrays of up to 32 elements and not
+//! for const-generic lengths. Use with `#[serde(with = "crate::serde_array")]`.
+
+use core::{fmt, marker::PhantomData};
+use serde::{
+    de::{Error, SeqAccess, Visitor},
+    ser::SerializeTuple,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+pub(crate) fn serialize<S, T, const N: usize>(
+    array: &[T; N],
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize,
+{
+    let mut tuple = serializer.serialize_tuple(N)?;
+    for element in array {
+        tuple.serialize_element(element)?;
+    }
+    tuple.end()
+}
+
+struct ArrayVisitor<T, const N: usize>(PhantomData<T>);
+
+impl<'de, T, const N: usize> Visitor<'de> for ArrayVisitor<T, N>
+where
+    T: Deserialize<'de> + Default + Copy,
+{
+    type Value = [T; N];
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "an array of length {}", N)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut array = [T::default(); N];
+        for (i, element) in array.iter_mut().enumerate() {
+            *element = seq
+                .next_element()?
+                .ok_or_else(|| Error::invalid_length(i, &self))?;
+        }
+        Ok(array)
+    }
+}
+
+pub(crate) fn deserialize<'de, D, T, const N: usize>(deserializer: D) -> Result<[T; N], D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default + Copy,
+{
+    deserializer.deserialize_tuple(N, ArrayVisitor(PhantomData))
+}
+
+/// Arrays of arrays of any length. Use with
+/// `#[serde(with = "crate::serde_array::nested")]`.
+pub(crate) mod nested {
+    use core::{fmt, marker::PhantomData};
+    use serde::{
+        de::{Error, SeqAccess, Visitor},
+        ser::SerializeTuple,
+        Deserialize, Deserializer, Serialize, Serializer,
+    };
+
+    struct Inner<'a, T, const N: usize>(&'a [T; N]);
+
+    impl<T: Serialize, const N: usize> Serialize for Inner<'_, T, N> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            super::serialize(self.0, serializer)
+        }
+    }
+
+    struct OwnedInner<T, const N: usize>([T; N]);
+
+    impl<'de, T, const N: usize> Deserialize<'de> for OwnedInner<T, N>
+    where
+        T: Deserialize<'de> + Default + Copy,
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            super::deserialize(deserializer).map(OwnedInner)
+        }
+    }
+
+    pub(crate) fn serialize<S, T, const N: usize, const M: usize>(
+        array: &[[T; N]; M],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize,
+    {
+        let mut tuple = serializer.serialize_tuple(M)?;
+        for inner in array {
+            tuple.serialize_element(&Inner(inner))?;
+        }
+        tuple.end()
+    }
+
+    struct NestedVisitor<T, const N: usize, const M: usize>(PhantomData<T>);
+
+    impl<'de, T, const N: usize, const M: usize> Visitor<'de> for NestedVisitor<T, N, M>
+    where
+        T: Deserialize<'de> + Default + Copy,
+    {
+        type Value = [[T; N]; M];
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "{} arrays of length {}", M, N)
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut array = [[T::default(); N]; M];
+            for (i, element) in array.iter_mut().enumerate() {
+                let inner: OwnedInner<T, N> = seq
+                    .next_element()?
+                    .ok_or_else(|| Error::invalid_length(i, &self))?;
+                *element = inner.0;
+            }
+            Ok(array)
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D, T, const N: usize, const M: usize>(
+        deserializer: D,
+    ) -> Result<[[T; N]; M], D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de> + Default + Copy,
+    {
+        deserializer.deserialize_tuple(M, NestedVisitor(PhantomData))
+    }
+}
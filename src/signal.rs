@@ -0,0 +1,43 @@
+//! Processing of the samples read from the FIFO.
+//!
+//! These are pure `no_std` building blocks which do not allocate. They
+//! operate on the values returned by [`read_fifo()`](crate::Max3010x::read_fifo).
+
+pub mod beat;
+pub mod fixed;
+pub mod hrv;
+pub mod motion;
+pub mod perfusion;
+pub mod quality;
+pub mod respiration;
+pub mod spo2;
+
+/// View of one channel of interleaved FIFO samples.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Channel<'a> {
+    samples: &'a [u32],
+    channels: usize,
+    index: usize,
+}
+
+impl<'a> Channel<'a> {
+    pub(crate) fn new(samples: &'a [u32], channels: usize, index: usize) -> Self {
+        Channel {
+            samples,
+            channels,
+            index,
+        }
+    }
+
+    pub(crate) fn single(samples: &'a [u32]) -> Self {
+        Channel::new(samples, 1, 0)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    pub(crate) fn get(&self, sample: usize) -> u32 {
+        self.samples[sample * self.channels + self.index]
+    }
+}
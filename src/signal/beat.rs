@@ -0,0 +1,223 @@
+//! Beat detection in PPG sample windows.
+//!
+//! The light received by the photodetector decreases with each pulse, so
+//! the beats are located at the minima of the raw samples. The samples are
+//! detrended with a moving average of 0.75 s and the beats are detected as
+//! the peaks of the inverted signal above 30% of its maximum which are at
+//! least 0.3 s apart (200 bpm).
+
+use crate::signal::Channel;
+
+/// Maximum number of beats detected in a window by the `signal` modules.
+pub const MAX_BEATS: usize = 64;
+
+/// Baseline-removed, inverted samples: positive during the pulses.
+pub(crate) struct Detrended<'a> {
+    samples: Channel<'a>,
+    half_window: usize,
+    index: usize,
+    low: usize,
+    high: usize,
+    sum: u64,
+}
+
+impl<'a> Detrended<'a> {
+    pub(crate) fn new(samples: Channel<'a>, sample_rate: f32) -> Self {
+        // The window never needs to be wider than the samples, which also
+        // keeps the window bounds from overflowing for huge sampling rates.
+        let half_window = ((sample_rate * 0.375) as usize).clamp(1, samples.len().max(1));
+        Detrended {
+            samples,
+            half_window,
+            index: 0,
+            low: 0,
+            high: 0,
+            sum: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for Detrended<'a> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.index >= self.samples.len() {
+            return None;
+        }
+        let value = self.samples.get(self.index);
+        let high = (self.index + self.half_window + 1).min(self.samples.len());
+        let low = self.index.saturating_sub(self.half_window);
+        while self.high < high {
+            self.sum += u64::from(self.samples.get(self.high));
+            self.high += 1;
+        }
+        while self.low < low {
+            self.sum -= u64::from(self.samples.get(self.low));
+            self.low += 1;
+        }
+        self.index += 1;
+        let baseline = self.sum as f32 / (self.high - self.low) as f32;
+        Some(baseline - value as f32)
+    }
+}
+
+/// Find the beats in a window of samples of one channel.
+///
+/// `sample_rate` is the effective sampling rate of the samples (see
+/// [`get_effective_sampling_rate()`](crate::Max3010x::get_effective_sampling_rate)).
+/// The sample indices of the beats are stored in `beats` and the number
+/// of beats found is returned. Detection stops when `beats` is full.
+pub fn find_beats(samples: &[u32], sample_rate: f32, beats: &mut [usize]) -> usize {
+    find_channel_beats(Channel::single(samples), sample_rate, beats)
+}
+
+pub(crate) fn find_channel_beats(samples: Channel, sample_rate: f32, beats: &mut [usize]) -> usize {
+    if samples.len() < 3 || beats.is_empty() {
+        return 0;
+    }
+    let max = Detrended::new(samples, sample_rate).fold(0.0_f32, f32::max);
+    if max <= 0.0 {
+        return 0;
+    }
+    let threshold = 0.3 * max;
+    let refractory = ((sample_rate * 0.3) as usize).max(1);
+    let mut count = 0;
+    let mut last_height = 0.0;
+    let mut values = Detrended::new(samples, sample_rate);
+    let mut previous = values.next().unwrap_or(0.0);
+    let mut current = values.next().unwrap_or(0.0);
+    for (index, next) in values.enumerate().map(|(i, v)| (i + 1, v)) {
+        if current > threshold && current >= previous && current > next {
+            if count > 0 && index - beats[count - 1] < refractory {
+                if current > last_height {
+                    beats[count - 1] = index;
+                    last_height = current;
+                }
+            } else if count < beats.len() {
+                beats[count] = index;
+                last_height = current;
+                count += 1;
+            } else {
+                break;
+            }
+        }
+        previous = current;
+        current = next;
+    }
+    count
+}
+
+/// Position of a detected beat with sub-sample resolution in samples.
+///
+/// This is the vertex of the parabola through the detrended samples around
+/// the beat.
+pub(crate) fn refine_beat(samples: Channel, sample_rate: f32, beat: usize) -> f32 {
+    if beat == 0 || beat + 1 >= samples.len() {
+        return beat as f32;
+    }
+    let mut values = Detrended::new(samples, sample_rate).skip(beat - 1);
+    let mut next = || values.next().unwrap_or(0.0);
+    let (before, at, after) = (next(), next(), next());
+    let curvature = before - 2.0 * at + after;
+    if curvature >= 0.0 {
+        return beat as f32;
+    }
+    beat as f32 + (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+}
+
+/// Average heart rate in beats per minute from the detected beats.
+///
+/// Returns `None` if fewer than two beats are given or if the beat indices
+/// are not strictly increasing.
+pub fn heart_rate(beats: &[usize], sample_rate: f32) -> Option<f32> {
+    if beats.len() < 2 || beats.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return None;
+    }
+    let span = (beats[beats.len() - 1] - beats[0]) as f32;
+    Some(60.0 * sample_rate * (beats.len() - 1) as f32 / span)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// PPG-like signal: the received light dips once per beat.
+    pub(crate) fn ppg(
+        samples: &mut [u32],
+        sample_rate: f32,
+        bpm: f32,
+        dc: f32,
+        ac: f32,
+    ) -> &mut [u32] {
+        let period = 60.0 * sample_rate / bpm;
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let phase = (i as f32 % period) / period;
+            let pulse = if phase < 0.2 {
+                phase / 0.2
+            } else {
+                1.0 - (phase - 0.2) / 0.8
+            };
+            *sample = (dc - ac * pulse) as u32;
+        }
+        samples
+    }
+
+    #[test]
+    fn finds_no_beats_in_flat_signal() {
+        let mut beats = [0; MAX_BEATS];
+        assert_eq!(0, find_beats(&[1000; 100], 50.0, &mut beats));
+    }
+
+    #[test]
+    fn finds_beats_at_pulse_minima() {
+        let mut samples = [0; 500];
+        let samples = ppg(&mut samples, 50.0, 60.0, 100_000.0, 1000.0);
+        let mut beats = [0; MAX_BEATS];
+        let count = find_beats(samples, 50.0, &mut beats);
+        assert_eq!(
+            &[10, 60, 110, 160, 210, 260, 310, 360, 410, 460],
+            &beats[..count]
+        );
+    }
+
+    #[test]
+    fn stops_when_output_is_full() {
+        let mut samples = [0; 500];
+        let samples = ppg(&mut samples, 50.0, 60.0, 100_000.0, 1000.0);
+        let mut beats = [0; 3];
+        assert_eq!(3, find_beats(samples, 50.0, &mut beats));
+        assert_eq!([10, 60, 110], beats);
+    }
+
+    #[test]
+    fn calculates_heart_rate() {
+        let mut samples = [0; 1000];
+        let samples = ppg(&mut samples, 100.0, 75.0, 100_000.0, 1000.0);
+        let mut beats = [0; MAX_BEATS];
+        let count = find_beats(samples, 100.0, &mut beats);
+        let rate = heart_rate(&beats[..count], 100.0).unwrap();
+        assert!(libm::fabsf(rate - 75.0) < 0.5, "{}", rate);
+    }
+
+    #[test]
+    fn heart_rate_needs_two_beats() {
+        assert_eq!(None, heart_rate(&[10], 50.0));
+    }
+
+    #[test]
+    fn heart_rate_needs_increasing_beats() {
+        assert_eq!(None, heart_rate(&[60, 10], 50.0));
+        assert_eq!(None, heart_rate(&[10, 10], 50.0));
+        assert_eq!(None, heart_rate(&[10, 60, 60, 110], 50.0));
+    }
+
+    #[test]
+    fn does_not_panic_with_invalid_sampling_rate() {
+        let mut samples = [0; 500];
+        let samples = ppg(&mut samples, 50.0, 60.0, 100_000.0, 1000.0);
+        let mut beats = [0; MAX_BEATS];
+        for rate in [f32::MAX, f32::INFINITY, f32::NAN] {
+            find_beats(samples, rate, &mut beats);
+        }
+    }
+}
@@ -0,0 +1,751 @@
+//! Fixed-point filters for targets without FPU.
+//!
+//! These filters only use integer arithmetic. They take the values returned
+//! by [`read_fifo()`](crate::Max3010x::read_fifo) (or the output of another
+//! filter) and produce `i32` values. All intermediate results are rounded
+//! half up.
+//!
+//! The DC blocker and the band-pass filter are tuned for each
+//! [`SamplingRate`] without sample averaging. With sample averaging, create
+//! them with your own coefficients for the effective sampling rate.
+//!
+//! ```no_run
+//! use linux_embedded_hal::I2cdev;
+//! use max3010x::{
+//!     signal::fixed::{Biquad, DcBlocker, MovingAverage},
+//!     Max3010x, SamplingRate,
+//! };
+//!
+//! let dev = I2cdev::new("/dev/i2c-1").unwrap();
+//! let mut sensor = Max3010x::new_max30102(dev).into_heart_rate().unwrap();
+//! sensor.set_sampling_rate(SamplingRate::Sps100).unwrap();
+//! let mut dc_blocker = DcBlocker::for_sampling_rate(SamplingRate::Sps100);
+//! let mut band_pass = Biquad::band_pass(SamplingRate::Sps100);
+//! let mut smoothing = MovingAverage::<4>::new();
+//!
+//! let mut samples = [0; 32];
+//! let count = usize::from(sensor.read_fifo(&mut samples).unwrap());
+//! let mut ac = [0; 32];
+//! dc_blocker.process(&samples[..count], &mut ac);
+//! let mut filtered = [0; 32];
+//! band_pass.process(&ac[..count], &mut filtered);
+//! smoothing.process(&filtered[..count], &mut ac);
+//! ```
+
+use crate::SamplingRate;
+
+/// Number of fractional bits of the DC blocker coefficient.
+const DC_BLOCKER_SHIFT: u32 = 15;
+/// Number of fractional bits of the biquad coefficients.
+const BIQUAD_SHIFT: u32 = 30;
+/// Number of fractional bits of the previous outputs kept by the recursive
+/// filters. Otherwise the rounding errors build up when the poles are close
+/// to 1, as for the high sampling rates.
+const STATE_SHIFT: u32 = 8;
+
+/// Shift right rounding half up.
+fn round_shift(value: i64, shift: u32) -> i64 {
+    (value + (1 << (shift - 1))) >> shift
+}
+
+/// Divide rounding half up.
+fn round_div(value: i64, divisor: i64) -> i64 {
+    (2 * value + divisor).div_euclid(2 * divisor)
+}
+
+fn saturate(value: i64) -> i32 {
+    value.clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32
+}
+
+/// Apply `step` to each input value and store the results in `output`.
+fn process_with<T: Copy + Into<i64>>(
+    input: &[T],
+    output: &mut [i32],
+    mut step: impl FnMut(i32) -> i32,
+) -> usize {
+    let mut count = 0;
+    for (input, output) in input.iter().zip(output.iter_mut()) {
+        *output = step(saturate((*input).into()));
+        count += 1;
+    }
+    count
+}
+
+/// First-order DC blocker: `y[n] = x[n] - x[n-1] + a·y[n-1]`.
+///
+/// The coefficient `a` is in Q15 format and the previous output is kept
+/// with 8 fractional bits. The cut-off frequency of the tuned filters is
+/// 0.5 Hz.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DcBlocker {
+    coefficient: i16,
+    previous_input: Option<i32>,
+    /// Previous output with `STATE_SHIFT` fractional bits.
+    previous_output: i32,
+}
+
+impl DcBlocker {
+    /// Create a DC blocker with the given Q15 coefficient.
+    pub fn new(coefficient: i16) -> Self {
+        DcBlocker {
+            coefficient,
+            previous_input: None,
+            previous_output: 0,
+        }
+    }
+
+    /// Create a DC blocker with a cut-off frequency of 0.5 Hz for the
+    /// sampling rate.
+    pub fn for_sampling_rate(sampling_rate: SamplingRate) -> Self {
+        Self::new(match sampling_rate {
+            SamplingRate::Sps50 => 30709,
+            SamplingRate::Sps100 => 31739,
+            SamplingRate::Sps200 => 32253,
+            SamplingRate::Sps400 => 32511,
+            SamplingRate::Sps800 => 32639,
+            SamplingRate::Sps1000 => 32665,
+            SamplingRate::Sps1600 => 32704,
+            SamplingRate::Sps3200 => 32736,
+        })
+    }
+
+    /// Filter one value.
+    ///
+    /// The first value is taken as the initial DC level.
+    pub fn step(&mut self, input: i32) -> i32 {
+        let previous_input = *self.previous_input.get_or_insert(input);
+        let feedback = i64::from(self.coefficient) * i64::from(self.previous_output);
+        let difference = i64::from(input) - i64::from(previous_input);
+        let state = (difference << STATE_SHIFT) + round_shift(feedback, DC_BLOCKER_SHIFT);
+        self.previous_input = Some(input);
+        self.previous_output = saturate(state);
+        round_shift(i64::from(self.previous_output), STATE_SHIFT) as i32
+    }
+
+    /// Filter the input values into `output` and return the number of
+    /// values processed.
+    pub fn process<T: Copy + Into<i64>>(&mut self, input: &[T], output: &mut [i32]) -> usize {
+        process_with(input, output, |value| self.step(value))
+    }
+}
+
+/// Biquad filter in direct form I.
+///
+/// The coefficients are in Q30 format rather than Q31: the poles of the
+/// band-pass filters are close to 1, so `a1` is close to -2 and does not fit
+/// in Q31, which only covers `-1..1`. This is the same as Q31 with a
+/// post-shift of 1 in CMSIS-DSP, and the coefficients keep 30 fractional
+/// bits of precision. `a0` is normalized to 1. The accumulator has 64 bits,
+/// so the input values should fit in 24 bits. The previous outputs are kept
+/// with 8 fractional bits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Biquad {
+    b: [i32; 3],
+    a: [i32; 2],
+    inputs: Option<[i32; 2]>,
+    /// Previous outputs with `STATE_SHIFT` fractional bits.
+    outputs: [i32; 2],
+}
+
+impl Biquad {
+    /// Create a biquad filter with the Q30 coefficients `[b0, b1, b2]` and
+    /// `[a1, a2]`.
+    pub fn new(b: [i32; 3], a: [i32; 2]) -> Self {
+        Biquad {
+            b,
+            a,
+            inputs: None,
+            outputs: [0; 2],
+        }
+    }
+
+    /// Create a band-pass filter from 0.5 Hz to 5 Hz (30 to 300 bpm) for
+    /// the sampling rate.
+    pub fn band_pass(sampling_rate: SamplingRate) -> Self {
+        let (b0, a1, a2) = match sampling_rate {
+            SamplingRate::Sps50 => (235_461_684, -1_643_575_038, 602_818_455),
+            SamplingRate::Sps100 => (132_803_340, -1_872_597_913, 808_135_144),
+            SamplingRate::Sps200 => (70_860_365, -2_003_288_917, 932_021_095),
+            SamplingRate::Sps400 => (36_650_080, -2_073_543_791, 1_000_441_665),
+            SamplingRate::Sps800 => (18_644_627, -2_110_031_686, 1_036_452_569),
+            SamplingRate::Sps1000 => (14_967_819, -2_117_443_515, 1_043_806_187),
+            SamplingRate::Sps1600 => (9_404_139, -2_128_634_336, 1_054_933_545),
+            SamplingRate::Sps3200 => (4_722_774, -2_138_027_797, 1_064_296_276),
+        };
+        Self::new([b0, 0, -b0], [a1, a2])
+    }
+
+    /// Filter one value.
+    ///
+    /// The first value is taken as the previous inputs so that a constant
+    /// input does not cause a transient in a filter blocking DC.
+    pub fn step(&mut self, input: i32) -> i32 {
+        let inputs = *self.inputs.get_or_insert([input; 2]);
+        let feedforward = i64::from(self.b[0]) * i64::from(input)
+            + i64::from(self.b[1]) * i64::from(inputs[0])
+            + i64::from(self.b[2]) * i64::from(inputs[1]);
+        let accumulator = (feedforward << STATE_SHIFT)
+            - i64::from(self.a[0]) * i64::from(self.outputs[0])
+            - i64::from(self.a[1]) * i64::from(self.outputs[1]);
+        let state = saturate(round_shift(accumulator, BIQUAD_SHIFT));
+        let output = round_shift(i64::from(state), STATE_SHIFT) as i32;
+        self.inputs = Some([input, inputs[0]]);
+        self.outputs = [state, self.outputs[0]];
+        output
+    }
+
+    /// Filter the input values into `output` and return the number of
+    /// values processed.
+    pub fn process<T: Copy + Into<i64>>(&mut self, input: &[T], output: &mut [i32]) -> usize {
+        process_with(input, output, |value| self.step(value))
+    }
+}
+
+/// Last `N` values. `N` must be at least 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "WindowFields<N>")
+)]
+struct Window<const N: usize> {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_array"))]
+    values: [i32; N],
+    next: usize,
+    len: usize,
+}
+
+/// Unvalidated contents of a deserialized [`Window`].
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct WindowFields<const N: usize> {
+    #[serde(with = "crate::serde_array")]
+    values: [i32; N],
+    next: usize,
+    len: usize,
+}
+
+#[cfg(feature = "serde")]
+impl<const N: usize> TryFrom<WindowFields<N>> for Window<N> {
+    type Error = &'static str;
+
+    fn try_from(fields: WindowFields<N>) -> Result<Self, Self::Error> {
+        if fields.next >= N || fields.len > N {
+            return Err("window position out of range");
+        }
+        Ok(Window {
+            values: fields.values,
+            next: fields.next,
+            len: fields.len,
+        })
+    }
+}
+
+impl<const N: usize> Window<N> {
+    fn new() -> Self {
+        Window {
+            values: [0; N],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    /// Add a value and return the one it replaces, if any.
+    fn push(&mut self, value: i32) -> Option<i32> {
+        let replaced = if self.len == N {
+            Some(self.values[self.next])
+        } else {
+            self.len += 1;
+            None
+        };
+        self.values[self.next] = value;
+        self.next = (self.next + 1) % N;
+        replaced
+    }
+
+    fn values(&self) -> &[i32] {
+        &self.values[..self.len]
+    }
+}
+
+/// Moving average of the last `N` values.
+///
+/// Until `N` values have been seen, the average of the values so far is
+/// returned. `N` must be at least 1, otherwise using the filter fails to
+/// compile:
+///
+/// ```compile_fail
+/// let filter = max3010x::signal::fixed::MovingAverage::<0>::new();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MovingAverage<const N: usize> {
+    window: Window<N>,
+    sum: i64,
+}
+
+impl<const N: usize> MovingAverage<N> {
+    const HAS_VALUES: () = assert!(N > 0, "MovingAverage needs at least one value");
+
+    /// Create a moving average filter.
+    pub fn new() -> Self {
+        let () = Self::HAS_VALUES;
+        MovingAverage {
+            window: Window::new(),
+            sum: 0,
+        }
+    }
+
+    /// Filter one value.
+    pub fn step(&mut self, input: i32) -> i32 {
+        let () = Self::HAS_VALUES;
+        if let Some(replaced) = self.window.push(input) {
+            self.sum -= i64::from(replaced);
+        }
+        self.sum += i64::from(input);
+        round_div(self.sum, self.window.len as i64) as i32
+    }
+
+    /// Filter the input values into `output` and return the number of
+    /// values processed.
+    pub fn process<T: Copy + Into<i64>>(&mut self, input: &[T], output: &mut [i32]) -> usize {
+        process_with(input, output, |value| self.step(value))
+    }
+}
+
+impl<const N: usize> Default for MovingAverage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Moving median of the last `N` values.
+///
+/// For an even number of values, the average of the two middle values is
+/// returned. Until `N` values have been seen, the median of the values so
+/// far is returned. `N` must be at least 1, otherwise using the filter fails
+/// to compile:
+///
+/// ```compile_fail
+/// let filter = max3010x::signal::fixed::MedianFilter::<0>::new();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MedianFilter<const N: usize> {
+    window: Window<N>,
+}
+
+impl<const N: usize> MedianFilter<N> {
+    const HAS_VALUES: () = assert!(N > 0, "MedianFilter needs at least one value");
+
+    /// Create a median filter.
+    pub fn new() -> Self {
+        let () = Self::HAS_VALUES;
+        MedianFilter {
+            window: Window::new(),
+        }
+    }
+
+    /// Filter one value.
+    pub fn step(&mut self, input: i32) -> i32 {
+        let () = Self::HAS_VALUES;
+        self.window.push(input);
+        let len = self.window.len;
+        let mut sorted = [0; N];
+        sorted[..len].copy_from_slice(self.window.values());
+        let sorted = &mut sorted[..len];
+        sorted.sort_unstable();
+        if len % 2 == 1 {
+            sorted[len / 2]
+        } else {
+            let sum = i64::from(sorted[len / 2 - 1]) + i64::from(sorted[len / 2]);
+            round_div(sum, 2) as i32
+        }
+    }
+
+    /// Filter the input values into `output` and return the number of
+    /// values processed.
+    pub fn process<T: Copy + Into<i64>>(&mut self, input: &[T], output: &mut [i32]) -> usize {
+        process_with(input, output, |value| self.step(value))
+    }
+}
+
+impl<const N: usize> Default for MedianFilter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decimator by a factor of `N` averaging each block of `N` values.
+///
+/// `N` must be at least 1, otherwise using the decimator fails to compile:
+///
+/// ```compile_fail
+/// let decimator = max3010x::signal::fixed::Decimator::<0>::new();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Decimator<const N: usize> {
+    sum: i64,
+    count: usize,
+}
+
+impl<const N: usize> Decimator<N> {
+    const HAS_VALUES: () = assert!(N > 0, "Decimator needs a factor of at least 1");
+
+    /// Create a decimator.
+    pub fn new() -> Self {
+        let () = Self::HAS_VALUES;
+        Decimator { sum: 0, count: 0 }
+    }
+
+    /// Add one value and return the average of the block once it is
+    /// complete.
+    pub fn step(&mut self, input: i32) -> Option<i32> {
+        let () = Self::HAS_VALUES;
+        self.sum += i64::from(input);
+        self.count += 1;
+        if self.count < N {
+            return None;
+        }
+        let output = round_div(self.sum, N as i64) as i32;
+        self.sum = 0;
+        self.count = 0;
+        Some(output)
+    }
+
+    /// Decimate the input values into `output` and return the number of
+    /// output values.
+    ///
+    /// The values of an incomplete block are kept for the next call.
+    /// Processing stops when `output` is full.
+    pub fn process<T: Copy + Into<i64>>(&mut self, input: &[T], output: &mut [i32]) -> usize {
+        let mut count = 0;
+        for value in input {
+            if count == output.len() {
+                break;
+            }
+            if let Some(value) = self.step(saturate((*value).into())) {
+                output[count] = value;
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+impl<const N: usize> Default for Decimator<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f32::consts::PI;
+
+    const RATES: [SamplingRate; 8] = [
+        SamplingRate::Sps50,
+        SamplingRate::Sps100,
+        SamplingRate::Sps200,
+        SamplingRate::Sps400,
+        SamplingRate::Sps800,
+        SamplingRate::Sps1000,
+        SamplingRate::Sps1600,
+        SamplingRate::Sps3200,
+    ];
+
+    /// PPG-like 18-bit input: DC, 1.2 Hz pulse and pseudo-random noise.
+    fn input(sample_rate: f32) -> [u32; 2000] {
+        let mut samples = [0; 2000];
+        let mut noise: u32 = 12345;
+        for (i, sample) in samples.iter_mut().enumerate() {
+            noise = noise.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let pulse = 2000.0 * libm::sinf(2.0 * PI * 1.2 * i as f32 / sample_rate);
+            *sample = (150_000.0 + pulse) as u32 + (noise >> 16) % 256;
+        }
+        samples
+    }
+
+    /// Round half up as done by the fixed-point filters.
+    fn round(value: f64) -> f64 {
+        libm::floor(value + 0.5)
+    }
+
+    /// DC blocker coefficient `1 - 2π·fc/fs` for a cut-off frequency of
+    /// 0.5 Hz.
+    fn dc_blocker_coefficient(rate: SamplingRate) -> f64 {
+        1.0 - 2.0 * core::f64::consts::PI * 0.5 / f64::from(rate.samples_per_second())
+    }
+
+    /// Band-pass coefficients `([b0, b1, b2], [a1, a2])` from 0.5 Hz to
+    /// 5 Hz as in the Audio EQ Cookbook (constant 0 dB peak gain).
+    fn band_pass_coefficients(rate: SamplingRate) -> ([f64; 3], [f64; 2]) {
+        let center = libm::sqrt(0.5 * 5.0);
+        let q = center / (5.0 - 0.5);
+        let w0 = 2.0 * core::f64::consts::PI * center / f64::from(rate.samples_per_second());
+        let alpha = libm::sin(w0) / (2.0 * q);
+        let b0 = alpha / (1.0 + alpha);
+        let a1 = -2.0 * libm::cos(w0) / (1.0 + alpha);
+        let a2 = (1.0 - alpha) / (1.0 + alpha);
+        ([b0, 0.0, -b0], [a1, a2])
+    }
+
+    /// Divide rounding half up, without overflow for the 64-bit
+    /// accumulators of the filters.
+    fn round_div_exact(value: i128, divisor: i128) -> i128 {
+        (value + divisor / 2).div_euclid(divisor)
+    }
+
+    fn max_error(output: &[i32], reference: &[f64]) -> f64 {
+        output
+            .iter()
+            .zip(reference)
+            .map(|(value, expected)| libm::fabs(f64::from(*value) - expected))
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn dc_blocker_coefficients_are_rounded_design() {
+        for rate in RATES {
+            let expected = libm::round(dc_blocker_coefficient(rate) * 32768.0);
+            let filter = DcBlocker::for_sampling_rate(rate);
+            assert_eq!(expected, f64::from(filter.coefficient), "{:?}", rate);
+        }
+    }
+
+    #[test]
+    fn band_pass_coefficients_are_rounded_design() {
+        for rate in RATES {
+            let (b, a) = band_pass_coefficients(rate);
+            let scale = f64::from(1 << BIQUAD_SHIFT);
+            let filter = Biquad::band_pass(rate);
+            assert_eq!(b.map(|b| libm::round(b * scale)), filter.b.map(f64::from));
+            assert_eq!(a.map(|a| libm::round(a * scale)), filter.a.map(f64::from));
+        }
+    }
+
+    /// The DC blocker computes `y[n] = x[n] - x[n-1] + a·y[n-1]` with the
+    /// Q15 coefficient, keeping `y` with 8 fractional bits.
+    #[test]
+    fn dc_blocker_is_bit_exact() {
+        for rate in RATES {
+            let samples = input(f32::from(rate.samples_per_second()));
+            let mut filter = DcBlocker::for_sampling_rate(rate);
+            let mut output = [0; 2000];
+            assert_eq!(2000, filter.process(&samples, &mut output));
+
+            let a = i128::from(filter.coefficient);
+            let (mut x1, mut y1) = (i128::from(samples[0]), 0);
+            for (x, expected) in samples.iter().zip(&output) {
+                let x = i128::from(*x);
+                y1 = (x - x1) * 256 + round_div_exact(a * y1, 32768);
+                x1 = x;
+                let y = round_div_exact(y1, 256);
+                assert_eq!(y, i128::from(*expected), "{:?}", rate);
+            }
+        }
+    }
+
+    /// The band-pass filter computes `y[n] = b0·x[n] + b1·x[n-1] +
+    /// b2·x[n-2] - a1·y[n-1] - a2·y[n-2]` with the Q30 coefficients,
+    /// keeping `y` with 8 fractional bits.
+    #[test]
+    fn biquad_is_bit_exact() {
+        for rate in RATES {
+            let samples = input(f32::from(rate.samples_per_second()));
+            let mut filter = Biquad::band_pass(rate);
+            let mut output = [0; 2000];
+            assert_eq!(2000, filter.process(&samples, &mut output));
+
+            let b = filter.b.map(i128::from);
+            let a = filter.a.map(i128::from);
+            let x0 = i128::from(samples[0]);
+            let (mut x, mut y) = ([x0, x0], [0, 0]);
+            for (input, expected) in samples.iter().zip(&output) {
+                let input = i128::from(*input);
+                let feedforward = b[0] * input + b[1] * x[0] + b[2] * x[1];
+                let state = round_div_exact(feedforward * 256 - a[0] * y[0] - a[1] * y[1], 1 << 30);
+                x = [input, x[0]];
+                y = [state, y[0]];
+                assert_eq!(
+                    round_div_exact(state, 256),
+                    i128::from(*expected),
+                    "{:?}",
+                    rate
+                );
+            }
+        }
+    }
+
+    /// The DC blocker stays within 5 (0.25% of the pulse amplitude) of an
+    /// `f64` filter with the exact coefficient. Above 0.5, the error comes
+    /// from the Q15 rounding of the coefficient at the high sampling rates.
+    #[test]
+    fn dc_blocker_matches_float_reference() {
+        for rate in RATES {
+            let samples = input(f32::from(rate.samples_per_second()));
+            let mut filter = DcBlocker::for_sampling_rate(rate);
+            let mut output = [0; 2000];
+            assert_eq!(2000, filter.process(&samples, &mut output));
+
+            let a = dc_blocker_coefficient(rate);
+            let mut reference = [0.0; 2000];
+            let (mut x1, mut y1) = (f64::from(samples[0]), 0.0);
+            for (x, y) in samples.iter().zip(reference.iter_mut()) {
+                let x = f64::from(*x);
+                *y = x - x1 + a * y1;
+                x1 = x;
+                y1 = *y;
+            }
+            let error = max_error(&output, &reference);
+            assert!(error <= 5.0, "{:?} {}", rate, error);
+        }
+    }
+
+    /// The band-pass filter stays within 5 (0.25% of the pulse amplitude)
+    /// of an `f64` filter with the exact coefficients. Above 0.5, the error
+    /// comes from the rounding of the state, which builds up at the high
+    /// sampling rates as the poles get closer to 1.
+    #[test]
+    fn biquad_matches_float_reference() {
+        for rate in RATES {
+            let samples = input(f32::from(rate.samples_per_second()));
+            let mut filter = Biquad::band_pass(rate);
+            let mut output = [0; 2000];
+            assert_eq!(2000, filter.process(&samples, &mut output));
+
+            let (b, a) = band_pass_coefficients(rate);
+            let mut reference = [0.0; 2000];
+            let x0 = f64::from(samples[0]);
+            let (mut x, mut y) = ([x0, x0], [0.0, 0.0]);
+            for (input, output) in samples.iter().zip(reference.iter_mut()) {
+                let input = f64::from(*input);
+                *output = b[0] * input + b[1] * x[0] + b[2] * x[1] - a[0] * y[0] - a[1] * y[1];
+                x = [input, x[0]];
+                y = [*output, y[0]];
+            }
+            let error = max_error(&output, &reference);
+            assert!(error <= 5.0, "{:?} {}", rate, error);
+        }
+    }
+
+    #[test]
+    fn band_pass_is_tuned_for_each_sampling_rate() {
+        for rate in RATES {
+            let sample_rate = f32::from(rate.samples_per_second());
+            let mut filter = Biquad::band_pass(rate);
+            // Center frequency: sqrt(0.5 Hz * 5 Hz)
+            let frequency = libm::sqrtf(2.5);
+            let settle = (10.0 * sample_rate) as usize;
+            let mut peak = 0;
+            for i in 0..settle + (2.0 * sample_rate) as usize {
+                let t = i as f32 / sample_rate;
+                let input = 100_000 + (10_000.0 * libm::sinf(2.0 * PI * frequency * t)) as i32;
+                let output = filter.step(input);
+                if i > settle {
+                    peak = peak.max(output.abs());
+                }
+            }
+            assert!((9_900..=10_100).contains(&peak), "{:?} {}", rate, peak);
+        }
+    }
+
+    #[test]
+    fn dc_blocker_removes_dc() {
+        for rate in RATES {
+            let mut filter = DcBlocker::for_sampling_rate(rate);
+            let mut output = [0; 100];
+            filter.process(&[150_000_u32; 100], &mut output);
+            assert_eq!([0; 100], output);
+        }
+    }
+
+    #[test]
+    fn dc_blocker_settles_after_step() {
+        for rate in RATES {
+            let mut filter = DcBlocker::for_sampling_rate(rate);
+            filter.step(150_000);
+            let mut output = 0;
+            for _ in 0..(4 * rate.samples_per_second()) {
+                output = filter.step(151_000);
+            }
+            assert!(output.abs() <= 2, "{:?} {}", rate, output);
+        }
+    }
+
+    #[test]
+    fn moving_average_matches_float_reference() {
+        let samples = input(100.0);
+        let mut filter = MovingAverage::<5>::new();
+        let mut output = [0; 2000];
+        assert_eq!(2000, filter.process(&samples, &mut output));
+        for (i, value) in output.iter().enumerate() {
+            let window = &samples[i.saturating_sub(4)..=i];
+            let sum: f64 = window.iter().map(|s| f64::from(*s)).sum();
+            assert_eq!(round(sum / window.len() as f64), f64::from(*value));
+        }
+    }
+
+    #[test]
+    fn median_matches_float_reference() {
+        let samples = input(100.0);
+        let mut filter = MedianFilter::<4>::new();
+        let mut output = [0; 2000];
+        assert_eq!(2000, filter.process(&samples, &mut output));
+        for (i, value) in output.iter().enumerate() {
+            let mut window = [0.0; 4];
+            let len = (i + 1).min(4);
+            for (w, s) in window.iter_mut().zip(&samples[i + 1 - len..=i]) {
+                *w = f64::from(*s);
+            }
+            let window = &mut window[..len];
+            window.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+            let median = if len % 2 == 1 {
+                window[len / 2]
+            } else {
+                round(0.5 * (window[len / 2 - 1] + window[len / 2]))
+            };
+            assert_eq!(median, f64::from(*value));
+        }
+    }
+
+    #[test]
+    fn median_rejects_spikes() {
+        let mut filter = MedianFilter::<3>::new();
+        let mut output = [0; 6];
+        filter.process(&[10, 10, 1000, 10, 12, 11], &mut output);
+        assert_eq!([10, 10, 10, 10, 12, 11], output);
+    }
+
+    #[test]
+    fn decimator_matches_float_reference() {
+        let samples = input(400.0);
+        let mut filter = Decimator::<8>::new();
+        let mut output = [0; 250];
+        let mut count = filter.process(&samples[..1001], &mut output);
+        count += filter.process(&samples[1001..], &mut output[count..]);
+        assert_eq!(250, count);
+        for (block, value) in samples.chunks(8).zip(&output) {
+            let sum: f64 = block.iter().map(|s| f64::from(*s)).sum();
+            assert_eq!(round(sum / 8.0), f64::from(*value));
+        }
+    }
+
+    #[test]
+    fn rounds_half_up() {
+        assert_eq!(3, round_div(5, 2));
+        assert_eq!(-2, round_div(-5, 2));
+        assert_eq!(2, round_shift(3, 1));
+        assert_eq!(-1, round_shift(-3, 1));
+    }
+}
@@ -0,0 +1,377 @@
+//! Heart rate variability (HRV) metrics from inter-beat intervals.
+//!
+//! The beat times are located with sub-sample resolution by fitting a
+//! parabola through the detrended samples around each detected beat. The
+//! sample times are derived from the configured sampling rate and sample
+//! averaging.
+//!
+//! Intervals outside of the physiological range (30 to 200 bpm) or which
+//! differ more than 20% from the median interval are considered ectopic
+//! beats or artifacts and are excluded from the statistics. Successive
+//! differences are only taken between two consecutive accepted intervals.
+//!
+//! ```no_run
+//! use linux_embedded_hal::I2cdev;
+//! use max3010x::{
+//!     signal::{
+//!         beat::MAX_BEATS,
+//!         hrv::{inter_beat_intervals, BeatInterval, BeatTimer, HrvMetrics},
+//!     },
+//!     Max3010x,
+//! };
+//!
+//! let dev = I2cdev::new("/dev/i2c-1").unwrap();
+//! let mut sensor = Max3010x::new_max30102(dev).into_heart_rate().unwrap();
+//! let timer = BeatTimer::for_heart_rate(&sensor);
+//! // One minute at the default 50 samples per second. Short-term HRV
+//! // metrics are usually calculated over one to five minutes.
+//! let mut window = [0; 60 * 50];
+//! let mut count = 0;
+//! while count < window.len() {
+//!     count += usize::from(sensor.read_fifo(&mut window[count..]).unwrap());
+//! }
+//! let mut times_ms = [0.0; MAX_BEATS];
+//! let beat_count = timer.beat_times_ms(&window, &mut times_ms);
+//! let mut intervals = [BeatInterval::default(); MAX_BEATS];
+//! let interval_count = inter_beat_intervals(&times_ms[..beat_count], &mut intervals);
+//! if let Some(hrv) = HrvMetrics::from_intervals(&intervals[..interval_count]) {
+//!     println!("RMSSD: {} ms", hrv.rmssd_ms);
+//! }
+//! ```
+
+use crate::{
+    marker,
+    signal::{
+        beat::{find_channel_beats, refine_beat, MAX_BEATS},
+        Channel,
+    },
+    Max3010x, SampleAveraging, SamplingRate,
+};
+
+/// Shortest accepted interval in ms (200 bpm).
+const MIN_INTERVAL_MS: f32 = 300.0;
+/// Longest accepted interval in ms (30 bpm).
+const MAX_INTERVAL_MS: f32 = 2000.0;
+/// Maximum relative deviation of an accepted interval from the median.
+const MAX_MEDIAN_DEVIATION: f32 = 0.2;
+/// Successive difference threshold for pNN50 in ms.
+const NN50_MS: f32 = 50.0;
+
+/// Beat time locator for the samples read in a given mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "BeatTimerFields")
+)]
+pub struct BeatTimer {
+    sample_period_ms: f32,
+    channels: u8,
+    channel: u8,
+}
+
+/// Unvalidated contents of a deserialized [`BeatTimer`].
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct BeatTimerFields {
+    sample_period_ms: f32,
+    channels: u8,
+    channel: u8,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<BeatTimerFields> for BeatTimer {
+    type Error = &'static str;
+
+    fn try_from(fields: BeatTimerFields) -> Result<Self, Self::Error> {
+        if !fields.sample_period_ms.is_finite() || fields.sample_period_ms <= 0.0 {
+            return Err("invalid sample period");
+        }
+        if fields.channel >= fields.channels {
+            return Err("channel out of range");
+        }
+        Ok(BeatTimer {
+            sample_period_ms: fields.sample_period_ms,
+            channels: fields.channels,
+            channel: fields.channel,
+        })
+    }
+}
+
+impl BeatTimer {
+    /// Create a beat timer for samples of a single channel acquired with
+    /// the given sampling rate and sample averaging.
+    pub fn new(sampling_rate: SamplingRate, sample_averaging: SampleAveraging) -> Self {
+        BeatTimer {
+            sample_period_ms: 1000.0 * f32::from(sample_averaging.sample_count())
+                / f32::from(sampling_rate.samples_per_second()),
+            channels: 1,
+            channel: 0,
+        }
+    }
+
+    /// Create a beat timer for the samples read in heart-rate mode.
+    pub fn for_heart_rate<I2C, IC>(sensor: &Max3010x<I2C, IC, marker::mode::HeartRate>) -> Self {
+        Self::new(sensor.get_sampling_rate(), sensor.get_sample_averaging())
+    }
+
+    /// Create a beat timer for the IR channel of the samples read in
+    /// oximeter mode.
+    pub fn for_oximeter<I2C, IC>(sensor: &Max3010x<I2C, IC, marker::mode::Oximeter>) -> Self {
+        BeatTimer {
+            channels: 2,
+            channel: 1,
+            ..Self::new(sensor.get_sampling_rate(), sensor.get_sample_averaging())
+        }
+    }
+
+    /// Locate the beats in a window of samples as returned by
+    /// [`read_fifo()`](crate::Max3010x::read_fifo).
+    ///
+    /// The beat times in ms relative to the first sample of the window are
+    /// stored in `times_ms` and the number of beats found is returned.
+    pub fn beat_times_ms(&self, samples: &[u32], times_ms: &mut [f32]) -> usize {
+        let channel = Channel::new(
+            samples,
+            usize::from(self.channels),
+            usize::from(self.channel),
+        );
+        let mut beats = [0; MAX_BEATS];
+        let capacity = times_ms.len().min(MAX_BEATS);
+        let sample_rate = 1000.0 / self.sample_period_ms;
+        let count = find_channel_beats(channel, sample_rate, &mut beats[..capacity]);
+        let beats = &beats[..count];
+        for (time, beat) in times_ms.iter_mut().zip(beats) {
+            *time = refine_beat(channel, sample_rate, *beat) * self.sample_period_ms;
+        }
+        count
+    }
+}
+
+/// Interval between two consecutive beats.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BeatInterval {
+    /// Duration in ms.
+    pub duration_ms: f32,
+    /// The interval is a normal-to-normal interval and is used for the
+    /// statistics. This is `false` for ectopic beats and artifacts.
+    pub accepted: bool,
+}
+
+/// Calculate the intervals between consecutive beat times in ms and
+/// classify them.
+///
+/// At most `MAX_BEATS - 1` intervals are considered. The intervals are
+/// stored in `intervals` and their number is returned.
+pub fn inter_beat_intervals(beat_times_ms: &[f32], intervals: &mut [BeatInterval]) -> usize {
+    let times = &beat_times_ms[..beat_times_ms.len().min(MAX_BEATS)];
+    let count = times.len().saturating_sub(1).min(intervals.len());
+    let mut sorted = [0.0_f32; MAX_BEATS];
+    for (i, pair) in times.windows(2).take(count).enumerate() {
+        intervals[i].duration_ms = pair[1] - pair[0];
+        sorted[i] = intervals[i].duration_ms;
+    }
+    let sorted = &mut sorted[..count];
+    sorted.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(core::cmp::Ordering::Equal));
+    let median = match count {
+        0 => return 0,
+        _ if count % 2 == 1 => sorted[count / 2],
+        _ => 0.5 * (sorted[count / 2 - 1] + sorted[count / 2]),
+    };
+    for interval in &mut intervals[..count] {
+        let duration = interval.duration_ms;
+        interval.accepted = (MIN_INTERVAL_MS..=MAX_INTERVAL_MS).contains(&duration)
+            && libm::fabsf(duration - median) <= MAX_MEDIAN_DEVIATION * median;
+    }
+    count
+}
+
+/// Heart rate variability statistics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HrvMetrics {
+    /// Mean of the accepted intervals in ms.
+    pub mean_nn_ms: f32,
+    /// Standard deviation of the accepted intervals in ms.
+    pub sdnn_ms: f32,
+    /// Root mean square of the successive differences in ms.
+    pub rmssd_ms: f32,
+    /// Percentage of successive differences larger than 50 ms.
+    pub pnn50: f32,
+    /// Poincaré plot standard deviation perpendicular to the line of
+    /// identity in ms (short-term variability).
+    pub sd1_ms: f32,
+    /// Poincaré plot standard deviation along the line of identity in ms
+    /// (long-term variability).
+    pub sd2_ms: f32,
+    /// Number of accepted intervals, saturating at 255.
+    pub interval_count: u8,
+    /// Number of rejected intervals, saturating at 255.
+    pub rejected_count: u8,
+}
+
+impl HrvMetrics {
+    /// Calculate the statistics of the accepted intervals.
+    ///
+    /// Returns `None` if there are fewer than three accepted intervals or no
+    /// two consecutive ones.
+    pub fn from_intervals(intervals: &[BeatInterval]) -> Option<Self> {
+        let accepted = || intervals.iter().filter(|i| i.accepted);
+        let count = accepted().count();
+        if count < 3 {
+            return None;
+        }
+        let mean = accepted().map(|i| i.duration_ms).sum::<f32>() / count as f32;
+        let variance = accepted()
+            .map(|i| (i.duration_ms - mean) * (i.duration_ms - mean))
+            .sum::<f32>()
+            / (count - 1) as f32;
+
+        let differences = || {
+            intervals
+                .windows(2)
+                .filter(|pair| pair[0].accepted && pair[1].accepted)
+                .map(|pair| pair[1].duration_ms - pair[0].duration_ms)
+        };
+        let difference_count = differences().count();
+        if difference_count == 0 {
+            return None;
+        }
+        let n = difference_count as f32;
+        let mean_square = differences().map(|d| d * d).sum::<f32>() / n;
+        let mean_difference = differences().sum::<f32>() / n;
+        let difference_variance = if difference_count > 1 {
+            differences()
+                .map(|d| (d - mean_difference) * (d - mean_difference))
+                .sum::<f32>()
+                / (n - 1.0)
+        } else {
+            0.0
+        };
+        let nn50 = differences().filter(|d| libm::fabsf(*d) > NN50_MS).count();
+
+        Some(HrvMetrics {
+            mean_nn_ms: mean,
+            sdnn_ms: libm::sqrtf(variance),
+            rmssd_ms: libm::sqrtf(mean_square),
+            pnn50: 100.0 * nn50 as f32 / n,
+            sd1_ms: libm::sqrtf(0.5 * difference_variance),
+            sd2_ms: libm::sqrtf((2.0 * variance - 0.5 * difference_variance).max(0.0)),
+            interval_count: u8::try_from(count).unwrap_or(u8::MAX),
+            rejected_count: u8::try_from(intervals.len() - count).unwrap_or(u8::MAX),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal::beat::tests::ppg;
+
+    fn intervals(times_ms: &[f32]) -> ([BeatInterval; MAX_BEATS], usize) {
+        let mut intervals = [BeatInterval::default(); MAX_BEATS];
+        let count = inter_beat_intervals(times_ms, &mut intervals);
+        (intervals, count)
+    }
+
+    #[test]
+    fn locates_beats_between_samples() {
+        // 72 bpm at 50 Hz: the beats fall between samples.
+        let period = 50.0 * 60.0 / 72.0;
+        let mut samples = [0; 500];
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let phase = 2.0 * core::f32::consts::PI * i as f32 / period;
+            *sample = (100_000.0 - 500.0 * (1.0 - libm::cosf(phase))) as u32;
+        }
+        let timer = BeatTimer::new(SamplingRate::Sps50, SampleAveraging::Sa1);
+        let mut times = [0.0; MAX_BEATS];
+        let count = timer.beat_times_ms(&samples, &mut times);
+        assert_eq!(12, count);
+        for (k, time) in times[..count].iter().enumerate() {
+            let expected = (0.5 + k as f32) * period * 20.0;
+            assert!(libm::fabsf(time - expected) < 2.0, "{} {}", time, expected);
+        }
+        let (intervals, count) = intervals(&times[..count]);
+        for interval in &intervals[..count] {
+            assert!(libm::fabsf(interval.duration_ms - 833.3) < 2.0);
+            assert!(interval.accepted);
+        }
+    }
+
+    #[test]
+    fn sample_period_includes_averaging() {
+        // 100 Hz with 2 samples averaged is stored as 50 Hz.
+        let mut samples = [0; 500];
+        let samples = ppg(&mut samples, 50.0, 60.0, 100_000.0, 1000.0);
+        let timer = BeatTimer::new(SamplingRate::Sps100, SampleAveraging::Sa2);
+        let mut times = [0.0; 3];
+        assert_eq!(3, timer.beat_times_ms(samples, &mut times));
+        for (time, expected) in times.iter().zip([200.0, 1200.0, 2200.0]) {
+            assert!(libm::fabsf(time - expected) < 10.0, "{} {}", time, expected);
+        }
+    }
+
+    #[test]
+    fn rejects_ectopic_intervals() {
+        let times = [0.0, 800.0, 1600.0, 1900.0, 2400.0, 3200.0, 4000.0];
+        let (intervals, count) = intervals(&times);
+        assert_eq!(6, count);
+        let mut accepted = [false; 6];
+        for (accepted, interval) in accepted.iter_mut().zip(&intervals) {
+            *accepted = interval.accepted;
+        }
+        assert_eq!([true, true, false, false, true, true], accepted);
+    }
+
+    #[test]
+    fn calculates_metrics() {
+        let times = [0.0, 800.0, 1660.0, 2460.0, 3320.0, 4120.0];
+        let (intervals, count) = intervals(&times);
+        let hrv = HrvMetrics::from_intervals(&intervals[..count]).unwrap();
+        assert_eq!(5, hrv.interval_count);
+        assert_eq!(0, hrv.rejected_count);
+        assert!(libm::fabsf(hrv.mean_nn_ms - 824.0) < 0.01);
+        assert!(libm::fabsf(hrv.sdnn_ms - 32.863) < 0.01, "{}", hrv.sdnn_ms);
+        assert!(libm::fabsf(hrv.rmssd_ms - 60.0) < 0.01);
+        assert_eq!(100.0, hrv.pnn50);
+        // SD1 = sqrt(0.5 * var(60, -60, 60, -60))
+        assert!(libm::fabsf(hrv.sd1_ms - 48.990) < 0.01, "{}", hrv.sd1_ms);
+        assert!(hrv.sd2_ms.is_finite());
+    }
+
+    #[test]
+    fn excludes_rejected_intervals_from_differences() {
+        let times = [0.0, 800.0, 1600.0, 1900.0, 2400.0, 3200.0, 4000.0];
+        let (intervals, count) = intervals(&times);
+        let hrv = HrvMetrics::from_intervals(&intervals[..count]).unwrap();
+        assert_eq!(4, hrv.interval_count);
+        assert_eq!(2, hrv.rejected_count);
+        assert_eq!(800.0, hrv.mean_nn_ms);
+        assert_eq!(0.0, hrv.rmssd_ms);
+        assert_eq!(0.0, hrv.pnn50);
+    }
+
+    #[test]
+    fn saturates_interval_counts() {
+        let mut intervals = [BeatInterval {
+            duration_ms: 800.0,
+            accepted: true,
+        }; 600];
+        for interval in &mut intervals[300..] {
+            interval.accepted = false;
+        }
+        let hrv = HrvMetrics::from_intervals(&intervals).unwrap();
+        assert_eq!(255, hrv.interval_count);
+        assert_eq!(255, hrv.rejected_count);
+    }
+
+    #[test]
+    fn needs_enough_intervals() {
+        let (intervals, count) = intervals(&[0.0, 800.0, 1600.0]);
+        assert_eq!(None, HrvMetrics::from_intervals(&intervals[..count]));
+    }
+}
@@ -0,0 +1,315 @@
+//! Motion artifact rejection with an accelerometer reference.
+//!
+//! The accelerometer samples are first resampled to the effective sampling
+//! rate of the PPG with [`AccelResampler`]. The [`NlmsFilter`] then adapts
+//! to the component of the PPG which is correlated with the motion and
+//! removes it. The cleaned samples can then be used for the heart rate
+//! estimation as usual.
+//!
+//! The accelerometer samples are given as `[x, y, z]` in any unit so that
+//! this does not depend on a specific accelerometer driver.
+//!
+//! ```no_run
+//! use linux_embedded_hal::I2cdev;
+//! use max3010x::{
+//!     signal::{
+//!         beat::{find_beats, MAX_BEATS},
+//!         motion::{AccelResampler, NlmsFilter},
+//!     },
+//!     Max3010x,
+//! };
+//! # fn read_accelerometer(_: &mut [[f32; 3]]) -> usize { 0 }
+//!
+//! let dev = I2cdev::new("/dev/i2c-1").unwrap();
+//! let mut sensor = Max3010x::new_max30102(dev).into_heart_rate().unwrap();
+//! let mut resampler = AccelResampler::for_sensor(25.0, &sensor);
+//! let mut filter = NlmsFilter::<8>::new(0.05);
+//!
+//! let mut ppg = [0; 32];
+//! let ppg_count = usize::from(sensor.read_fifo(&mut ppg).unwrap());
+//! let mut accel = [[0.0; 3]; 32];
+//! let accel_count = read_accelerometer(&mut accel);
+//! let mut reference = [[0.0; 3]; 64];
+//! let reference_count = resampler.push(&accel[..accel_count], &mut reference);
+//! let mut cleaned = [0; 32];
+//! let count = filter.process(&ppg[..ppg_count], &reference[..reference_count], &mut cleaned);
+//! let mut beats = [0; MAX_BEATS];
+//! let _beat_count = find_beats(&cleaned[..count], sensor.get_effective_sampling_rate(), &mut beats);
+//! ```
+
+use crate::Max3010x;
+
+/// Weight of the new sample in the DC level trackers.
+const DC_TRACKING: f32 = 1.0 / 64.0;
+
+/// Streaming linear resampler for accelerometer samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AccelResampler {
+    /// Input samples per output sample.
+    step: f32,
+    /// Position of the next output sample relative to the last input sample.
+    position: f32,
+    last: Option<[f32; 3]>,
+}
+
+impl AccelResampler {
+    /// Create a resampler from `input_rate` to `output_rate`, both in
+    /// samples per second.
+    pub fn new(input_rate: f32, output_rate: f32) -> Self {
+        AccelResampler {
+            step: input_rate / output_rate,
+            position: 0.0,
+            last: None,
+        }
+    }
+
+    /// Create a resampler from `input_rate` to the effective sampling rate
+    /// of the sensor.
+    ///
+    /// The resampler must be recreated if the sampling rate or the sample
+    /// averaging of the sensor is changed.
+    pub fn for_sensor<I2C, IC, MODE>(input_rate: f32, sensor: &Max3010x<I2C, IC, MODE>) -> Self {
+        Self::new(input_rate, sensor.get_effective_sampling_rate())
+    }
+
+    /// Resample the next accelerometer samples.
+    ///
+    /// The resampled values are stored in `output` and their number is
+    /// returned. `output` should hold at least
+    /// `input.len() * output_rate / input_rate + 1` values. Otherwise the
+    /// remaining values are dropped.
+    pub fn push(&mut self, input: &[[f32; 3]], output: &mut [[f32; 3]]) -> usize {
+        let offset = usize::from(self.last.is_some());
+        let len = input.len() + offset;
+        if len == 0 {
+            return 0;
+        }
+        let last = self.last;
+        let get = |i: usize| match (i, last) {
+            (0, Some(last)) => last,
+            _ => input[i - offset],
+        };
+        let mut count = 0;
+        while count < output.len() && (self.position as usize) + 1 < len {
+            let i = self.position as usize;
+            let fraction = self.position - i as f32;
+            let (a, b) = (get(i), get(i + 1));
+            for axis in 0..3 {
+                output[count][axis] = a[axis] + fraction * (b[axis] - a[axis]);
+            }
+            count += 1;
+            self.position += self.step;
+        }
+        self.position = (self.position - (len - 1) as f32).max(0.0);
+        self.last = Some(get(len - 1));
+        count
+    }
+
+    /// Forget the previous samples.
+    pub fn reset(&mut self) {
+        self.position = 0.0;
+        self.last = None;
+    }
+}
+
+/// Normalized least mean squares (NLMS) adaptive filter removing the
+/// motion-correlated component from the PPG.
+///
+/// The filter uses the last `TAPS` samples of each accelerometer axis as
+/// reference. A larger step size adapts faster to changes in the motion
+/// but leaves more residual noise. Values between 0.01 and 0.1 work well.
+///
+/// `TAPS` must be at least 1, otherwise using the filter fails to compile:
+///
+/// ```compile_fail
+/// let filter = max3010x::signal::motion::NlmsFilter::<0>::new(0.05);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NlmsFilter<const TAPS: usize> {
+    step_size: f32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_array::nested"))]
+    weights: [[f32; TAPS]; 3],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_array::nested"))]
+    history: [[f32; TAPS]; 3],
+    ppg_dc: Option<f32>,
+    accel_dc: Option<[f32; 3]>,
+}
+
+impl<const TAPS: usize> NlmsFilter<TAPS> {
+    const HAS_TAPS: () = assert!(TAPS > 0, "NlmsFilter needs at least one tap");
+
+    /// Create a filter with the given adaptation step size.
+    pub fn new(step_size: f32) -> Self {
+        let () = Self::HAS_TAPS;
+        NlmsFilter {
+            step_size,
+            weights: [[0.0; TAPS]; 3],
+            history: [[0.0; TAPS]; 3],
+            ppg_dc: None,
+            accel_dc: None,
+        }
+    }
+
+    /// Filter one zero-mean PPG value with the corresponding zero-mean
+    /// accelerometer sample and return the cleaned value.
+    pub fn filter(&mut self, ppg: f32, accel: [f32; 3]) -> f32 {
+        let () = Self::HAS_TAPS;
+        let mut estimate = 0.0;
+        let mut power = 0.0;
+        let axes = self.history.iter_mut().zip(&self.weights).zip(accel);
+        for ((history, weights), accel) in axes {
+            history.copy_within(..TAPS - 1, 1);
+            history[0] = accel;
+            for (weight, value) in weights.iter().zip(history.iter()) {
+                estimate += weight * value;
+                power += value * value;
+            }
+        }
+        let error = ppg - estimate;
+        let gain = self.step_size * error / (power + f32::EPSILON);
+        for (weights, history) in self.weights.iter_mut().zip(self.history.iter()) {
+            for (weight, value) in weights.iter_mut().zip(history.iter()) {
+                *weight += gain * value;
+            }
+        }
+        error
+    }
+
+    /// Remove the motion-correlated component from samples of one channel
+    /// as returned by [`read_fifo()`](crate::Max3010x::read_fifo).
+    ///
+    /// `accel` contains the accelerometer samples resampled to the same
+    /// rate. The DC level of both is tracked and removed before filtering
+    /// and added back to the cleaned samples, which are stored in `output`.
+    /// The number of samples processed is returned.
+    pub fn process(&mut self, samples: &[u32], accel: &[[f32; 3]], output: &mut [u32]) -> usize {
+        let mut count = 0;
+        for ((sample, accel), output) in samples.iter().zip(accel).zip(output.iter_mut()) {
+            let value = *sample as f32;
+            let ppg_dc = track(&mut self.ppg_dc, value);
+            let mut reference = [0.0; 3];
+            let accel_dc = self.accel_dc.get_or_insert(*accel);
+            for axis in 0..3 {
+                accel_dc[axis] += DC_TRACKING * (accel[axis] - accel_dc[axis]);
+                reference[axis] = accel[axis] - accel_dc[axis];
+            }
+            let cleaned = self.filter(value - ppg_dc, reference) + ppg_dc;
+            *output = if cleaned > 0.0 { cleaned as u32 } else { 0 };
+            count += 1;
+        }
+        count
+    }
+
+    /// Reset the filter weights, history and DC levels.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.step_size);
+    }
+}
+
+fn track(dc: &mut Option<f32>, value: f32) -> f32 {
+    let dc = dc.get_or_insert(value);
+    *dc += DC_TRACKING * (value - *dc);
+    *dc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f32::consts::PI;
+
+    #[test]
+    fn resamples_linearly() {
+        let input: [[f32; 3]; 5] = [
+            [0.0, 0.0, 10.0],
+            [1.0, -2.0, 10.0],
+            [2.0, -4.0, 10.0],
+            [3.0, -6.0, 10.0],
+            [4.0, -8.0, 10.0],
+        ];
+        let mut output = [[0.0; 3]; 16];
+        let mut resampler = AccelResampler::new(25.0, 50.0);
+        let count = resampler.push(&input, &mut output);
+        assert_eq!(8, count);
+        for (i, value) in output[..count].iter().enumerate() {
+            assert_eq!([0.5 * i as f32, -(i as f32), 10.0], *value);
+        }
+    }
+
+    #[test]
+    fn resampling_is_continuous_across_pushes() {
+        let mut input = [[0.0; 3]; 40];
+        for (i, value) in input.iter_mut().enumerate() {
+            *value = [i as f32, 0.0, 0.0];
+        }
+        let mut whole = [[0.0; 3]; 64];
+        let whole_count = AccelResampler::new(40.0, 50.0).push(&input, &mut whole);
+
+        let mut resampler = AccelResampler::new(40.0, 50.0);
+        let mut chunked = [[0.0; 3]; 64];
+        let mut count = 0;
+        for chunk in input.chunks(7) {
+            count += resampler.push(chunk, &mut chunked[count..]);
+        }
+        assert_eq!(whole_count, count);
+        for (a, b) in whole[..count].iter().zip(&chunked[..count]) {
+            assert!(libm::fabsf(a[0] - b[0]) < 1e-4, "{:?} {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn removes_motion_correlated_component() {
+        let sample_rate = 50.0;
+        let mut samples = [0; 1500];
+        let mut accel = [[0.0; 3]; 1500];
+        let mut motion = [0.0; 1500];
+        for i in 0..samples.len() {
+            let t = i as f32 / sample_rate;
+            let x = 100.0 * libm::sinf(2.0 * PI * 2.1 * t);
+            let z = 50.0 * libm::sinf(2.0 * PI * 0.7 * t + 1.0);
+            accel[i] = [x, 0.0, 1000.0 + z];
+            let previous_x = 100.0 * libm::sinf(2.0 * PI * 2.1 * (t - 1.0 / sample_rate));
+            motion[i] = 8.0 * x + 4.0 * previous_x - 6.0 * z;
+            let heart = 200.0 * libm::sinf(2.0 * PI * 1.2 * t);
+            samples[i] = (50_000.0 + heart + motion[i]) as u32;
+        }
+        let mut filter = NlmsFilter::<4>::new(0.1);
+        let mut cleaned = [0; 1500];
+        assert_eq!(1500, filter.process(&samples, &accel, &mut cleaned));
+
+        // Residual motion after convergence.
+        let residual_power = |values: &[u32]| {
+            let mut power = 0.0;
+            for (i, value) in values.iter().enumerate().skip(1000) {
+                let t = i as f32 / sample_rate;
+                let heart = 200.0 * libm::sinf(2.0 * PI * 1.2 * t);
+                let residual = *value as f32 - 50_000.0 - heart;
+                power += residual * residual;
+            }
+            power
+        };
+        let before = residual_power(&samples);
+        let after = residual_power(&cleaned);
+        assert!(after < 0.05 * before, "{} {}", after, before);
+    }
+
+    #[test]
+    fn passes_signal_without_motion() {
+        let mut filter = NlmsFilter::<4>::new(0.1);
+        let samples = [50_000; 100];
+        let accel = [[0.0, 0.0, 1000.0]; 100];
+        let mut cleaned = [0; 100];
+        filter.process(&samples, &accel, &mut cleaned);
+        assert_eq!(samples, cleaned);
+    }
+
+    #[test]
+    fn processes_up_to_shortest_input() {
+        let mut filter = NlmsFilter::<4>::new(0.1);
+        let mut cleaned = [0; 10];
+        assert_eq!(3, filter.process(&[1; 5], &[[0.0; 3]; 3], &mut cleaned));
+    }
+}
@@ -0,0 +1,219 @@
+//! Perfusion index calculation.
+//!
+//! The perfusion index (PI) is the ratio of the pulsatile (AC) to the
+//! non-pulsatile (DC) light received, in %. It is calculated for every beat
+//! as the peak-to-peak amplitude divided by the mean of the samples between
+//! two consecutive beats.
+//!
+//! ```no_run
+//! use linux_embedded_hal::I2cdev;
+//! use max3010x::{signal::perfusion::PerfusionIndexCalculator, Max3010x};
+//!
+//! let dev = I2cdev::new("/dev/i2c-1").unwrap();
+//! let mut sensor = Max3010x::new_max30102(dev).into_oximeter().unwrap();
+//! let calculator = PerfusionIndexCalculator::for_oximeter(&sensor);
+//! let mut window = [0; 32 * 2];
+//! let count = usize::from(sensor.read_fifo(&mut window).unwrap());
+//! let mut per_beat = [0.0; 8];
+//! let pi = calculator.calculate(&window[..count * 2], &mut per_beat);
+//! let _beats = &per_beat[..usize::from(pi.beat_count).min(per_beat.len())];
+//! ```
+
+use crate::{
+    marker,
+    signal::{
+        beat::{find_channel_beats, MAX_BEATS},
+        Channel,
+    },
+    Max3010x,
+};
+
+/// Perfusion index of a window of samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PerfusionIndex {
+    /// Average perfusion index of all beats in the window in %.
+    ///
+    /// This is 0 if fewer than two beats were detected.
+    pub average: f32,
+    /// Number of beats for which the perfusion index was calculated.
+    pub beat_count: u8,
+}
+
+/// Perfusion index calculator for the samples read in a given mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "PerfusionIndexCalculatorFields")
+)]
+pub struct PerfusionIndexCalculator {
+    sample_rate: f32,
+    channels: u8,
+    channel: u8,
+}
+
+/// Unvalidated contents of a deserialized [`PerfusionIndexCalculator`].
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct PerfusionIndexCalculatorFields {
+    sample_rate: f32,
+    channels: u8,
+    channel: u8,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<PerfusionIndexCalculatorFields> for PerfusionIndexCalculator {
+    type Error = &'static str;
+
+    fn try_from(fields: PerfusionIndexCalculatorFields) -> Result<Self, Self::Error> {
+        if !fields.sample_rate.is_finite() || fields.sample_rate <= 0.0 {
+            return Err("invalid sample rate");
+        }
+        if fields.channel >= fields.channels {
+            return Err("channel out of range");
+        }
+        Ok(PerfusionIndexCalculator {
+            sample_rate: fields.sample_rate,
+            channels: fields.channels,
+            channel: fields.channel,
+        })
+    }
+}
+
+impl PerfusionIndexCalculator {
+    /// Create a calculator for samples of a single channel at the given
+    /// effective sampling rate.
+    pub fn new(sample_rate: f32) -> Self {
+        PerfusionIndexCalculator {
+            sample_rate,
+            channels: 1,
+            channel: 0,
+        }
+    }
+
+    /// Create a calculator for the samples read in heart-rate mode.
+    ///
+    /// Only LED1 is active in this mode (red in the MAX30102).
+    pub fn for_heart_rate<I2C, IC>(sensor: &Max3010x<I2C, IC, marker::mode::HeartRate>) -> Self {
+        Self::new(sensor.get_effective_sampling_rate())
+    }
+
+    /// Create a calculator for the IR channel of the samples read in
+    /// oximeter mode.
+    pub fn for_oximeter<I2C, IC>(sensor: &Max3010x<I2C, IC, marker::mode::Oximeter>) -> Self {
+        PerfusionIndexCalculator {
+            sample_rate: sensor.get_effective_sampling_rate(),
+            channels: 2,
+            channel: 1,
+        }
+    }
+
+    /// Calculate the perfusion index of a window of samples as returned by
+    /// [`read_fifo()`](crate::Max3010x::read_fifo).
+    ///
+    /// The perfusion index of each beat is stored in `per_beat` as long as
+    /// it fits. The average covers all beats.
+    pub fn calculate(&self, samples: &[u32], per_beat: &mut [f32]) -> PerfusionIndex {
+        let channel = Channel::new(
+            samples,
+            usize::from(self.channels),
+            usize::from(self.channel),
+        );
+        let mut beats = [0; MAX_BEATS];
+        let count = find_channel_beats(channel, self.sample_rate, &mut beats);
+        calculate(channel, &beats[..count], per_beat)
+    }
+}
+
+/// Perfusion index of the intervals between consecutive beats.
+pub(crate) fn calculate(samples: Channel, beats: &[usize], per_beat: &mut [f32]) -> PerfusionIndex {
+    let mut total = 0.0;
+    let mut beat_count = 0;
+    for (i, pair) in beats.windows(2).enumerate() {
+        let (mut min, mut max, mut sum) = (u32::MAX, 0, 0_u64);
+        for sample in pair[0]..pair[1] {
+            let value = samples.get(sample);
+            min = min.min(value);
+            max = max.max(value);
+            sum += u64::from(value);
+        }
+        let dc = sum as f32 / (pair[1] - pair[0]) as f32;
+        let pi = if dc > 0.0 {
+            100.0 * (max - min) as f32 / dc
+        } else {
+            0.0
+        };
+        if let Some(value) = per_beat.get_mut(i) {
+            *value = pi;
+        }
+        total += pi;
+        beat_count += 1;
+    }
+    PerfusionIndex {
+        average: if beat_count > 0 {
+            total / beat_count as f32
+        } else {
+            0.0
+        },
+        beat_count,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal::beat::tests::ppg;
+
+    #[test]
+    fn calculates_perfusion_index_per_beat() {
+        let mut samples = [0; 500];
+        let samples = ppg(&mut samples, 50.0, 60.0, 20_000.0, 400.0);
+        let mut per_beat = [0.0; 16];
+        let pi = PerfusionIndexCalculator::new(50.0).calculate(samples, &mut per_beat);
+        assert_eq!(9, pi.beat_count);
+        assert!(libm::fabsf(pi.average - 2.0) < 0.05, "{}", pi.average);
+        for value in &per_beat[..9] {
+            assert!(libm::fabsf(value - 2.0) < 0.05, "{}", value);
+        }
+        assert_eq!(0.0, per_beat[9]);
+    }
+
+    #[test]
+    fn average_covers_beats_not_fitting_output() {
+        let mut samples = [0; 500];
+        let samples = ppg(&mut samples, 50.0, 60.0, 20_000.0, 400.0);
+        let mut per_beat = [0.0; 2];
+        let pi = PerfusionIndexCalculator::new(50.0).calculate(samples, &mut per_beat);
+        assert_eq!(9, pi.beat_count);
+        assert!(libm::fabsf(pi.average - 2.0) < 0.05);
+    }
+
+    #[test]
+    fn uses_ir_channel_of_interleaved_samples() {
+        let mut ir = [0; 500];
+        let ir = ppg(&mut ir, 50.0, 60.0, 20_000.0, 200.0);
+        let mut samples = [0; 1000];
+        for (frame, ir) in samples.chunks_mut(2).zip(ir.iter()) {
+            frame[0] = 30_000;
+            frame[1] = *ir;
+        }
+        let calculator = PerfusionIndexCalculator {
+            sample_rate: 50.0,
+            channels: 2,
+            channel: 1,
+        };
+        let pi = calculator.calculate(&samples, &mut []);
+        assert_eq!(9, pi.beat_count);
+        assert!(libm::fabsf(pi.average - 1.0) < 0.05, "{}", pi.average);
+    }
+
+    #[test]
+    fn no_beats_give_zero() {
+        let pi = PerfusionIndexCalculator::new(50.0).calculate(&[1000; 100], &mut []);
+        assert_eq!(0, pi.beat_count);
+        assert_eq!(0.0, pi.average);
+    }
+}
@@ -0,0 +1,237 @@
+//! Signal quality index for windows of samples.
+//!
+//! The score combines the perfusion index, the correlation of the
+//! individual beats with their average shape (template), the fraction of
+//! samples clipped at the ADC full scale and the ambient light cancellation
+//! overflow flag. Heart rate and SpO2 results can be gated on the score with
+//! [`SignalQuality::gate()`].
+//!
+//! ```no_run
+//! use linux_embedded_hal::I2cdev;
+//! use max3010x::{signal::quality::assess, Max3010x};
+//!
+//! let dev = I2cdev::new("/dev/i2c-1").unwrap();
+//! let mut sensor = Max3010x::new_max30102(dev).into_heart_rate().unwrap();
+//! let mut window = [0; 32];
+//! let count = usize::from(sensor.read_fifo(&mut window).unwrap());
+//! let status = sensor.read_interrupt_status().unwrap();
+//! let quality = assess(
+//!     &window[..count],
+//!     sensor.get_effective_sampling_rate(),
+//!     sensor.get_pulse_width(),
+//!     status.alc_overflow,
+//! );
+//! if quality.score < 50 {
+//!     // ask the user to adjust the finger
+//! }
+//! ```
+
+use crate::{
+    signal::{
+        beat::{find_beats, MAX_BEATS},
+        perfusion, Channel,
+    },
+    LedPulseWidth,
+};
+
+/// Number of points used for the beat template.
+const TEMPLATE_LEN: usize = 64;
+
+/// Perfusion index at or below which the score is 0 in %.
+const MIN_PERFUSION_INDEX: f32 = 0.2;
+/// Perfusion index at or above which the score is maximum in %.
+const GOOD_PERFUSION_INDEX: f32 = 1.0;
+/// Template correlation at or below which the score is 0.
+const MIN_CORRELATION: f32 = 0.5;
+/// Template correlation at or above which the score is maximum.
+const GOOD_CORRELATION: f32 = 0.9;
+/// Fraction of clipped samples at which the score drops to 0.
+const MAX_CLIPPED_FRACTION: f32 = 0.1;
+
+/// Quality assessment of a window of samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SignalQuality {
+    /// Overall score from 0 (unusable) to 100 (excellent).
+    pub score: u8,
+    /// Average perfusion index of the beats in %.
+    pub perfusion_index: f32,
+    /// Average correlation of the beats with the beat template (-1 to 1).
+    pub template_correlation: f32,
+    /// Fraction of samples at the ADC full scale (0 to 1).
+    pub clipped_fraction: f32,
+    /// Ambient light cancellation overflow occurred.
+    pub alc_overflow: bool,
+    /// Number of beats detected.
+    pub beat_count: u8,
+}
+
+impl SignalQuality {
+    /// Return `value` only if the score is at least `min_score`.
+    pub fn gate<T>(&self, value: T, min_score: u8) -> Option<T> {
+        if self.score >= min_score {
+            Some(value)
+        } else {
+            None
+        }
+    }
+}
+
+/// Assess the quality of a window of samples of one channel.
+///
+/// The window should contain several beats. `sample_rate` is the
+/// effective sampling rate and `pulse_width` the configured LED pulse
+/// width, which determines the ADC full scale. `alc_overflow` is the flag
+/// of the same name from [`read_interrupt_status()`](crate::Max3010x::read_interrupt_status).
+pub fn assess(
+    samples: &[u32],
+    sample_rate: f32,
+    pulse_width: LedPulseWidth,
+    alc_overflow: bool,
+) -> SignalQuality {
+    let mut beats = [0; MAX_BEATS];
+    let beat_count = find_beats(samples, sample_rate, &mut beats);
+    let beats = &beats[..beat_count];
+    let perfusion_index = perfusion::calculate(Channel::single(samples), beats, &mut []).average;
+    let template_correlation = template_correlation(samples, beats);
+    let clipped_fraction = clipped_fraction(samples, pulse_width);
+
+    let perfusion_score = normalize(perfusion_index, MIN_PERFUSION_INDEX, GOOD_PERFUSION_INDEX);
+    let correlation_score = normalize(template_correlation, MIN_CORRELATION, GOOD_CORRELATION);
+    let clipping_score = 1.0 - normalize(clipped_fraction, 0.0, MAX_CLIPPED_FRACTION);
+    let mut score = 100.0 * (0.35 * perfusion_score + 0.65 * correlation_score) * clipping_score;
+    if alc_overflow {
+        score = 0.0;
+    }
+    SignalQuality {
+        score: score as u8,
+        perfusion_index,
+        template_correlation,
+        clipped_fraction,
+        alc_overflow,
+        beat_count: beat_count as u8,
+    }
+}
+
+fn normalize(value: f32, low: f32, high: f32) -> f32 {
+    ((value - low) / (high - low)).clamp(0.0, 1.0)
+}
+
+fn clipped_fraction(samples: &[u32], pulse_width: LedPulseWidth) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let full_scale = (1 << pulse_width.adc_resolution()) - 1;
+    let clipped = samples.iter().filter(|s| **s >= full_scale).count();
+    clipped as f32 / samples.len() as f32
+}
+
+/// Average Pearson correlation of each beat with the average beat.
+fn template_correlation(samples: &[u32], beats: &[usize]) -> f32 {
+    if beats.len() < 3 {
+        return 0.0;
+    }
+    let length = beats.windows(2).map(|b| b[1] - b[0]).min().unwrap_or(0);
+    let stride = 1 + length.saturating_sub(1) / TEMPLATE_LEN;
+    let points = length / stride;
+    if points < 2 {
+        return 0.0;
+    }
+    let segments = beats.len() - 1;
+    let point = |beat: usize, j: usize| samples[beats[beat] + j * stride] as f32;
+    let mut template = [0.0; TEMPLATE_LEN];
+    for (j, value) in template.iter_mut().enumerate().take(points) {
+        let sum: f32 = (0..segments).map(|k| point(k, j)).sum();
+        *value = sum / segments as f32;
+    }
+    let template = &template[..points];
+    let mut total = 0.0;
+    for k in 0..segments {
+        total += correlation(points, |j| point(k, j), |j| template[j]);
+    }
+    total / segments as f32
+}
+
+fn correlation(len: usize, a: impl Fn(usize) -> f32, b: impl Fn(usize) -> f32) -> f32 {
+    let mean_a = (0..len).map(&a).sum::<f32>() / len as f32;
+    let mean_b = (0..len).map(&b).sum::<f32>() / len as f32;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for j in 0..len {
+        let da = a(j) - mean_a;
+        let db = b(j) - mean_b;
+        cov += da * db;
+        var_a += da * da;
+        var_b += db * db;
+    }
+    let denominator = libm::sqrtf(var_a * var_b);
+    if denominator > 0.0 {
+        cov / denominator
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal::beat::tests::ppg;
+
+    #[test]
+    fn clean_signal_has_high_score() {
+        let mut samples = [0; 500];
+        let samples = ppg(&mut samples, 50.0, 60.0, 20_000.0, 400.0);
+        let quality = assess(samples, 50.0, LedPulseWidth::Pw69, false);
+        assert_eq!(10, quality.beat_count);
+        assert!(libm::fabsf(quality.perfusion_index - 2.0) < 0.1);
+        assert!(quality.template_correlation > 0.99);
+        assert_eq!(0.0, quality.clipped_fraction);
+        assert!(quality.score > 95, "{}", quality.score);
+    }
+
+    #[test]
+    fn low_perfusion_reduces_score() {
+        let mut samples = [0; 500];
+        let samples = ppg(&mut samples, 50.0, 60.0, 20_000.0, 40.0);
+        let quality = assess(samples, 50.0, LedPulseWidth::Pw69, false);
+        assert!(quality.perfusion_index < 0.3);
+        assert!(quality.score < 70, "{}", quality.score);
+    }
+
+    #[test]
+    fn clipping_reduces_score() {
+        let mut samples = [0; 500];
+        let samples = ppg(&mut samples, 50.0, 60.0, 32_767.0, 400.0);
+        for sample in samples.iter_mut().step_by(8) {
+            *sample = 32_767;
+        }
+        let quality = assess(samples, 50.0, LedPulseWidth::Pw69, false);
+        assert!(quality.clipped_fraction > 0.1);
+        assert_eq!(0, quality.score);
+    }
+
+    #[test]
+    fn alc_overflow_zeroes_score() {
+        let mut samples = [0; 500];
+        let samples = ppg(&mut samples, 50.0, 60.0, 20_000.0, 400.0);
+        let quality = assess(samples, 50.0, LedPulseWidth::Pw69, true);
+        assert!(quality.alc_overflow);
+        assert_eq!(0, quality.score);
+    }
+
+    #[test]
+    fn flat_signal_has_zero_score() {
+        let quality = assess(&[20_000; 500], 50.0, LedPulseWidth::Pw69, false);
+        assert_eq!(0, quality.beat_count);
+        assert_eq!(0, quality.score);
+    }
+
+    #[test]
+    fn can_gate_values() {
+        let mut samples = [0; 500];
+        let samples = ppg(&mut samples, 50.0, 60.0, 20_000.0, 400.0);
+        let quality = assess(samples, 50.0, LedPulseWidth::Pw69, false);
+        assert_eq!(Some(72.0), quality.gate(72.0, 80));
+        assert_eq!(None, quality.gate(72.0, 101));
+    }
+}
@@ -0,0 +1,369 @@
+//! Respiratory rate estimation from the PPG modulations.
+//!
+//! Breathing modulates the PPG in three ways, which are extracted once per
+//! beat:
+//! - Baseline (respiratory-induced intensity variation): mean of the samples.
+//! - Amplitude (respiratory-induced amplitude variation): peak-to-peak
+//!   amplitude of the pulse.
+//! - Frequency (respiratory-induced frequency variation): duration of the
+//!   inter-beat interval.
+//!
+//! The rate of each modulation is calculated from the mean crossings of its
+//! series and its quality from the regularity of the breaths. The rates are
+//! then fused weighted by their quality. The overall quality also decreases
+//! when the modulations disagree.
+//!
+//! The window should contain several breaths, e.g. 30 to 60 s of samples.
+//! At most [`MAX_BEATS`] beats are used.
+//!
+//! ```no_run
+//! use linux_embedded_hal::I2cdev;
+//! use max3010x::{signal::respiration::RespirationEstimator, Max3010x};
+//!
+//! let dev = I2cdev::new("/dev/i2c-1").unwrap();
+//! let mut sensor = Max3010x::new_max30102(dev).into_oximeter().unwrap();
+//! let estimator = RespirationEstimator::for_oximeter(&sensor);
+//! let mut window = [0; 2 * 2000];
+//! let mut count = 0;
+//! while count < window.len() {
+//!     count += 2 * usize::from(sensor.read_fifo(&mut window[count..]).unwrap());
+//! }
+//! if let Some(respiration) = estimator.estimate(&window) {
+//!     println!("{} breaths/min", respiration.breaths_per_minute);
+//! }
+//! ```
+
+use crate::{
+    marker,
+    signal::{
+        beat::{find_channel_beats, refine_beat, MAX_BEATS},
+        Channel,
+    },
+    Max3010x,
+};
+
+/// Lowest accepted respiratory rate in breaths per minute.
+const MIN_RATE: f32 = 4.0;
+/// Highest accepted respiratory rate in breaths per minute.
+const MAX_RATE: f32 = 60.0;
+/// Minimum relative modulation depth (standard deviation) of a series.
+const MIN_MODULATION_DEPTH: f32 = 0.02;
+/// Coefficient of variation of the breath durations at which the quality
+/// of a modulation drops to 0.
+const MAX_PERIOD_VARIATION: f32 = 0.5;
+/// Relative deviation of a modulation rate from the fused rate at which the
+/// overall quality drops to 0.
+const MAX_DISAGREEMENT: f32 = 0.25;
+
+/// Respiratory rate estimate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RespiratoryRate {
+    /// Fused respiratory rate in breaths per minute.
+    pub breaths_per_minute: f32,
+    /// Quality from 0 (unusable) to 100 (excellent).
+    pub quality: u8,
+    /// Rate of the baseline modulation in breaths per minute, if any.
+    pub baseline_rate: Option<f32>,
+    /// Rate of the amplitude modulation in breaths per minute, if any.
+    pub amplitude_rate: Option<f32>,
+    /// Rate of the frequency modulation in breaths per minute, if any.
+    pub frequency_rate: Option<f32>,
+}
+
+/// Respiratory rate estimator for the samples read in a given mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "RespirationEstimatorFields")
+)]
+pub struct RespirationEstimator {
+    sample_rate: f32,
+    channels: u8,
+    channel: u8,
+}
+
+/// Unvalidated contents of a deserialized [`RespirationEstimator`].
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct RespirationEstimatorFields {
+    sample_rate: f32,
+    channels: u8,
+    channel: u8,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RespirationEstimatorFields> for RespirationEstimator {
+    type Error = &'static str;
+
+    fn try_from(fields: RespirationEstimatorFields) -> Result<Self, Self::Error> {
+        if !fields.sample_rate.is_finite() || fields.sample_rate <= 0.0 {
+            return Err("invalid sample rate");
+        }
+        if fields.channel >= fields.channels {
+            return Err("channel out of range");
+        }
+        Ok(RespirationEstimator {
+            sample_rate: fields.sample_rate,
+            channels: fields.channels,
+            channel: fields.channel,
+        })
+    }
+}
+
+impl RespirationEstimator {
+    /// Create an estimator for samples of a single channel at the given
+    /// effective sampling rate.
+    pub fn new(sample_rate: f32) -> Self {
+        RespirationEstimator {
+            sample_rate,
+            channels: 1,
+            channel: 0,
+        }
+    }
+
+    /// Create an estimator for the samples read in heart-rate mode.
+    pub fn for_heart_rate<I2C, IC>(sensor: &Max3010x<I2C, IC, marker::mode::HeartRate>) -> Self {
+        Self::new(sensor.get_effective_sampling_rate())
+    }
+
+    /// Create an estimator for the IR channel of the samples read in
+    /// oximeter mode.
+    pub fn for_oximeter<I2C, IC>(sensor: &Max3010x<I2C, IC, marker::mode::Oximeter>) -> Self {
+        RespirationEstimator {
+            sample_rate: sensor.get_effective_sampling_rate(),
+            channels: 2,
+            channel: 1,
+        }
+    }
+
+    /// Estimate the respiratory rate of a window of samples as returned by
+    /// [`read_fifo()`](crate::Max3010x::read_fifo).
+    ///
+    /// Returns `None` if none of the modulations shows at least two
+    /// breaths at a rate between 4 and 60 breaths per minute.
+    pub fn estimate(&self, samples: &[u32]) -> Option<RespiratoryRate> {
+        let channel = Channel::new(
+            samples,
+            usize::from(self.channels),
+            usize::from(self.channel),
+        );
+        let mut beats = [0; MAX_BEATS];
+        let count = find_channel_beats(channel, self.sample_rate, &mut beats);
+        let intervals = count.saturating_sub(1);
+        let mut positions = [0.0; MAX_BEATS];
+        for (position, beat) in positions.iter_mut().zip(&beats[..count]) {
+            *position = refine_beat(channel, self.sample_rate, *beat);
+        }
+        let mut times = [0.0; MAX_BEATS];
+        let mut baseline = [0.0; MAX_BEATS];
+        let mut amplitude = [0.0; MAX_BEATS];
+        let mut frequency = [0.0; MAX_BEATS];
+        for (k, pair) in beats[..count].windows(2).enumerate() {
+            let (mut min, mut max, mut sum) = (u32::MAX, 0, 0_u64);
+            for sample in pair[0]..pair[1] {
+                let value = channel.get(sample);
+                min = min.min(value);
+                max = max.max(value);
+                sum += u64::from(value);
+            }
+            times[k] = 0.5 * (positions[k] + positions[k + 1]) / self.sample_rate;
+            baseline[k] = sum as f32 / (pair[1] - pair[0]) as f32;
+            amplitude[k] = (max - min) as f32;
+            frequency[k] = (positions[k + 1] - positions[k]) / self.sample_rate;
+        }
+        let times = &times[..intervals];
+        let amplitude = &amplitude[..intervals];
+        let frequency = &frequency[..intervals];
+        // The baseline variation is relative to the pulse amplitude since
+        // the DC level depends mostly on the skin and the LED current.
+        let mean_amplitude = mean(amplitude);
+        let estimates = [
+            modulation_rate(times, &baseline[..intervals], mean_amplitude),
+            modulation_rate(times, amplitude, mean_amplitude),
+            modulation_rate(times, frequency, mean(frequency)),
+        ];
+        fuse(estimates)
+    }
+}
+
+fn mean(values: &[f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f32>() / values.len() as f32
+}
+
+/// Rate in breaths per minute and quality (0 to 1) of a per-beat series.
+///
+/// The series is ignored if its standard deviation is below
+/// `MIN_MODULATION_DEPTH` of `scale`.
+fn modulation_rate(times: &[f32], values: &[f32], scale: f32) -> Option<(f32, f32)> {
+    if values.len() < 3 {
+        return None;
+    }
+    let mean = mean(values);
+    let variance =
+        values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / values.len() as f32;
+    let deviation = libm::sqrtf(variance);
+    if deviation <= MIN_MODULATION_DEPTH * scale {
+        return None;
+    }
+    let hysteresis = 0.25 * deviation;
+
+    // Upward mean crossings after having been clearly below the mean.
+    let mut crossings = [0.0; MAX_BEATS];
+    let mut count = 0;
+    let mut armed = false;
+    for k in 0..values.len() {
+        let value = values[k] - mean;
+        if value < -hysteresis {
+            armed = true;
+        } else if armed && value >= 0.0 {
+            let previous = values[k - 1] - mean;
+            let fraction = -previous / (value - previous);
+            crossings[count] = times[k - 1] + fraction * (times[k] - times[k - 1]);
+            count += 1;
+            armed = false;
+        }
+    }
+    if count < 3 {
+        return None;
+    }
+
+    let periods = count - 1;
+    let crossings = &crossings[..count];
+    let mean_period = (crossings[periods] - crossings[0]) / periods as f32;
+    let rate = 60.0 / mean_period;
+    if !(MIN_RATE..=MAX_RATE).contains(&rate) {
+        return None;
+    }
+    let period_variance = crossings
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0] - mean_period) * (pair[1] - pair[0] - mean_period))
+        .sum::<f32>()
+        / periods as f32;
+    let variation = libm::sqrtf(period_variance) / mean_period;
+    let quality = (1.0 - variation / MAX_PERIOD_VARIATION).clamp(0.0, 1.0);
+    Some((rate, quality))
+}
+
+fn fuse(estimates: [Option<(f32, f32)>; 3]) -> Option<RespiratoryRate> {
+    let available = || estimates.iter().flatten();
+    let total_quality: f32 = available().map(|(_, quality)| quality).sum();
+    let breaths_per_minute = if total_quality > 0.0 {
+        available()
+            .map(|(rate, quality)| rate * quality)
+            .sum::<f32>()
+            / total_quality
+    } else {
+        let count = available().count();
+        if count == 0 {
+            return None;
+        }
+        available().map(|(rate, _)| rate).sum::<f32>() / count as f32
+    };
+    let disagreement = available()
+        .map(|(rate, _)| libm::fabsf(rate - breaths_per_minute) / breaths_per_minute)
+        .fold(0.0, f32::max);
+    let agreement = (1.0 - disagreement / MAX_DISAGREEMENT).clamp(0.0, 1.0);
+    let quality = 100.0 * total_quality / estimates.len() as f32 * agreement;
+    Some(RespiratoryRate {
+        breaths_per_minute,
+        quality: quality as u8,
+        baseline_rate: estimates[0].map(|(rate, _)| rate),
+        amplitude_rate: estimates[1].map(|(rate, _)| rate),
+        frequency_rate: estimates[2].map(|(rate, _)| rate),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f32::consts::PI;
+
+    const SAMPLE_RATE: f32 = 100.0;
+
+    /// PPG-like signal with the given respiratory modulations (0 to 1).
+    fn modulated_ppg(
+        samples: &mut [u32],
+        breaths_per_minute: f32,
+        baseline: f32,
+        amplitude: f32,
+        frequency: f32,
+    ) {
+        let mut beat_phase = 0.0;
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let t = i as f32 / SAMPLE_RATE;
+            let breath = libm::sinf(2.0 * PI * breaths_per_minute / 60.0 * t);
+            let heart_rate = 1.2 * (1.0 + frequency * breath);
+            beat_phase = (beat_phase + heart_rate / SAMPLE_RATE) % 1.0;
+            let pulse = 0.5 * (1.0 - libm::cosf(2.0 * PI * beat_phase));
+            let dc = 50_000.0 + 1000.0 * baseline * breath;
+            let ac = 1000.0 * (1.0 + amplitude * breath);
+            *sample = (dc - ac * pulse) as u32;
+        }
+    }
+
+    #[test]
+    fn estimates_rate_from_all_modulations() {
+        let mut samples = [0; 6000];
+        modulated_ppg(&mut samples, 15.0, 0.5, 0.2, 0.1);
+        let respiration = RespirationEstimator::new(SAMPLE_RATE)
+            .estimate(&samples)
+            .unwrap();
+        assert!(libm::fabsf(respiration.breaths_per_minute - 15.0) < 1.0);
+        for rate in [
+            respiration.baseline_rate,
+            respiration.amplitude_rate,
+            respiration.frequency_rate,
+        ] {
+            assert!(libm::fabsf(rate.unwrap() - 15.0) < 1.5, "{:?}", rate);
+        }
+        assert!(respiration.quality > 60, "{}", respiration.quality);
+    }
+
+    #[test]
+    fn estimates_rate_from_single_modulation() {
+        let mut samples = [0; 6000];
+        modulated_ppg(&mut samples, 12.0, 0.0, 0.0, 0.1);
+        let respiration = RespirationEstimator::new(SAMPLE_RATE)
+            .estimate(&samples)
+            .unwrap();
+        assert!(libm::fabsf(respiration.breaths_per_minute - 12.0) < 1.0);
+        assert_eq!(None, respiration.baseline_rate);
+        assert_eq!(None, respiration.amplitude_rate);
+        assert!(respiration.quality < 50, "{}", respiration.quality);
+    }
+
+    #[test]
+    fn uses_ir_channel_of_interleaved_samples() {
+        let mut ir = [0; 6000];
+        modulated_ppg(&mut ir, 15.0, 0.5, 0.2, 0.1);
+        let mut samples = [0; 12000];
+        for (frame, ir) in samples.chunks_mut(2).zip(ir.iter()) {
+            frame[0] = 30_000;
+            frame[1] = *ir;
+        }
+        let estimator = RespirationEstimator {
+            sample_rate: SAMPLE_RATE,
+            channels: 2,
+            channel: 1,
+        };
+        let respiration = estimator.estimate(&samples).unwrap();
+        assert!(libm::fabsf(respiration.breaths_per_minute - 15.0) < 1.0);
+    }
+
+    #[test]
+    fn unmodulated_signal_gives_no_estimate() {
+        let mut samples = [0; 6000];
+        modulated_ppg(&mut samples, 15.0, 0.0, 0.0, 0.0);
+        assert_eq!(
+            None,
+            RespirationEstimator::new(SAMPLE_RATE).estimate(&samples)
+        );
+    }
+}
@@ -0,0 +1,377 @@
+//! Temperature-compensated SpO2 estimation.
+//!
+//! The red and IR LED wavelengths shift with the die temperature, which
+//! biases the red/IR ratio of ratios. The estimator applies a linear
+//! compensation to the ratio based on the last measured die temperature
+//! and reports the temperature in use together with every result.
+//!
+//! ```no_run
+//! use linux_embedded_hal::I2cdev;
+//! use max3010x::{signal::spo2::{Spo2Estimator, TemperatureCompensation}, Max3010x};
+//!
+//! let dev = I2cdev::new("/dev/i2c-1").unwrap();
+//! let mut sensor = Max3010x::new_max30102(dev).into_oximeter().unwrap();
+//! let mut estimator = Spo2Estimator::new(100)
+//!     .with_temperature_compensation(TemperatureCompensation {
+//!         reference_temperature: 25.0,
+//!         ratio_coefficient: 0.002,
+//!     })
+//!     .with_temperature_interval(500);
+//! let mut data = [0; 32 * 2];
+//! loop {
+//!     estimator.poll_temperature(&mut sensor).unwrap();
+//!     let samples = usize::from(sensor.read_fifo(&mut data).unwrap());
+//!     for frame in data[..samples * 2].chunks(2) {
+//!         if let Some(measurement) = estimator.push(frame[0], frame[1]) {
+//!             let _spo2 = measurement.spo2;
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::{Error, Max3010x};
+use hal::i2c;
+
+/// Linear temperature compensation of the red/IR ratio.
+///
+/// The compensated ratio is calculated as
+/// `ratio / (1 + ratio_coefficient * (temperature - reference_temperature))`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TemperatureCompensation {
+    /// Die temperature at which the calibration was performed in °C.
+    pub reference_temperature: f32,
+    /// Relative change of the red/IR ratio per °C.
+    pub ratio_coefficient: f32,
+}
+
+impl Default for TemperatureCompensation {
+    /// No compensation with a reference temperature of 25°C.
+    fn default() -> Self {
+        TemperatureCompensation {
+            reference_temperature: 25.0,
+            ratio_coefficient: 0.0,
+        }
+    }
+}
+
+impl TemperatureCompensation {
+    /// Apply the compensation to a red/IR ratio measured at `temperature`.
+    pub fn compensate(&self, ratio: f32, temperature: f32) -> f32 {
+        let divisor = 1.0 + self.ratio_coefficient * (temperature - self.reference_temperature);
+        if divisor > 0.0 {
+            ratio / divisor
+        } else {
+            ratio
+        }
+    }
+}
+
+/// Linear calibration curve `spo2 = offset - slope * ratio`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Spo2Calibration {
+    /// Offset in %.
+    pub offset: f32,
+    /// Slope in % per ratio unit.
+    pub slope: f32,
+}
+
+impl Default for Spo2Calibration {
+    /// Commonly used empirical curve `110 - 25 * ratio`.
+    fn default() -> Self {
+        Spo2Calibration {
+            offset: 110.0,
+            slope: 25.0,
+        }
+    }
+}
+
+/// SpO2 measurement result for one window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Spo2Measurement {
+    /// Oxygen saturation in %, clamped to `0..=100`.
+    pub spo2: f32,
+    /// Red/IR ratio of ratios before temperature compensation.
+    pub raw_ratio: f32,
+    /// Red/IR ratio of ratios after temperature compensation.
+    pub ratio: f32,
+    /// Die temperature in °C used for the compensation.
+    ///
+    /// This is `None` if no temperature has been measured yet, in which
+    /// case no compensation was applied.
+    pub temperature: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ChannelStats {
+    min: u32,
+    max: u32,
+    sum: u64,
+}
+
+impl ChannelStats {
+    const fn new() -> Self {
+        ChannelStats {
+            min: u32::MAX,
+            max: 0,
+            sum: 0,
+        }
+    }
+
+    fn push(&mut self, value: u32) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += u64::from(value);
+    }
+
+    /// AC/DC of the channel, using peak-to-peak amplitude as AC.
+    fn normalized_ac(&self, count: u16) -> Option<f32> {
+        let dc = self.sum as f32 / f32::from(count);
+        if dc <= 0.0 {
+            return None;
+        }
+        Some((self.max - self.min) as f32 / dc)
+    }
+}
+
+/// Streaming SpO2 estimator over fixed-length windows of red/IR samples.
+///
+/// Feed it the red (LED1) and IR (LED2) values of every sample read in
+/// oximeter mode. A measurement is produced at the end of every window.
+#[derive(Debug, Clone)]
+pub struct Spo2Estimator {
+    window_len: u16,
+    count: u16,
+    red: ChannelStats,
+    ir: ChannelStats,
+    calibration: Spo2Calibration,
+    compensation: TemperatureCompensation,
+    temperature: Option<f32>,
+    temperature_interval: u32,
+    samples_since_temperature: u32,
+}
+
+impl Spo2Estimator {
+    /// Create a new estimator producing one measurement every `window_len` samples.
+    ///
+    /// The window should span at least one heart beat at the effective
+    /// sampling rate. A `window_len` of 0 is treated as 1.
+    pub fn new(window_len: u16) -> Self {
+        Spo2Estimator {
+            window_len: window_len.max(1),
+            count: 0,
+            red: ChannelStats::new(),
+            ir: ChannelStats::new(),
+            calibration: Spo2Calibration::default(),
+            compensation: TemperatureCompensation::default(),
+            temperature: None,
+            temperature_interval: u32::from(window_len.max(1)),
+            samples_since_temperature: 0,
+        }
+    }
+
+    /// Set the calibration curve.
+    pub fn with_calibration(mut self, calibration: Spo2Calibration) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
+    /// Set the temperature compensation.
+    pub fn with_temperature_compensation(mut self, compensation: TemperatureCompensation) -> Self {
+        self.compensation = compensation;
+        self
+    }
+
+    /// Set the number of samples after which a new die temperature
+    /// measurement is due. (default: window length)
+    pub fn with_temperature_interval(mut self, samples: u32) -> Self {
+        self.temperature_interval = samples;
+        self
+    }
+
+    /// Die temperature currently in use, if any.
+    pub fn temperature(&self) -> Option<f32> {
+        self.temperature
+    }
+
+    /// Set the die temperature in °C used for the following measurements.
+    pub fn update_temperature(&mut self, temperature: f32) {
+        self.temperature = Some(temperature);
+        self.samples_since_temperature = 0;
+    }
+
+    /// Whether a new die temperature measurement is due.
+    pub fn is_temperature_due(&self) -> bool {
+        self.temperature.is_none() || self.samples_since_temperature >= self.temperature_interval
+    }
+
+    /// Sample the die temperature if due.
+    ///
+    /// This uses the non-blocking [`read_temperature()`](Max3010x::read_temperature)
+    /// so it should be called periodically, for example before every FIFO
+    /// read. The first call starts a conversion and a later call picks up
+    /// the result.
+    pub fn poll_temperature<I2C, E, IC, MODE>(
+        &mut self,
+        sensor: &mut Max3010x<I2C, IC, MODE>,
+    ) -> Result<(), Error<E>>
+    where
+        I2C: i2c::I2c<Error = E>,
+    {
+        if !self.is_temperature_due() {
+            return Ok(());
+        }
+        match sensor.read_temperature() {
+            Ok(temperature) => {
+                self.update_temperature(temperature);
+                Ok(())
+            }
+            Err(nb::Error::WouldBlock) => Ok(()),
+            Err(nb::Error::Other(e)) => Err(e),
+        }
+    }
+
+    /// Add a red/IR sample pair.
+    ///
+    /// Returns a measurement when a window is complete. Windows where
+    /// either channel has no DC or the IR channel has no AC component
+    /// do not produce a measurement.
+    pub fn push(&mut self, red: u32, ir: u32) -> Option<Spo2Measurement> {
+        self.red.push(red);
+        self.ir.push(ir);
+        self.count += 1;
+        self.samples_since_temperature = self.samples_since_temperature.saturating_add(1);
+        if self.count < self.window_len {
+            return None;
+        }
+        let measurement = self.measure();
+        self.reset();
+        measurement
+    }
+
+    /// Discard the samples of the current window.
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.red = ChannelStats::new();
+        self.ir = ChannelStats::new();
+    }
+
+    fn measure(&self) -> Option<Spo2Measurement> {
+        let red = self.red.normalized_ac(self.count)?;
+        let ir = self.ir.normalized_ac(self.count)?;
+        if ir <= 0.0 {
+            return None;
+        }
+        let raw_ratio = red / ir;
+        let ratio = match self.temperature {
+            Some(temperature) => self.compensation.compensate(raw_ratio, temperature),
+            None => raw_ratio,
+        };
+        let spo2 = (self.calibration.offset - self.calibration.slope * ratio).clamp(0.0, 100.0);
+        Some(Spo2Measurement {
+            spo2,
+            raw_ratio,
+            ratio,
+            temperature: self.temperature,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(value: f32, expected: f32, tolerance: f32) {
+        let difference = value - expected;
+        assert!(
+            difference < tolerance && difference > -tolerance,
+            "{} {}",
+            value,
+            expected
+        );
+    }
+
+    fn feed(estimator: &mut Spo2Estimator, red_ac: u32, ir_ac: u32) -> Option<Spo2Measurement> {
+        let mut result = None;
+        for i in 0..estimator.window_len {
+            let up = i % 2 == 0;
+            let red = if up {
+                10_000 + red_ac / 2
+            } else {
+                10_000 - red_ac / 2
+            };
+            let ir = if up {
+                10_000 + ir_ac / 2
+            } else {
+                10_000 - ir_ac / 2
+            };
+            result = estimator.push(red, ir);
+        }
+        result
+    }
+
+    #[test]
+    fn produces_measurement_at_end_of_window() {
+        let mut estimator = Spo2Estimator::new(4);
+        assert_eq!(None, estimator.push(10, 10));
+        assert_eq!(None, estimator.push(12, 12));
+        assert_eq!(None, estimator.push(10, 10));
+        assert!(estimator.push(12, 12).is_some());
+    }
+
+    #[test]
+    fn calculates_ratio_and_spo2() {
+        let mut estimator = Spo2Estimator::new(10);
+        let m = feed(&mut estimator, 100, 200).unwrap();
+        assert_close(m.raw_ratio, 0.5, 1e-4);
+        assert_close(m.spo2, 97.5, 1e-3);
+        assert_eq!(None, m.temperature);
+    }
+
+    #[test]
+    fn flat_ir_does_not_produce_measurement() {
+        let mut estimator = Spo2Estimator::new(10);
+        assert_eq!(None, feed(&mut estimator, 100, 0));
+    }
+
+    #[test]
+    fn applies_temperature_compensation() {
+        let compensation = TemperatureCompensation {
+            reference_temperature: 25.0,
+            ratio_coefficient: 0.01,
+        };
+        let mut estimator = Spo2Estimator::new(10).with_temperature_compensation(compensation);
+        estimator.update_temperature(35.0);
+        let m = feed(&mut estimator, 100, 200).unwrap();
+        assert_close(m.raw_ratio, 0.5, 1e-4);
+        assert_close(m.ratio, 0.5 / 1.1, 1e-4);
+        assert_eq!(Some(35.0), m.temperature);
+    }
+
+    #[test]
+    fn no_compensation_at_reference_temperature() {
+        let compensation = TemperatureCompensation {
+            reference_temperature: 30.0,
+            ratio_coefficient: 0.01,
+        };
+        assert_eq!(0.7, compensation.compensate(0.7, 30.0));
+    }
+
+    #[test]
+    fn temperature_is_due_periodically() {
+        let mut estimator = Spo2Estimator::new(10).with_temperature_interval(3);
+        assert!(estimator.is_temperature_due());
+        estimator.update_temperature(30.0);
+        assert!(!estimator.is_temperature_due());
+        estimator.push(1, 1);
+        estimator.push(1, 1);
+        assert!(!estimator.is_temperature_due());
+        estimator.push(1, 1);
+        assert!(estimator.is_temperature_due());
+    }
+}
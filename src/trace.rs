@@ -0,0 +1,396 @@
+//! I²C transaction tracing.
+
+use crate::{BitFlags as BF, Register as Reg};
+use core::fmt;
+use hal::i2c;
+
+/// An I²C operation traced by [`I2cTracer`].
+///
+/// The [`Display`](fmt::Display) implementation decodes the registers
+/// accessed against the device register map, for example:
+///
+/// `12 us +85 us: 0x57 write MODE=0x83 (SHDN MODE=SpO2)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceEvent<'a> {
+    /// Device address
+    pub address: u8,
+    /// Bytes written. The first one is the register address.
+    pub write: &'a [u8],
+    /// Bytes read, starting at the register address written before.
+    pub read: &'a [u8],
+    /// Register address the read started at, if any bytes were read.
+    pub read_register: Option<u8>,
+    /// Timestamp at the start of the operation in µs.
+    pub start_us: u64,
+    /// Duration of the operation in µs.
+    pub duration_us: u64,
+    /// Error returned by the bus, if any.
+    pub error: Option<i2c::ErrorKind>,
+}
+
+/// Receiver of traced I²C operations.
+///
+/// This is implemented for closures taking a [`TraceEvent`] so that it can
+/// be forwarded to any logging facility.
+pub trait TraceSink {
+    /// Handle a traced operation.
+    fn trace(&mut self, event: &TraceEvent);
+}
+
+impl<F> TraceSink for F
+where
+    F: FnMut(&TraceEvent),
+{
+    fn trace(&mut self, event: &TraceEvent) {
+        self(event)
+    }
+}
+
+/// Sink logging the traced operations with `defmt::debug!`.
+#[cfg(feature = "defmt")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefmtSink;
+
+#[cfg(feature = "defmt")]
+impl TraceSink for DefmtSink {
+    fn trace(&mut self, event: &TraceEvent) {
+        defmt::debug!("{}", defmt::Display2Format(event));
+    }
+}
+
+/// Sink logging the traced operations with `log::debug!`.
+#[cfg(feature = "log")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LogSink;
+
+#[cfg(feature = "log")]
+impl TraceSink for LogSink {
+    fn trace(&mut self, event: &TraceEvent) {
+        log::debug!("{}", event);
+    }
+}
+
+/// I²C bus wrapper tracing every operation of the driver.
+///
+/// `now_us` must return a monotonic timestamp in µs. It is called before
+/// and after each operation to measure its duration.
+///
+/// ```no_run
+/// use linux_embedded_hal::I2cdev;
+/// use max3010x::{I2cTracer, Max3010x, TraceEvent};
+/// use std::time::Instant;
+///
+/// let dev = I2cdev::new("/dev/i2c-1").unwrap();
+/// let start = Instant::now();
+/// let tracer = I2cTracer::new(
+///     dev,
+///     |event: &TraceEvent| println!("{}", event),
+///     move || start.elapsed().as_micros() as u64,
+/// );
+/// let mut sensor = Max3010x::new_max30102(tracer).into_heart_rate().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct I2cTracer<I2C, S, C> {
+    i2c: I2C,
+    sink: S,
+    now_us: C,
+}
+
+impl<I2C, S, C> I2cTracer<I2C, S, C>
+where
+    I2C: i2c::I2c,
+    S: TraceSink,
+    C: FnMut() -> u64,
+{
+    /// Wrap an I²C bus.
+    pub fn new(i2c: I2C, sink: S, now_us: C) -> Self {
+        I2cTracer { i2c, sink, now_us }
+    }
+
+    /// Destroy the tracer and return the I²C bus and the sink.
+    pub fn destroy(self) -> (I2C, S) {
+        (self.i2c, self.sink)
+    }
+
+    /// Run an operation and return its result, start time and duration.
+    fn timed<F>(&mut self, f: F) -> (Result<(), I2C::Error>, u64, u64)
+    where
+        F: FnOnce(&mut I2C) -> Result<(), I2C::Error>,
+    {
+        let start_us = (self.now_us)();
+        let result = f(&mut self.i2c);
+        let duration_us = (self.now_us)().saturating_sub(start_us);
+        (result, start_us, duration_us)
+    }
+
+    fn emit(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &[u8],
+        read_register: Option<u8>,
+        timing: (u64, u64),
+        error: Option<i2c::ErrorKind>,
+    ) {
+        self.sink.trace(&TraceEvent {
+            address,
+            write,
+            read,
+            read_register,
+            start_us: timing.0,
+            duration_us: timing.1,
+            error,
+        });
+    }
+}
+
+fn error_kind<E: i2c::Error>(result: &Result<(), E>) -> Option<i2c::ErrorKind> {
+    result.as_ref().err().map(i2c::Error::kind)
+}
+
+impl<I2C, S, C> i2c::ErrorType for I2cTracer<I2C, S, C>
+where
+    I2C: i2c::I2c,
+{
+    type Error = I2C::Error;
+}
+
+impl<I2C, S, C> i2c::I2c for I2cTracer<I2C, S, C>
+where
+    I2C: i2c::I2c,
+    S: TraceSink,
+    C: FnMut() -> u64,
+{
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        let (result, start, duration) = self.timed(|i2c| i2c.read(address, read));
+        let error = error_kind(&result);
+        self.emit(address, &[], read, None, (start, duration), error);
+        result
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        let (result, start, duration) = self.timed(|i2c| i2c.write(address, write));
+        let error = error_kind(&result);
+        self.emit(address, write, &[], None, (start, duration), error);
+        result
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        let (result, start, duration) = self.timed(|i2c| i2c.write_read(address, write, read));
+        let error = error_kind(&result);
+        let register = write.first().copied();
+        self.emit(address, write, read, register, (start, duration), error);
+        result
+    }
+
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [i2c::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let (result, start, duration) = self.timed(|i2c| i2c.transaction(address, operations));
+        let error = error_kind(&result);
+        let mut register = None;
+        for operation in operations.iter() {
+            match operation {
+                i2c::Operation::Write(write) => {
+                    register = write.first().copied();
+                    self.emit(address, write, &[], None, (start, duration), error);
+                }
+                i2c::Operation::Read(read) => {
+                    self.emit(address, &[], read, register, (start, duration), error);
+                }
+            }
+        }
+        result
+    }
+}
+
+fn register_name(register: u8) -> Option<&'static str> {
+    let name = match register {
+        Reg::INT_STATUS => "INT_STATUS",
+        Reg::INT_STATUS2 => "INT_STATUS2",
+        Reg::INT_EN1 => "INT_EN1",
+        Reg::INT_EN2 => "INT_EN2",
+        Reg::FIFO_WR_PTR => "FIFO_WR_PTR",
+        Reg::OVF_COUNTER => "OVF_COUNTER",
+        Reg::FIFO_RD_PTR => "FIFO_RD_PTR",
+        Reg::FIFO_DATA => "FIFO_DATA",
+        Reg::FIFO_CONFIG => "FIFO_CONFIG",
+        Reg::MODE => "MODE",
+        Reg::SPO2_CONFIG => "SPO2_CONFIG",
+        Reg::LED1_PA => "LED1_PA",
+        Reg::LED2_PA => "LED2_PA",
+        Reg::SLOT_CONFIG0 => "SLOT_CONFIG0",
+        Reg::SLOT_CONFIG1 => "SLOT_CONFIG1",
+        Reg::TEMP_INT => "TEMP_INT",
+        Reg::TEMP_FRAC => "TEMP_FRAC",
+        Reg::TEMP_CONFIG => "TEMP_CONFIG",
+        Reg::PROX_INT_THRESH => "PROX_INT_THRESH",
+        Reg::REV_ID => "REV_ID",
+        Reg::PART_ID => "PART_ID",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Writes the decoded fields of a register value within parentheses.
+struct Fields<'a, 'b> {
+    f: &'a mut fmt::Formatter<'b>,
+    open: bool,
+}
+
+impl Fields<'_, '_> {
+    fn item(&mut self, args: fmt::Arguments<'_>) -> fmt::Result {
+        self.f.write_str(if self.open { " " } else { " (" })?;
+        self.open = true;
+        self.f.write_fmt(args)
+    }
+
+    fn flags(&mut self, value: u8, flags: &[(u8, &str)]) -> fmt::Result {
+        for (flag, name) in flags {
+            if value & flag != 0 {
+                self.item(format_args!("{}", name))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn slot(&mut self, slot: u8, mask: u8) -> fmt::Result {
+        match mask & 0b111 {
+            0 => self.item(format_args!("SLOT{}=off", slot)),
+            1 => self.item(format_args!("SLOT{}=LED1", slot)),
+            2 => self.item(format_args!("SLOT{}=LED2", slot)),
+            other => self.item(format_args!("SLOT{}={}", slot, other)),
+        }
+    }
+
+    fn finish(self) -> fmt::Result {
+        if self.open {
+            self.f.write_str(")")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Write the meaning of the fields of a register value.
+fn write_fields(f: &mut fmt::Formatter<'_>, register: u8, value: u8) -> fmt::Result {
+    let mut fields = Fields { f, open: false };
+    match register {
+        Reg::INT_STATUS | Reg::INT_EN1 => fields.flags(
+            value,
+            &[
+                (BF::FIFO_A_FULL_INT, "A_FULL"),
+                (BF::PPG_RDY_INT, "PPG_RDY"),
+                (BF::ALC_OVF_INT, "ALC_OVF"),
+                (BF::PWR_RDY_INT, "PWR_RDY"),
+            ],
+        )?,
+        Reg::INT_STATUS2 | Reg::INT_EN2 => {
+            fields.flags(value, &[(BF::DIE_TEMP_RDY_INT, "DIE_TEMP_RDY")])?
+        }
+        Reg::FIFO_CONFIG => {
+            fields.item(format_args!("SMP_AVE={}", 1 << (value >> 5).min(5)))?;
+            fields.flags(value, &[(BF::FIFO_ROLLOVER_EN, "ROLLOVER_EN")])?;
+            fields.item(format_args!("A_FULL={}", value & 0x0F))?;
+        }
+        Reg::MODE => {
+            fields.flags(value, &[(BF::SHUTDOWN, "SHDN"), (BF::RESET, "RESET")])?;
+            match value & 0b111 {
+                0b010 => fields.item(format_args!("MODE=HR"))?,
+                0b011 => fields.item(format_args!("MODE=SpO2"))?,
+                0b111 => fields.item(format_args!("MODE=multi-LED"))?,
+                other => fields.item(format_args!("MODE={:#05b}", other))?,
+            }
+        }
+        Reg::SPO2_CONFIG => {
+            let sps: [u16; 8] = [50, 100, 200, 400, 800, 1000, 1600, 3200];
+            let pw: [u16; 4] = [69, 118, 215, 411];
+            let range = 2048_u16 << ((value >> 5) & 0b11);
+            fields.item(format_args!("ADC_RGE={}nA", range))?;
+            let rate = sps[usize::from((value >> 2) & 0b111)];
+            fields.item(format_args!("SR={}sps", rate))?;
+            fields.item(format_args!("LED_PW={}us", pw[usize::from(value & 0b11)]))?;
+        }
+        Reg::LED1_PA | Reg::LED2_PA => {
+            let current = u16::from(value) * 2;
+            fields.item(format_args!("{}.{}mA", current / 10, current % 10))?;
+        }
+        Reg::SLOT_CONFIG0 => {
+            fields.slot(1, value)?;
+            fields.slot(2, value >> 4)?;
+        }
+        Reg::SLOT_CONFIG1 => {
+            fields.slot(3, value)?;
+            fields.slot(4, value >> 4)?;
+        }
+        Reg::TEMP_CONFIG => fields.flags(value, &[(BF::TEMP_EN, "TEMP_EN")])?,
+        _ => (),
+    }
+    fields.finish()
+}
+
+fn write_register_name(f: &mut fmt::Formatter<'_>, register: u8) -> fmt::Result {
+    match register_name(register) {
+        Some(name) => f.write_str(name),
+        None => write!(f, "{:#04x}", register),
+    }
+}
+
+/// Write the registers accessed starting at `register` with auto-increment.
+///
+/// The FIFO data register does not auto-increment so only the number of
+/// bytes is written.
+fn write_registers(f: &mut fmt::Formatter<'_>, register: u8, values: &[u8]) -> fmt::Result {
+    if register == Reg::FIFO_DATA {
+        return write!(f, " FIFO_DATA[{} bytes]", values.len());
+    }
+    for (offset, value) in values.iter().enumerate() {
+        let register = register.wrapping_add(offset as u8);
+        f.write_str(" ")?;
+        write_register_name(f, register)?;
+        write!(f, "={:#04x}", value)?;
+        write_fields(f, register, *value)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for TraceEvent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} us +{} us: {:#04x}",
+            self.start_us, self.duration_us, self.address
+        )?;
+        if let Some((register, values)) = self.write.split_first() {
+            write!(f, " write")?;
+            if values.is_empty() {
+                f.write_str(" ")?;
+                write_register_name(f, *register)?;
+            } else {
+                write_registers(f, *register, values)?;
+            }
+        }
+        if !self.read.is_empty() {
+            write!(f, " read")?;
+            match self.read_register {
+                Some(register) => write_registers(f, register, self.read)?,
+                None => {
+                    for value in self.read {
+                        write!(f, " {:#04x}", value)?;
+                    }
+                }
+            }
+        }
+        if let Some(error) = self.error {
+            write!(f, " error: {:?}", error)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+//! Split-phase FIFO reads for transfers done outside of the driver.
+
+use crate::reading::{decode_fifo_bytes, ChannelCount, BYTES_PER_CHANNEL};
+use crate::{Error, LedPulseWidth, Max3010x, Register as Reg};
+use hal::i2c;
+
+/// FIFO data transfer prepared by [`begin_fifo_read()`](Max3010x::begin_fifo_read).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FifoTransfer {
+    /// Device I²C address.
+    pub address: u8,
+    /// Register to write before reading the data.
+    pub register: u8,
+    /// Number of bytes to read.
+    pub byte_count: usize,
+    /// Number of samples contained in the bytes.
+    pub sample_count: u8,
+    /// Number of samples lost before this transfer.
+    pub overflow_count: u8,
+}
+
+/// FIFO data transfer pending completion with the configuration needed to
+/// decode its bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PendingFifoRead {
+    transfer: FifoTransfer,
+    channels: u8,
+    pulse_width: LedPulseWidth,
+}
+
+impl<I2C, E, IC, MODE> Max3010x<I2C, IC, MODE>
+where
+    I2C: i2c::I2c<Error = E>,
+    MODE: ChannelCount<IC, MODE>,
+{
+    /// Prepare a FIFO data transfer to be done outside of the driver.
+    ///
+    /// This reads the FIFO pointers and returns the register and number of
+    /// bytes to read so that the transfer can be done in any way, for
+    /// example by DMA. The transfer holds as many available samples as fit
+    /// in `buffer_len` bytes, which may be none.
+    ///
+    /// Once the transfer is complete, the bytes must be passed to
+    /// [`finish_fifo_read()`](Max3010x::finish_fifo_read). Calling this
+    /// again replaces the pending transfer.
+    ///
+    /// The bytes are decoded with the configuration at the time of this
+    /// call, so changing the configuration in between does not affect them.
+    pub fn begin_fifo_read(&mut self, buffer_len: usize) -> Result<FifoTransfer, Error<E>> {
+        let pointers = self.read_fifo_pointers()?;
+        let channels = self.channel_count();
+        let bytes_per_sample = usize::from(channels) * BYTES_PER_CHANNEL;
+        let fitting = buffer_len
+            .checked_div(bytes_per_sample)
+            .unwrap_or(0)
+            .min(32) as u8;
+        let sample_count = pointers.available().min(fitting);
+        let transfer = FifoTransfer {
+            address: self.address,
+            register: Reg::FIFO_DATA,
+            byte_count: usize::from(sample_count) * bytes_per_sample,
+            sample_count,
+            overflow_count: pointers.overflow,
+        };
+        self.fifo_transfer = Some(PendingFifoRead {
+            transfer,
+            channels,
+            pulse_width: self.get_pulse_width(),
+        });
+        Ok(transfer)
+    }
+
+    /// Complete the pending FIFO data transfer and decode the samples.
+    ///
+    /// `bytes` must be the data read for the transfer returned by
+    /// [`begin_fifo_read()`](Max3010x::begin_fifo_read). The output buffer
+    /// is filled like in [`read_fifo()`](Max3010x::read_fifo) and must have
+    /// room for all the samples.
+    ///
+    /// Returns the number of _samples_ decoded.
+    ///
+    /// Returns `Error::InvalidArguments` if there is no pending transfer,
+    /// the number of bytes does not match or the output buffer is too
+    /// small. The transfer remains pending in that case.
+    pub fn finish_fifo_read(&mut self, bytes: &[u8], output: &mut [u32]) -> Result<u8, Error<E>> {
+        let pending = match self.fifo_transfer {
+            Some(pending) => pending,
+            None => return Err(Error::InvalidArguments),
+        };
+        let transfer = pending.transfer;
+        let value_count = usize::from(transfer.sample_count) * usize::from(pending.channels);
+        if bytes.len() != transfer.byte_count || output.len() < value_count {
+            return Err(Error::InvalidArguments);
+        }
+        let values = decode_fifo_bytes(bytes, pending.channels, pending.pulse_width);
+        for (out_item, value) in output.iter_mut().zip(values) {
+            *out_item = value;
+        }
+        self.fifo_transfer = None;
+        Ok(transfer.sample_count)
+    }
+
+    /// Get the FIFO data transfer pending completion, if any.
+    pub fn pending_fifo_read(&self) -> Option<FifoTransfer> {
+        self.fifo_transfer.map(|pending| pending.transfer)
+    }
+}
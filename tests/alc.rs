@@ -0,0 +1,143 @@
+extern crate embedded_hal_mock as hal;
+use hal::eh1::i2c::Transaction as I2cTrans;
+extern crate max3010x;
+use max3010x::{AdcRange, AlcOverflowEvent, AlcOverflowPolicy, INVALID_SAMPLE};
+mod base;
+use crate::base::{destroy, new, BitFlags as BF, Register as Reg, DEV_ADDR};
+
+fn status(alc_overflow: bool) -> I2cTrans {
+    let status1 = if alc_overflow { BF::ALC_OVF_INT } else { 0 };
+    I2cTrans::write_read(DEV_ADDR, vec![Reg::INT_STATUS], vec![status1, 0])
+}
+
+fn fifo_read(sample_count: u8, data: Vec<u8>) -> [I2cTrans; 2] {
+    [
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![sample_count, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_DATA], data),
+    ]
+}
+
+const POLICY: AlcOverflowPolicy = AlcOverflowPolicy {
+    mark_invalid: true,
+    step_up_adc_range: true,
+};
+
+#[test]
+fn default_policy_only_marks_samples_invalid() {
+    let policy = AlcOverflowPolicy::default();
+    assert!(policy.mark_invalid);
+    assert!(!policy.step_up_adc_range);
+}
+
+#[test]
+fn returns_samples_without_overflow() {
+    let mut transactions = vec![
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        status(false),
+    ];
+    transactions.extend(fifo_read(2, vec![0, 0, 8, 0, 0, 16]));
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    let mut data = [0; 2];
+    let read = dev.read_fifo_checked(&mut data, POLICY).unwrap();
+    assert_eq!(2, read.sample_count);
+    assert!(!read.status.alc_overflow);
+    assert_eq!(None, read.alc_overflow);
+    assert_eq!([1, 2], data);
+    destroy(dev);
+}
+
+#[test]
+fn marks_samples_invalid_on_overflow() {
+    let mut transactions = vec![
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        status(true),
+    ];
+    transactions.extend(fifo_read(2, vec![0, 0, 8, 0, 0, 16]));
+    transactions.push(I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 1 << 5]));
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    let mut data = [0; 3];
+    let read = dev.read_fifo_checked(&mut data, POLICY).unwrap();
+    assert_eq!(2, read.sample_count);
+    assert!(read.status.alc_overflow);
+    let event = AlcOverflowEvent {
+        invalid_sample_count: 2,
+        adc_range: Some(AdcRange::Fs4k),
+    };
+    assert_eq!(Some(event), read.alc_overflow);
+    assert_eq!([INVALID_SAMPLE, INVALID_SAMPLE, 0], data);
+    destroy(dev);
+}
+
+#[test]
+fn can_keep_samples_on_overflow() {
+    let mut transactions = vec![
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        status(true),
+    ];
+    transactions.extend(fifo_read(1, vec![0, 0, 8]));
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    let mut data = [0; 1];
+    let policy = AlcOverflowPolicy {
+        mark_invalid: false,
+        step_up_adc_range: false,
+    };
+    let read = dev.read_fifo_checked(&mut data, policy).unwrap();
+    let event = AlcOverflowEvent {
+        invalid_sample_count: 0,
+        adc_range: None,
+    };
+    assert_eq!(Some(event), read.alc_overflow);
+    assert_eq!([1], data);
+    destroy(dev);
+}
+
+#[test]
+fn steps_up_adc_range() {
+    let mut transactions = vec![
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b011]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 1 << 5]),
+        status(true),
+    ];
+    transactions.extend(fifo_read(1, vec![0, 0, 8, 0, 0, 16]));
+    transactions.push(I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 2 << 5]));
+    let mut dev = new(&transactions).into_oximeter().unwrap();
+    dev.set_adc_range(AdcRange::Fs4k).unwrap();
+    let mut data = [0; 2];
+    let read = dev.read_fifo_checked(&mut data, POLICY).unwrap();
+    let event = AlcOverflowEvent {
+        invalid_sample_count: 1,
+        adc_range: Some(AdcRange::Fs8k),
+    };
+    assert_eq!(Some(event), read.alc_overflow);
+    assert_eq!([INVALID_SAMPLE; 2], data);
+    destroy(dev);
+}
+
+#[test]
+fn does_not_step_up_beyond_maximum_adc_range() {
+    let mut transactions = vec![
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b011]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 3 << 5]),
+        status(true),
+    ];
+    transactions.push(I2cTrans::write_read(
+        DEV_ADDR,
+        vec![Reg::FIFO_WR_PTR],
+        vec![0, 0, 0],
+    ));
+    let mut dev = new(&transactions).into_oximeter().unwrap();
+    dev.set_adc_range(AdcRange::Fs16k).unwrap();
+    let mut data = [0; 2];
+    let read = dev.read_fifo_checked(&mut data, POLICY).unwrap();
+    let event = AlcOverflowEvent {
+        invalid_sample_count: 0,
+        adc_range: None,
+    };
+    assert_eq!(Some(event), read.alc_overflow);
+    destroy(dev);
+}
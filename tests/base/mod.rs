@@ -7,10 +7,12 @@ pub struct Register;
 #[allow(unused)]
 impl Register {
     pub const INT_STATUS: u8 = 0x0;
+    pub const INT_STATUS2: u8 = 0x01;
     pub const INT_EN1: u8 = 0x02;
     pub const INT_EN2: u8 = 0x03;
     pub const FIFO_WR_PTR: u8 = 0x04;
     pub const OVF_COUNTER: u8 = 0x05;
+    pub const FIFO_RD_PTR: u8 = 0x06;
     pub const FIFO_DATA: u8 = 0x07;
     pub const FIFO_CONFIG: u8 = 0x08;
     pub const MODE: u8 = 0x09;
@@ -18,8 +20,11 @@ impl Register {
     pub const LED1_PA: u8 = 0x0C;
     pub const LED2_PA: u8 = 0x0D;
     pub const SLOT_CONFIG0: u8 = 0x11;
+    pub const SLOT_CONFIG1: u8 = 0x12;
     pub const TEMP_INT: u8 = 0x1F;
+    pub const TEMP_FRAC: u8 = 0x20;
     pub const TEMP_CONFIG: u8 = 0x21;
+    pub const PROX_INT_THRESH: u8 = 0x30;
     pub const REV_ID: u8 = 0xFE;
     pub const PART_ID: u8 = 0xFF;
 }
@@ -68,6 +73,22 @@ macro_rules! assert_invalid_args {
     };
 }
 
+#[macro_export]
+macro_rules! assert_unsupported_config {
+    ($result:expr, $pulse_width:expr, $sampling_rate:expr) => {
+        match $result {
+            Err(max3010x::Error::UnsupportedSamplingConfiguration {
+                pulse_width,
+                sampling_rate,
+            }) => {
+                assert_eq!($pulse_width, pulse_width);
+                assert_eq!($sampling_rate, sampling_rate);
+            }
+            _ => panic!("Did not return Error::UnsupportedSamplingConfiguration"),
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! assert_near {
     ($left:expr, $right:expr, $eps:expr) => {
@@ -1,6 +1,6 @@
 extern crate embedded_hal_mock as hal;
 extern crate max3010x;
-use max3010x::Error;
+use max3010x::{Error, LedPulseWidth, SamplingRate};
 
 #[allow(unused)]
 mod base;
@@ -27,6 +27,28 @@ fn assert_invalid_args_can_fail() {
     assert_invalid_args!(Ok::<(), Error<()>>(()));
 }
 
+#[test]
+fn assert_unsupported_config_can_succeed() {
+    assert_unsupported_config!(
+        Err::<(), Error<()>>(Error::UnsupportedSamplingConfiguration {
+            pulse_width: LedPulseWidth::Pw411,
+            sampling_rate: SamplingRate::Sps800,
+        }),
+        LedPulseWidth::Pw411,
+        SamplingRate::Sps800
+    );
+}
+
+#[test]
+#[should_panic]
+fn assert_unsupported_config_can_fail() {
+    assert_unsupported_config!(
+        Err::<(), Error<()>>(Error::InvalidArguments),
+        LedPulseWidth::Pw411,
+        SamplingRate::Sps800
+    );
+}
+
 #[test]
 fn assert_near_can_succeed() {
     assert_near!(1.0, 1.01, 0.1);
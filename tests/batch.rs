@@ -0,0 +1,144 @@
+extern crate embedded_hal;
+extern crate embedded_hal_mock as hal;
+use embedded_hal::i2c::ErrorKind;
+use hal::eh1::i2c::Transaction as I2cTrans;
+extern crate max3010x;
+use max3010x::{Error, Led, LedPulseWidth, SampleAveraging, SamplingRate, TimeSlot};
+mod base;
+use crate::base::{destroy, new, BitFlags as BF, Register as Reg, DEV_ADDR};
+
+#[test]
+fn flush_without_batch_does_nothing() {
+    let mut dev = new(&[]);
+    assert!(!dev.is_batching());
+    dev.flush_batch().unwrap();
+    destroy(dev);
+}
+
+#[test]
+fn empty_batch_writes_nothing() {
+    let mut dev = new(&[]);
+    dev.begin_batch();
+    assert!(dev.is_batching());
+    dev.flush_batch().unwrap();
+    assert!(!dev.is_batching());
+    destroy(dev);
+}
+
+#[test]
+fn bursts_initial_setup() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::INT_EN1, BF::FIFO_A_FULL_INT]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED1_PA, 15, 15]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_CONFIG, 0b0101_0000]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 0b0000_0111]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b011]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ];
+    let mut dev = new(&transactions);
+    dev.begin_batch();
+    let mut dev = dev.into_oximeter().unwrap();
+    assert!(dev.is_batching());
+    dev.set_sample_averaging(SampleAveraging::Sa4).unwrap();
+    dev.enable_fifo_rollover().unwrap();
+    dev.set_sampling_rate(SamplingRate::Sps100).unwrap();
+    dev.set_pulse_width(LedPulseWidth::Pw411).unwrap();
+    dev.set_pulse_amplitude(Led::All, 15).unwrap();
+    dev.enable_fifo_almost_full_interrupt().unwrap();
+    dev.flush_batch().unwrap();
+    assert!(!dev.is_batching());
+    destroy(dev);
+}
+
+#[test]
+fn includes_unchanged_registers_between_changes() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b111]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SLOT_CONFIG0, 2 << 4 | 1, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_CONFIG, 0b0010_0000]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 0b0000_0100]),
+    ];
+    let mut dev = new(&transactions).into_multi_led().unwrap();
+    dev.begin_batch();
+    dev.set_led_time_slots([
+        TimeSlot::Led1,
+        TimeSlot::Led2,
+        TimeSlot::Disabled,
+        TimeSlot::Disabled,
+    ])
+    .unwrap();
+    dev.set_sample_averaging(SampleAveraging::Sa2).unwrap();
+    dev.set_sampling_rate(SamplingRate::Sps100).unwrap();
+    dev.flush_batch().unwrap();
+    destroy(dev);
+}
+
+#[test]
+fn writes_mode_after_sampling_configuration() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 0b0000_0100]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ];
+    let mut dev = new(&transactions);
+    dev.begin_batch();
+    let mut dev = dev.into_heart_rate().unwrap();
+    dev.set_sampling_rate(SamplingRate::Sps100).unwrap();
+    dev.flush_batch().unwrap();
+    destroy(dev);
+}
+
+#[test]
+fn flushes_before_reading_fifo() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 0b0000_0011]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![1, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_DATA], vec![0, 0, 1]),
+    ];
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    dev.begin_batch();
+    dev.set_pulse_width(LedPulseWidth::Pw411).unwrap();
+    let mut data = [0; 1];
+    assert_eq!(1, dev.read_fifo(&mut data).unwrap());
+    assert_eq!([1], data);
+    assert!(!dev.is_batching());
+    destroy(dev);
+}
+
+#[test]
+fn writes_single_changes_on_their_own() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::INT_EN2, BF::DIE_TEMP_RDY_INT]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED2_PA, 7]),
+    ];
+    let mut dev = new(&transactions);
+    dev.begin_batch();
+    dev.enable_temperature_ready_interrupt().unwrap();
+    dev.set_pulse_amplitude(Led::Led2, 7).unwrap();
+    dev.flush_batch().unwrap();
+    destroy(dev);
+}
+
+#[test]
+fn keeps_pending_changes_on_error() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED1_PA, 3]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, BF::SHUTDOWN]).with_error(ErrorKind::Other),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, BF::SHUTDOWN]),
+    ];
+    let mut dev = new(&transactions);
+    dev.begin_batch();
+    dev.set_pulse_amplitude(Led::Led1, 3).unwrap();
+    dev.shutdown().unwrap();
+    match dev.flush_batch() {
+        Err(Error::I2C(ErrorKind::Other)) => (),
+        _ => panic!("Did not return Error::I2C"),
+    }
+    assert!(dev.is_batching());
+    dev.flush_batch().unwrap();
+    assert!(!dev.is_batching());
+    destroy(dev);
+}
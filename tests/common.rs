@@ -2,7 +2,9 @@ extern crate embedded_hal_mock as hal;
 use hal::eh1::i2c::Transaction as I2cTrans;
 extern crate max3010x;
 extern crate nb;
-use max3010x::{FifoAlmostFullLevelInterrupt, Led, LedPulseWidth, SampleAveraging};
+use max3010x::{
+    decode_fifo_bytes, FifoAlmostFullLevelInterrupt, Led, LedPulseWidth, SampleAveraging,
+};
 mod base;
 use base::{destroy, new, BitFlags as BF, Register as Reg, DEV_ADDR};
 
@@ -36,6 +38,14 @@ mod available_sample_count {
     available_sample_count_test!(one, 1, 0, 1);
     available_sample_count_test!(two, 2, 0, 2);
     available_sample_count_test!(rollover, 0, 1, 31);
+    read_test!(
+        full,
+        get_available_sample_count,
+        [],
+        FIFO_WR_PTR,
+        [5, 3, 5],
+        32
+    );
 }
 
 #[test]
@@ -214,6 +224,48 @@ fn read_fifo_samples_2channels_pw411() {
     read_fifo_samples_2channels(LedPulseWidth::Pw411, 3, 0);
 }
 
+#[test]
+fn read_fifo_raw_reads_bytes_fitting_in_buffer() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b011]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![3, 0, 0]),
+        I2cTrans::write_read(
+            DEV_ADDR,
+            vec![Reg::FIFO_DATA],
+            vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12],
+        ),
+    ];
+    let mut dev = new(&transactions).into_oximeter().unwrap();
+    let mut data = [0xFF; 13];
+    assert_eq!(2, dev.read_fifo_raw(&mut data).unwrap());
+    assert_eq!([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 0xFF], data);
+    destroy(dev);
+}
+
+#[test]
+fn read_fifo_raw_too_small_buffer_returns0() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![5, 0, 0]),
+    ];
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    let mut data = [0; 2];
+    assert_eq!(0, dev.read_fifo_raw(&mut data).unwrap());
+    destroy(dev);
+}
+
+#[test]
+fn decode_fifo_bytes_shifts_and_ignores_incomplete_samples() {
+    let bytes = [0, 0, 8, 0xFF, 0xFF, 0xF8, 0, 0, 16, 1];
+    let values: Vec<u32> = decode_fifo_bytes(&bytes, 1, LedPulseWidth::Pw69).collect();
+    assert_eq!(vec![1, 0x1F_FFFF, 2], values);
+    let values = decode_fifo_bytes(&bytes, 2, LedPulseWidth::Pw69);
+    assert_eq!(2, values.len());
+    assert_eq!(0, decode_fifo_bytes(&bytes, 0, LedPulseWidth::Pw69).count());
+}
+
 mod set_pulse_amplitude {
     use super::*;
     write_test!(led1, set_pulse_amplitude, [Led::Led1, 50], LED1_PA, [50]);
@@ -276,3 +328,16 @@ high_low_flag_method_test!(
     0,
     FIFO_CONFIG
 );
+
+#[test]
+fn effective_sampling_rate_includes_averaging() {
+    let transactions = [I2cTrans::write(
+        DEV_ADDR,
+        vec![Reg::FIFO_CONFIG, 0b0100_0000],
+    )];
+    let mut dev = new(&transactions);
+    assert_near!(50.0, dev.get_effective_sampling_rate(), 0.001);
+    dev.set_sample_averaging(SampleAveraging::Sa4).unwrap();
+    assert_near!(12.5, dev.get_effective_sampling_rate(), 0.001);
+    destroy(dev);
+}
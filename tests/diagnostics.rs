@@ -0,0 +1,144 @@
+extern crate embedded_hal_mock as hal;
+use hal::eh1::i2c::Transaction as I2cTrans;
+extern crate max3010x;
+use max3010x::{
+    AdcRange, FifoAlmostFullLevelInterrupt, Led, LedPulseWidth, RegisterMismatch, SampleAveraging,
+    SamplingRate, TimeSlot,
+};
+mod base;
+use base::{destroy, new, BitFlags as BF, Register as Reg, DEV_ADDR};
+
+fn dump_transactions(registers: [u8; 0x22]) -> Vec<I2cTrans> {
+    vec![
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::INT_STATUS], registers[..0x07].to_vec()),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_CONFIG], registers[0x08..].to_vec()),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::PROX_INT_THRESH], vec![0xAA]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::REV_ID], vec![0x03, 0x15]),
+    ]
+}
+
+#[test]
+fn can_dump_registers() {
+    let mut registers = [0; 0x22];
+    registers[usize::from(Reg::INT_STATUS)] = BF::PWR_RDY_INT;
+    registers[usize::from(Reg::INT_STATUS2)] = BF::DIE_TEMP_RDY_INT;
+    registers[usize::from(Reg::INT_EN1)] = BF::ALC_OVF_INT;
+    registers[usize::from(Reg::FIFO_WR_PTR)] = 5;
+    registers[usize::from(Reg::OVF_COUNTER)] = 1;
+    registers[usize::from(Reg::FIFO_RD_PTR)] = 2;
+    registers[usize::from(Reg::FIFO_CONFIG)] = 0b0101_0011;
+    registers[usize::from(Reg::MODE)] = BF::SHUTDOWN | 0b011;
+    registers[usize::from(Reg::SPO2_CONFIG)] = 0b0010_0110;
+    registers[usize::from(Reg::LED1_PA)] = 0x1F;
+    registers[usize::from(Reg::LED2_PA)] = 0x2F;
+    registers[usize::from(Reg::SLOT_CONFIG0)] = 0x21;
+    registers[usize::from(Reg::SLOT_CONFIG1)] = 0x01;
+    registers[usize::from(Reg::TEMP_INT)] = 25;
+    registers[usize::from(Reg::TEMP_FRAC)] = 8;
+    let transactions = dump_transactions(registers);
+    let mut dev = new(&transactions);
+    let dump = dev.dump_registers().unwrap();
+    assert_eq!(registers, dump.registers);
+    assert_eq!(0xAA, dump.proximity_interrupt_threshold);
+    assert_eq!(0x03, dump.revision_id);
+    assert_eq!(0x15, dump.part_id);
+    assert!(dump.interrupt_status.power_ready);
+    assert!(dump.interrupt_status.temperature_ready);
+    assert!(dump.alc_overflow_interrupt_enabled);
+    assert!(!dump.fifo_almost_full_interrupt_enabled);
+    assert_eq!(5, dump.fifo_write_pointer);
+    assert_eq!(1, dump.fifo_overflow_counter);
+    assert_eq!(2, dump.fifo_read_pointer);
+    assert_eq!(SampleAveraging::Sa4, dump.sample_averaging);
+    assert!(dump.fifo_rollover_enabled);
+    assert_eq!(
+        FifoAlmostFullLevelInterrupt::L3,
+        dump.fifo_almost_full_level
+    );
+    assert!(dump.shutdown);
+    assert_eq!(0b011, dump.mode);
+    assert_eq!(AdcRange::Fs4k, dump.adc_range);
+    assert_eq!(SamplingRate::Sps100, dump.sampling_rate);
+    assert_eq!(LedPulseWidth::Pw215, dump.pulse_width);
+    assert_eq!(0x1F, dump.led1_pulse_amplitude);
+    assert_eq!(0x2F, dump.led2_pulse_amplitude);
+    assert_eq!(
+        [
+            Some(TimeSlot::Led1),
+            Some(TimeSlot::Led2),
+            Some(TimeSlot::Led1),
+            Some(TimeSlot::Disabled)
+        ],
+        dump.time_slots
+    );
+    assert_near!(25.5, dump.temperature, 0.01);
+    destroy(dev);
+}
+
+#[test]
+fn diff_is_empty_when_registers_match() {
+    let mut registers = [0; 0x22];
+    registers[usize::from(Reg::INT_STATUS)] = BF::PWR_RDY_INT;
+    registers[usize::from(Reg::MODE)] = 0b010;
+    registers[usize::from(Reg::LED1_PA)] = 15;
+    registers[usize::from(Reg::LED2_PA)] = 15;
+    let mut transactions = vec![
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED1_PA, 15, 15]),
+    ];
+    transactions.extend(dump_transactions(registers));
+    let dev = new(&transactions);
+    let mut dev = dev.into_heart_rate().unwrap();
+    dev.set_pulse_amplitude(Led::All, 15).unwrap();
+    let dump = dev.dump_registers().unwrap();
+    let diff = dev.diff_registers(&dump);
+    assert!(diff.is_empty());
+    destroy(dev);
+}
+
+#[test]
+fn diff_reports_registers_reset_to_defaults() {
+    let mut transactions = vec![
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED2_PA, 15]),
+    ];
+    transactions.extend(dump_transactions([0; 0x22]));
+    let dev = new(&transactions);
+    let mut dev = dev.into_heart_rate().unwrap();
+    dev.set_pulse_amplitude(Led::Led2, 15).unwrap();
+    let dump = dev.dump_registers().unwrap();
+    let diff = dev.diff_registers(&dump);
+    assert_eq!(
+        &[
+            RegisterMismatch {
+                register: Reg::MODE,
+                expected: 0b010,
+                actual: 0
+            },
+            RegisterMismatch {
+                register: Reg::LED2_PA,
+                expected: 15,
+                actual: 0
+            }
+        ],
+        diff.as_slice()
+    );
+    destroy(dev);
+}
+
+#[test]
+fn diff_ignores_bits_not_under_driver_control() {
+    let mut registers = [0; 0x22];
+    // reserved bits and self-clearing reset bit
+    registers[usize::from(Reg::INT_EN1)] = 0b0001_1111;
+    registers[usize::from(Reg::MODE)] = BF::RESET | 0b0011_1000;
+    registers[usize::from(Reg::SPO2_CONFIG)] = 0b1000_0000;
+    registers[usize::from(Reg::SLOT_CONFIG0)] = 0b1000_1000;
+    let transactions = dump_transactions(registers);
+    let mut dev = new(&transactions);
+    let dump = dev.dump_registers().unwrap();
+    assert!(dev.diff_registers(&dump).is_empty());
+    destroy(dev);
+}
@@ -0,0 +1,167 @@
+extern crate embedded_hal_mock as hal;
+use core::cell::{Cell, RefCell};
+use embedded_hal_bus::i2c::RefCellDevice;
+use hal::eh1::i2c::{Mock as I2cMock, Transaction as I2cTrans};
+extern crate max3010x;
+use max3010x::{
+    marker::{ic::Max30102, mode::HeartRate},
+    Max3010x, SensorGroup,
+};
+#[allow(unused)]
+mod base;
+use base::{BitFlags as BF, Register as Reg, DEV_ADDR};
+
+const OTHER_ADDR: u8 = 0x58;
+
+fn into_heart_rate_transactions(address: u8) -> Vec<I2cTrans> {
+    vec![
+        I2cTrans::write(address, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(address, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ]
+}
+
+fn clock(times: &[u64]) -> impl FnMut() -> u64 + '_ {
+    let index = Cell::new(0);
+    move || {
+        let time = times[index.get()];
+        index.set(index.get() + 1);
+        time
+    }
+}
+
+#[test]
+fn reads_same_sample_count_from_all_devices() {
+    let mut transactions = into_heart_rate_transactions(DEV_ADDR);
+    transactions.extend(into_heart_rate_transactions(OTHER_ADDR));
+    transactions.extend([
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(OTHER_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![3, 0, 0]),
+        I2cTrans::write_read(OTHER_ADDR, vec![Reg::FIFO_WR_PTR], vec![2, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_DATA], vec![0, 0, 8, 0, 0, 16]),
+        I2cTrans::write_read(OTHER_ADDR, vec![Reg::FIFO_DATA], vec![0, 0, 24, 0, 0, 32]),
+    ]);
+    let bus = RefCell::new(I2cMock::new(&transactions));
+    let finger = Max3010x::new_max30102(RefCellDevice::new(&bus));
+    let earlobe = Max3010x::new_max30102_with_address(RefCellDevice::new(&bus), OTHER_ADDR);
+    let finger = finger.into_heart_rate().unwrap();
+    let earlobe = earlobe.into_heart_rate().unwrap();
+    let mut group = SensorGroup::new([finger, earlobe]);
+    group.start(clock(&[0, 100, 200, 300])).unwrap();
+
+    let mut finger_data = [0; 4];
+    let mut earlobe_data = [0; 4];
+    let result = group.read([&mut finger_data, &mut earlobe_data]).unwrap();
+    assert_eq!(2, result.sample_count);
+    assert_eq!([1, 2, 0, 0], finger_data);
+    assert_eq!([3, 4, 0, 0], earlobe_data);
+    // 50 sps: 20 ms per sample
+    assert_eq!([20_050, 20_250], result.timestamps_us);
+    assert_eq!([0, 200], result.skew_us);
+
+    for sensor in group.destroy() {
+        sensor.destroy();
+    }
+    bus.into_inner().done();
+}
+
+#[test]
+fn discards_samples_of_earlier_device() {
+    let mut transactions = into_heart_rate_transactions(DEV_ADDR);
+    transactions.extend(into_heart_rate_transactions(OTHER_ADDR));
+    transactions.extend([
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(OTHER_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![3, 0, 0]),
+        I2cTrans::write_read(OTHER_ADDR, vec![Reg::FIFO_WR_PTR], vec![1, 0, 0]),
+        // discard two samples
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_DATA], vec![0, 0, 8, 0, 0, 16]),
+        // aligned read
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_DATA], vec![0, 0, 24]),
+        I2cTrans::write_read(OTHER_ADDR, vec![Reg::FIFO_DATA], vec![0, 0, 32]),
+    ]);
+    let bus = RefCell::new(I2cMock::new(&transactions));
+    let finger = Max3010x::new_max30102(RefCellDevice::new(&bus));
+    let earlobe = Max3010x::new_max30102_with_address(RefCellDevice::new(&bus), OTHER_ADDR);
+    let finger = finger.into_heart_rate().unwrap();
+    let earlobe = earlobe.into_heart_rate().unwrap();
+    let mut group = SensorGroup::new([finger, earlobe]);
+    group.start(clock(&[0, 0, 40_000, 40_000])).unwrap();
+
+    let mut finger_data = [0; 4];
+    let mut earlobe_data = [0; 4];
+    let result = group.read([&mut finger_data, &mut earlobe_data]).unwrap();
+    assert_eq!(1, result.sample_count);
+    assert_eq!(3, finger_data[0]);
+    assert_eq!(4, earlobe_data[0]);
+    assert_eq!([60_000, 60_000], result.timestamps_us);
+    assert_eq!([0, 0], result.skew_us);
+
+    for sensor in group.destroy() {
+        sensor.destroy();
+    }
+    bus.into_inner().done();
+}
+
+fn single_sensor_group(transactions: &[I2cTrans]) -> SensorGroup<I2cMock, Max30102, HeartRate, 1> {
+    let sensor = Max3010x::new_max30102(I2cMock::new(transactions));
+    SensorGroup::new([sensor.into_heart_rate().unwrap()])
+}
+
+#[test]
+fn accounts_samples_lost_after_fifo_contents() {
+    let mut transactions = into_heart_rate_transactions(DEV_ADDR);
+    transactions.extend([
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        // full FIFO, 3 samples lost
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![5, 3, 5]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_DATA], vec![0; 12]),
+        // 28 old and 2 new samples
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![7, 0, 9]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_DATA], vec![0; 90]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![7, 0, 7]),
+    ]);
+    let mut group = single_sensor_group(&transactions);
+    group.start(clock(&[0, 0])).unwrap();
+
+    let mut data = [0; 32];
+    let result = group.read([&mut data[..4]]).unwrap();
+    assert_eq!(4, result.sample_count);
+    assert_eq!([3], result.lost_sample_counts);
+    assert_eq!([20_000], result.timestamps_us);
+
+    let result = group.read([&mut data]).unwrap();
+    assert_eq!(30, result.sample_count);
+    assert_eq!([0], result.lost_sample_counts);
+    assert_eq!([100_000], result.timestamps_us);
+
+    let result = group.read([&mut data]).unwrap();
+    assert_eq!(0, result.sample_count);
+    assert_eq!([(4 + 30 + 3 + 1) * 20_000], result.timestamps_us);
+
+    let [sensor] = group.destroy();
+    sensor.destroy().done();
+}
+
+#[test]
+fn accounts_samples_lost_before_fifo_contents_with_rollover() {
+    let mut transactions = into_heart_rate_transactions(DEV_ADDR);
+    transactions.extend([
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_CONFIG, BF::FIFO_ROLLOVER_EN]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![7, 2, 7]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_DATA], vec![0; 3]),
+    ]);
+    let mut group = single_sensor_group(&transactions);
+    group.sensors_mut()[0].enable_fifo_rollover().unwrap();
+    group.start(clock(&[0, 0])).unwrap();
+
+    let mut data = [0; 1];
+    let result = group.read([&mut data]).unwrap();
+    assert_eq!(1, result.sample_count);
+    assert_eq!([2], result.lost_sample_counts);
+    assert_eq!([3 * 20_000], result.timestamps_us);
+
+    let [sensor] = group.destroy();
+    sensor.destroy().done();
+}
@@ -2,7 +2,7 @@ extern crate embedded_hal_mock as hal;
 use hal::eh1::i2c::Transaction as I2cTrans;
 extern crate max3010x;
 extern crate nb;
-use max3010x::{LedPulseWidth as LedPw, SamplingRate as SR};
+use max3010x::{AdcRange, LedPulseWidth as LedPw, SamplingRate as SR};
 mod base;
 use base::{destroy, new, BitFlags as BF, Register as Reg, DEV_ADDR};
 
@@ -57,6 +57,20 @@ set_test!(can_set_pw_118, set_pulse_width, LedPw::Pw118, 1);
 set_test!(can_set_pw_215, set_pulse_width, LedPw::Pw215, 2);
 set_test!(can_set_pw_411, set_pulse_width, LedPw::Pw411, 3);
 
+#[test]
+fn can_get_pulse_width() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 2]),
+    ];
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    assert_eq!(LedPw::Pw69, dev.get_pulse_width());
+    dev.set_pulse_width(LedPw::Pw215).unwrap();
+    assert_eq!(LedPw::Pw215, dev.get_pulse_width());
+    destroy(dev);
+}
+
 set_test!(can_set_sr_50, set_sampling_rate, SR::Sps50, 0);
 set_test!(can_set_sr_100, set_sampling_rate, SR::Sps100, 1 << 2);
 set_test!(can_set_sr_200, set_sampling_rate, SR::Sps200, 2 << 2);
@@ -65,3 +79,30 @@ set_test!(can_set_sr_800, set_sampling_rate, SR::Sps800, 4 << 2);
 set_test!(can_set_sr_1000, set_sampling_rate, SR::Sps1000, 5 << 2);
 set_test!(can_set_sr_1600, set_sampling_rate, SR::Sps1600, 6 << 2);
 set_test!(can_set_sr_3200, set_sampling_rate, SR::Sps3200, 7 << 2);
+
+set_test!(adc_rge_2k, set_adc_range, AdcRange::Fs2k, 0);
+set_test!(adc_rge_4k, set_adc_range, AdcRange::Fs4k, 1 << 5);
+set_test!(adc_rge_8k, set_adc_range, AdcRange::Fs8k, 2 << 5);
+set_test!(adc_rge_16k, set_adc_range, AdcRange::Fs16k, 3 << 5);
+
+#[test]
+fn keeps_adc_range_when_changing_mode() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 2 << 5]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b011]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 2 << 5 | 1 << 2]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b111]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 2 << 5 | 1 << 2 | 3]),
+    ];
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    dev.set_adc_range(AdcRange::Fs8k).unwrap();
+    let mut dev = dev.into_oximeter().unwrap();
+    dev.set_sampling_rate(SR::Sps100).unwrap();
+    let mut dev = dev.into_multi_led().unwrap();
+    dev.set_pulse_width(LedPw::Pw411).unwrap();
+    destroy(dev);
+}
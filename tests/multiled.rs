@@ -2,9 +2,13 @@ extern crate embedded_hal_mock as hal;
 use hal::eh1::i2c::Transaction as I2cTrans;
 extern crate max3010x;
 extern crate nb;
-use max3010x::{LedPulseWidth as LedPw, SamplingRate as SR, TimeSlot};
+use max3010x::{
+    recording::{Recorder, RecordingConfig, ReplayI2c},
+    AdcRange, Error, LedPulseWidth as LedPw, Max3010x, ModeChangePolicy, SampleAveraging,
+    SamplingRate as SR, TimeSlot,
+};
 mod base;
-use base::{destroy, new, Register as Reg, DEV_ADDR};
+use base::{destroy, new, BitFlags as BF, Register as Reg, DEV_ADDR};
 
 #[test]
 fn can_change_into_multi_led() {
@@ -106,3 +110,233 @@ set_test!(can_set_sr_800, set_sampling_rate, SR::Sps800, 4 << 2);
 set_test!(can_set_sr_1000, set_sampling_rate, SR::Sps1000, 5 << 2);
 set_test!(can_set_sr_1600, set_sampling_rate, SR::Sps1600, 6 << 2);
 set_test!(can_set_sr_3200, set_sampling_rate, SR::Sps3200, 7 << 2);
+
+#[test]
+fn cannot_set_sr_invalid_for_slots() {
+    // Exemplary integration test. All other combinations tested in unit tests
+    use TimeSlot::*;
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b111]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SLOT_CONFIG0, 2 << 4 | 1, 0]),
+    ];
+    let dev = new(&transactions);
+    let mut dev = dev.into_multi_led().unwrap();
+    dev.set_led_time_slots([Led1, Led2, Disabled, Disabled])
+        .unwrap();
+    assert_unsupported_config!(dev.set_sampling_rate(SR::Sps3200), LedPw::Pw69, SR::Sps3200);
+    destroy(dev);
+}
+
+#[test]
+fn does_not_check_sr_for_more_than_two_slots() {
+    use TimeSlot::*;
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b111]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SLOT_CONFIG0, 2 << 4 | 1, 1]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 7 << 2]),
+    ];
+    let dev = new(&transactions);
+    let mut dev = dev.into_multi_led().unwrap();
+    dev.set_led_time_slots([Led1, Led2, Led1, Disabled])
+        .unwrap();
+    dev.set_sampling_rate(SR::Sps3200).unwrap();
+    destroy(dev);
+}
+
+#[test]
+fn cannot_set_pw_invalid_for_slots() {
+    use TimeSlot::*;
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b111]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SLOT_CONFIG0, 2 << 4 | 1, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 4 << 2]),
+    ];
+    let dev = new(&transactions);
+    let mut dev = dev.into_multi_led().unwrap();
+    dev.set_led_time_slots([Led1, Led2, Disabled, Disabled])
+        .unwrap();
+    dev.set_sampling_rate(SR::Sps800).unwrap();
+    assert_unsupported_config!(dev.set_pulse_width(LedPw::Pw411), LedPw::Pw411, SR::Sps800);
+    destroy(dev);
+}
+
+#[test]
+fn cannot_enable_led_slots_invalid_for_sr_and_pw() {
+    use TimeSlot::*;
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b111]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 7 << 2]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SLOT_CONFIG0, 1, 0]),
+    ];
+    let dev = new(&transactions);
+    let mut dev = dev.into_multi_led().unwrap();
+    dev.set_sampling_rate(SR::Sps3200).unwrap();
+    dev.set_led_time_slots([Led1, Disabled, Disabled, Disabled])
+        .unwrap();
+    assert_unsupported_config!(
+        dev.set_led_time_slots([Led1, Led2, Disabled, Disabled]),
+        LedPw::Pw69,
+        SR::Sps3200
+    );
+    destroy(dev);
+}
+
+fn two_slots_then_heart_rate_at_3200_transactions() -> Vec<I2cTrans> {
+    vec![
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b111]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SLOT_CONFIG0, 2 << 4 | 1, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 7 << 2]),
+    ]
+}
+
+#[test]
+fn cannot_change_into_multi_led_with_unsupported_config_for_slots() {
+    use TimeSlot::*;
+    let mut dev = new(&two_slots_then_heart_rate_at_3200_transactions())
+        .into_multi_led()
+        .unwrap();
+    dev.set_led_time_slots([Led1, Led2, Disabled, Disabled])
+        .unwrap();
+    let mut dev = dev.into_heart_rate().unwrap();
+    dev.set_sampling_rate(SR::Sps3200).unwrap();
+    let error = match dev.into_multi_led() {
+        Err(error) => error,
+        Ok(_) => panic!("Did not return an error"),
+    };
+    match error.error {
+        Error::UnsupportedSamplingConfiguration {
+            pulse_width: LedPw::Pw69,
+            sampling_rate: SR::Sps3200,
+        } => (),
+        _ => panic!("Did not return Error::UnsupportedSamplingConfiguration"),
+    }
+    destroy(error.dev);
+}
+
+#[test]
+fn reduces_sampling_rate_for_slots_when_changing_into_multi_led() {
+    use TimeSlot::*;
+    let mut transactions = two_slots_then_heart_rate_at_3200_transactions();
+    transactions.extend([
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 6 << 2]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b111]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ]);
+    let mut dev = new(&transactions).into_multi_led().unwrap();
+    dev.set_led_time_slots([Led1, Led2, Disabled, Disabled])
+        .unwrap();
+    let mut dev = dev.into_heart_rate().unwrap();
+    dev.set_sampling_rate(SR::Sps3200).unwrap();
+    let dev = dev
+        .into_multi_led_with_policy(ModeChangePolicy::ReduceSamplingRate)
+        .unwrap();
+    destroy(dev);
+}
+
+set_test!(adc_rge_2k, set_adc_range, AdcRange::Fs2k, 0);
+set_test!(adc_rge_4k, set_adc_range, AdcRange::Fs4k, 1 << 5);
+set_test!(adc_rge_8k, set_adc_range, AdcRange::Fs8k, 2 << 5);
+set_test!(adc_rge_16k, set_adc_range, AdcRange::Fs16k, 3 << 5);
+
+set_in_mode_test!(
+    enable_new_fifo_data_ready_interrupt,
+    into_multi_led,
+    0b111,
+    enable_new_fifo_data_ready_interrupt,
+    [],
+    INT_EN1,
+    BF::PPG_RDY_INT
+);
+set_in_mode_test!(
+    disable_new_fifo_data_ready_interrupt,
+    into_multi_led,
+    0b111,
+    disable_new_fifo_data_ready_interrupt,
+    [],
+    INT_EN1,
+    0
+);
+
+/// Replay `sample_count` samples with the given slots, each channel value
+/// being its index, and read them back through the driver.
+fn replay_slots(slots: [TimeSlot; 4], sample_count: usize) {
+    let config = RecordingConfig {
+        mode: 0b111,
+        sampling_rate: SR::Sps100,
+        pulse_width: LedPw::Pw411,
+        adc_range: AdcRange::Fs4k,
+        sample_averaging: SampleAveraging::Sa1,
+        slots,
+        led1_amplitude: 10,
+        led2_amplitude: 10,
+    };
+    let channels = usize::from(config.channel_count());
+    let values: Vec<u32> = (1..=(sample_count * channels) as u32).collect();
+    let bytes: Vec<u8> = values
+        .iter()
+        .flat_map(|value| [0, (value >> 8) as u8, *value as u8])
+        .collect();
+    let mut recorder = Recorder::new();
+    let mut recording = vec![0; 512];
+    let mut len = recorder.write_header(&config, &mut recording).unwrap();
+    len += recorder
+        .write_read(0, 0, &bytes, &mut recording[len..])
+        .unwrap();
+    recording.truncate(len);
+
+    let replay = ReplayI2c::new(&recording).unwrap();
+    let mut dev = Max3010x::new_max30102(replay).into_multi_led().unwrap();
+    dev.apply_recording_config(&config).unwrap();
+    dev.enable_new_fifo_data_ready_interrupt().unwrap();
+    let mut data = vec![0; 32 * 4];
+    let read = dev.read_fifo(&mut data).unwrap();
+    assert_eq!(sample_count, usize::from(read));
+    assert_eq!(values, data[..sample_count * channels]);
+    assert!(data[sample_count * channels..].iter().all(|v| *v == 0));
+    assert!(dev.destroy().is_finished());
+}
+
+#[test]
+fn reads_samples_with_one_slot() {
+    use TimeSlot::*;
+    replay_slots([Led1, Disabled, Disabled, Disabled], 5);
+}
+
+#[test]
+fn reads_samples_with_two_slots() {
+    use TimeSlot::*;
+    replay_slots([Led1, Led2, Disabled, Disabled], 5);
+}
+
+#[test]
+fn reads_samples_with_three_slots() {
+    use TimeSlot::*;
+    replay_slots([Led1, Led2, Led1, Disabled], 5);
+}
+
+#[test]
+fn reads_samples_with_four_slots_in_several_transfers() {
+    use TimeSlot::*;
+    replay_slots([Led1, Led2, Led2, Led1], 20);
+}
+
+#[test]
+fn reads_nothing_without_slots() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b111]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ];
+    let mut dev = new(&transactions).into_multi_led().unwrap();
+    let mut data = [0; 4];
+    assert_eq!(0, dev.read_fifo(&mut data).unwrap());
+    let mut bytes = [0; 12];
+    assert_eq!(0, dev.read_fifo_raw(&mut bytes).unwrap());
+    destroy(dev);
+}
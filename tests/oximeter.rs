@@ -1,7 +1,9 @@
+extern crate embedded_hal;
 extern crate embedded_hal_mock as hal;
+use embedded_hal::i2c::ErrorKind;
 use hal::eh1::i2c::Transaction as I2cTrans;
 extern crate max3010x;
-use max3010x::{AdcRange, LedPulseWidth as LedPw, SamplingRate as SR};
+use max3010x::{AdcRange, Error, LedPulseWidth as LedPw, ModeChangePolicy, SamplingRate as SR};
 mod base;
 use base::{destroy, new, BitFlags as BF, Register as Reg, DEV_ADDR};
 
@@ -70,6 +72,112 @@ fn cannot_set_sr_3200() {
     ];
     let dev = new(&transactions);
     let mut dev = dev.into_oximeter().unwrap();
-    assert_invalid_args!(dev.set_sampling_rate(SR::Sps3200));
+    assert_unsupported_config!(dev.set_sampling_rate(SR::Sps3200), LedPw::Pw69, SR::Sps3200);
     destroy(dev);
 }
+
+fn heart_rate_transactions(spo2_config: u8) -> Vec<I2cTrans> {
+    vec![
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b10]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, spo2_config & 0b1_1100]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, spo2_config]),
+    ]
+}
+
+#[test]
+fn cannot_change_into_oximeter_with_unsupported_config() {
+    let mut dev = new(&heart_rate_transactions(6 << 2 | 1))
+        .into_heart_rate()
+        .unwrap();
+    dev.set_sampling_rate(SR::Sps1600).unwrap();
+    dev.set_pulse_width(LedPw::Pw118).unwrap();
+    let error = match dev.into_oximeter() {
+        Err(error) => error,
+        Ok(_) => panic!("Did not return an error"),
+    };
+    match error.error {
+        Error::UnsupportedSamplingConfiguration {
+            pulse_width: LedPw::Pw118,
+            sampling_rate: SR::Sps1600,
+        } => (),
+        _ => panic!("Did not return Error::UnsupportedSamplingConfiguration"),
+    }
+    destroy(error.dev);
+}
+
+fn change_into_oximeter_with_policy(
+    sampling_rate: SR,
+    pulse_width: LedPw,
+    hr_spo2_config: u8,
+    policy: ModeChangePolicy,
+    adjusted_spo2_config: u8,
+) {
+    let mut transactions = heart_rate_transactions(hr_spo2_config);
+    transactions.extend([
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, adjusted_spo2_config]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b11]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ]);
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    dev.set_sampling_rate(sampling_rate).unwrap();
+    dev.set_pulse_width(pulse_width).unwrap();
+    let dev = dev.into_oximeter_with_policy(policy).unwrap();
+    destroy(dev);
+}
+
+#[test]
+fn reduces_sampling_rate_when_changing_into_oximeter() {
+    change_into_oximeter_with_policy(
+        SR::Sps1600,
+        LedPw::Pw118,
+        6 << 2 | 1,
+        ModeChangePolicy::ReduceSamplingRate,
+        5 << 2 | 1,
+    );
+}
+
+#[test]
+fn reduces_pulse_width_when_changing_into_oximeter() {
+    change_into_oximeter_with_policy(
+        SR::Sps1600,
+        LedPw::Pw215,
+        6 << 2 | 2,
+        ModeChangePolicy::ReducePulseWidth,
+        6 << 2,
+    );
+}
+
+#[test]
+fn reduces_pulse_width_and_sampling_rate_when_changing_into_oximeter() {
+    change_into_oximeter_with_policy(
+        SR::Sps3200,
+        LedPw::Pw69,
+        7 << 2,
+        ModeChangePolicy::ReducePulseWidth,
+        6 << 2,
+    );
+}
+
+#[test]
+fn restores_sampling_rate_if_mode_change_fails() {
+    let mut transactions = heart_rate_transactions(6 << 2 | 1);
+    transactions.extend([
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 5 << 2 | 1]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b11]).with_error(ErrorKind::Other),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 6 << 2 | 1]),
+    ]);
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    dev.set_sampling_rate(SR::Sps1600).unwrap();
+    dev.set_pulse_width(LedPw::Pw118).unwrap();
+    let error = match dev.into_oximeter_with_policy(ModeChangePolicy::ReduceSamplingRate) {
+        Err(error) => error,
+        Ok(_) => panic!("Did not return an error"),
+    };
+    match error.error {
+        Error::I2C(ErrorKind::Other) => (),
+        _ => panic!("Did not return Error::I2C"),
+    }
+    assert_eq!(1600.0, error.dev.get_effective_sampling_rate());
+    destroy(error.dev);
+}
@@ -0,0 +1,107 @@
+extern crate embedded_hal_mock as hal;
+use hal::eh1::i2c::Transaction as I2cTrans;
+extern crate max3010x;
+use max3010x::{Led, LedPulseWidth as LedPw, PowerConfig, SamplingRate as SR};
+mod base;
+use crate::base::{destroy, new, BitFlags as BF, Register as Reg, DEV_ADDR};
+
+fn assert_near(expected: f32, actual: f32) {
+    assert!((expected - actual).abs() < 0.01, "{} {}", expected, actual);
+}
+
+const CONFIG: PowerConfig = PowerConfig {
+    led1_amplitude: 50,
+    led2_amplitude: 25,
+    pulse_width: LedPw::Pw411,
+    sampling_rate: SR::Sps100,
+    led1_pulses: 1,
+    led2_pulses: 1,
+    shutdown: false,
+};
+
+#[test]
+fn pulse_width_durations() {
+    assert_eq!(69, LedPw::Pw69.microseconds());
+    assert_eq!(118, LedPw::Pw118.microseconds());
+    assert_eq!(215, LedPw::Pw215.microseconds());
+    assert_eq!(411, LedPw::Pw411.microseconds());
+}
+
+#[test]
+fn can_calculate_led_current() {
+    // 10 mA * 411 µs * 100 sps + 5 mA * 411 µs * 100 sps
+    assert_near(616.5, CONFIG.led_current_ua());
+    assert_near(1216.5, CONFIG.supply_current_ua());
+}
+
+#[test]
+fn led_current_scales_with_pulses_per_sample() {
+    let config = PowerConfig {
+        pulse_width: LedPw::Pw118,
+        sampling_rate: SR::Sps400,
+        led1_pulses: 2,
+        led2_pulses: 0,
+        ..CONFIG
+    };
+    // 2 * 10 mA * 118 µs * 400 sps
+    assert_near(944.0, config.led_current_ua());
+}
+
+#[test]
+fn no_current_drawn_by_leds_in_shutdown() {
+    let config = PowerConfig {
+        shutdown: true,
+        ..CONFIG
+    };
+    assert_near(0.0, config.led_current_ua());
+    assert_near(0.7, config.supply_current_ua());
+}
+
+#[test]
+fn can_get_power_config_in_oximeter_mode() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b011]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED1_PA, 50]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED2_PA, 25]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 3]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 1 << 2 | 3]),
+    ];
+    let mut dev = new(&transactions).into_oximeter().unwrap();
+    dev.set_pulse_amplitude(Led::Led1, 50).unwrap();
+    dev.set_pulse_amplitude(Led::Led2, 25).unwrap();
+    dev.set_pulse_width(LedPw::Pw411).unwrap();
+    dev.set_sampling_rate(SR::Sps100).unwrap();
+    assert_eq!(CONFIG, dev.get_power_config());
+    assert_near(1216.5, dev.estimate_supply_current_ua());
+    destroy(dev);
+}
+
+#[test]
+fn only_led1_is_active_in_heart_rate_mode() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED2_PA, 25]),
+    ];
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    dev.set_pulse_amplitude(Led::Led2, 25).unwrap();
+    let config = dev.get_power_config();
+    assert_eq!((1, 0), (config.led1_pulses, config.led2_pulses));
+    assert_near(600.0, dev.estimate_supply_current_ua());
+    destroy(dev);
+}
+
+#[test]
+fn reports_shutdown_state() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, BF::SHUTDOWN | 0b010]),
+    ];
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    dev.shutdown().unwrap();
+    assert!(dev.get_power_config().shutdown);
+    assert_near(0.7, dev.estimate_supply_current_ua());
+    destroy(dev);
+}
@@ -0,0 +1,257 @@
+extern crate embedded_hal_mock as hal;
+use hal::eh1::i2c::Transaction as I2cTrans;
+extern crate max3010x;
+use max3010x::{
+    recording::{
+        RecordError, Recorder, RecordingConfig, RecordingError, RecordingReader, ReplayError,
+        ReplayI2c,
+    },
+    AdcRange, Error, LedPulseWidth, Max3010x, SampleAveraging, SamplingRate, TimeSlot,
+};
+mod base;
+use crate::base::{destroy, new, Register as Reg, DEV_ADDR};
+
+const OXIMETER: RecordingConfig = RecordingConfig {
+    mode: 0b011,
+    sampling_rate: SamplingRate::Sps100,
+    pulse_width: LedPulseWidth::Pw411,
+    adc_range: AdcRange::Fs8k,
+    sample_averaging: SampleAveraging::Sa4,
+    slots: [TimeSlot::Disabled; 4],
+    led1_amplitude: 30,
+    led2_amplitude: 40,
+};
+
+fn record(config: &RecordingConfig, reads: &[(u64, u8, &[u8])]) -> Vec<u8> {
+    let mut recorder = Recorder::new();
+    let mut buffer = [0; 256];
+    let len = recorder.write_header(config, &mut buffer).unwrap();
+    let mut recording = buffer[..len].to_vec();
+    for (timestamp_us, overflow_count, fifo_bytes) in reads {
+        let len = recorder
+            .write_read(*timestamp_us, *overflow_count, fifo_bytes, &mut buffer)
+            .unwrap();
+        recording.extend_from_slice(&buffer[..len]);
+    }
+    recording
+}
+
+#[test]
+fn can_read_back_recording() {
+    let recording = record(
+        &OXIMETER,
+        &[(1000, 0, &[1, 2, 3, 4, 5, 6]), (41_000, 3, &[])],
+    );
+    let mut reader = RecordingReader::new(&recording).unwrap();
+    assert_eq!(OXIMETER, reader.config());
+    let read = reader.next().unwrap().unwrap();
+    assert_eq!(1000, read.timestamp_us);
+    assert_eq!(0, read.overflow_count);
+    assert_eq!(&[1, 2, 3, 4, 5, 6], read.fifo_bytes);
+    let read = reader.next().unwrap().unwrap();
+    assert_eq!(41_000, read.timestamp_us);
+    assert_eq!(3, read.overflow_count);
+    assert!(read.fifo_bytes.is_empty());
+    assert!(reader.next().is_none());
+}
+
+#[test]
+fn rejects_invalid_recordings() {
+    let recording = record(&OXIMETER, &[(0, 0, &[1, 2, 3, 4, 5, 6])]);
+    let mut invalid = recording.clone();
+    invalid[0] = b'X';
+    assert_eq!(
+        RecordingError::InvalidHeader,
+        RecordingReader::new(&invalid).unwrap_err()
+    );
+    let mut invalid = recording.clone();
+    invalid[4] = 2;
+    assert_eq!(
+        RecordingError::UnsupportedVersion(2),
+        RecordingReader::new(&invalid).unwrap_err()
+    );
+    let truncated = &recording[..recording.len() - 1];
+    let mut reader = RecordingReader::new(truncated).unwrap();
+    assert_eq!(Some(Err(RecordingError::Truncated)), reader.next());
+    assert!(reader.next().is_none());
+}
+
+#[test]
+fn cannot_write_into_small_buffer() {
+    let mut buffer = [0; 10];
+    let mut recorder = Recorder::new();
+    assert_eq!(
+        Err(RecordingError::BufferTooSmall),
+        recorder.write_header(&OXIMETER, &mut buffer)
+    );
+    assert_eq!(
+        Err(RecordingError::BufferTooSmall),
+        recorder.write_read(0, 0, &[0; 6], &mut buffer)
+    );
+}
+
+#[test]
+fn cannot_write_header_without_sampling_mode() {
+    let dev = new(&[]);
+    let config = RecordingConfig::from_sensor(&dev);
+    assert_eq!(
+        Err(RecordingError::InvalidConfig),
+        Recorder::new().write_header(&config, &mut [0; 16])
+    );
+    destroy(dev);
+}
+
+#[test]
+fn can_record_fifo() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b011]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![3, 2, 1]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_DATA], (1..=12).collect()),
+    ];
+    let mut dev = new(&transactions).into_oximeter().unwrap();
+    let config = RecordingConfig::from_sensor(&dev);
+    assert_eq!(0b011, config.mode);
+    assert_eq!(2, config.channel_count());
+    let mut recorder = Recorder::new();
+    let mut buffer = [0; 32];
+    let len = dev.record_fifo(&mut recorder, 0x0102, &mut buffer).unwrap();
+    assert_eq!(19, len);
+    assert_eq!([0x02, 0x01, 0, 0, 2, 12, 0], buffer[..7]);
+    assert_eq!((1..=12).collect::<Vec<u8>>(), buffer[7..19]);
+    destroy(dev);
+}
+
+#[test]
+fn records_only_samples_fitting_in_buffer() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![5, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_DATA], vec![1, 2, 3, 4, 5, 6]),
+    ];
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    let mut buffer = [0; 15];
+    let len = dev
+        .record_fifo(&mut Recorder::new(), 0, &mut buffer)
+        .unwrap();
+    assert_eq!(13, len);
+    destroy(dev);
+}
+
+#[test]
+fn does_not_read_fifo_if_time_gap_is_too_large() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![1, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_DATA], vec![1, 2, 3]),
+    ];
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    let mut recorder = Recorder::new();
+    let mut buffer = [0; 16];
+    match dev.record_fifo(&mut recorder, 1 << 32, &mut buffer) {
+        Err(RecordError::Recording(RecordingError::TimeGapTooLarge)) => (),
+        _ => panic!("should have failed"),
+    }
+    assert_eq!(
+        10,
+        dev.record_fifo(&mut recorder, 1000, &mut buffer).unwrap()
+    );
+    assert_eq!([1, 2, 3], buffer[7..10]);
+    destroy(dev);
+}
+
+#[test]
+fn replays_recording_to_driver() {
+    let recording = record(
+        &OXIMETER,
+        &[
+            (1000, 0, &[0, 0, 1, 0, 0, 2, 0, 0, 3, 0, 0, 4]),
+            (2000, 0, &[]),
+            (3000, 5, &[0, 0, 5, 0, 0, 6]),
+        ],
+    );
+    let replay = ReplayI2c::new(&recording).unwrap();
+    let config = replay.config();
+    let mut dev = Max3010x::new_max30102(replay).into_oximeter().unwrap();
+    dev.apply_recording_config(&config).unwrap();
+    assert_eq!(25.0, dev.get_effective_sampling_rate());
+    assert_eq!(0x15, dev.get_part_id().unwrap());
+
+    let mut data = [0; 2];
+    assert_eq!(0, dev.get_overflow_sample_count().unwrap());
+    assert_eq!(2, dev.get_available_sample_count().unwrap());
+    assert_eq!(1, dev.read_fifo(&mut data).unwrap());
+    assert_eq!([1, 2], data);
+    assert_eq!(1, dev.read_fifo(&mut data).unwrap());
+    assert_eq!([3, 4], data);
+    assert_eq!(5, dev.get_overflow_sample_count().unwrap());
+    assert_eq!(1, dev.read_fifo(&mut data).unwrap());
+    assert_eq!([5, 6], data);
+    assert_eq!(0, dev.read_fifo(&mut data).unwrap());
+    let replay = dev.destroy();
+    assert!(replay.is_finished());
+    assert_eq!(3000, replay.timestamp_us());
+}
+
+#[test]
+fn replays_recording_at_custom_address() {
+    let recording = record(&OXIMETER, &[(1000, 0, &[0, 0, 1, 0, 0, 2])]);
+    let replay = ReplayI2c::with_address(&recording, 0x58).unwrap();
+    let mut dev = Max3010x::new_max30102_with_address(replay, 0x58)
+        .into_oximeter()
+        .unwrap();
+    dev.apply_recording_config(&OXIMETER).unwrap();
+    let mut data = [0; 2];
+    assert_eq!(1, dev.read_fifo(&mut data).unwrap());
+    assert_eq!([1, 2], data);
+    assert!(dev.destroy().is_finished());
+}
+
+#[test]
+fn replay_rejects_other_address() {
+    let recording = record(&OXIMETER, &[]);
+    let replay = ReplayI2c::new(&recording).unwrap();
+    let mut dev = Max3010x::new_max30102_with_address(replay, 0x58);
+    match dev.get_part_id() {
+        Err(Error::I2C(ReplayError::Address(0x58))) => (),
+        _ => panic!("should have failed"),
+    }
+}
+
+#[test]
+fn replays_multi_led_recording() {
+    let config = RecordingConfig {
+        mode: 0b111,
+        slots: [
+            TimeSlot::Led2,
+            TimeSlot::Led1,
+            TimeSlot::Disabled,
+            TimeSlot::Disabled,
+        ],
+        ..OXIMETER
+    };
+    let recording = record(&config, &[(0, 0, &[0, 0, 7, 0, 0, 8])]);
+    let replay = ReplayI2c::new(&recording).unwrap();
+    assert_eq!(2, replay.config().channel_count());
+    let mut dev = Max3010x::new_max30102(replay).into_multi_led().unwrap();
+    dev.apply_recording_config(&config).unwrap();
+    let mut data = [0; 4];
+    assert_eq!(1, dev.read_fifo(&mut data).unwrap());
+    assert_eq!([7, 8, 0, 0], data);
+    let dump = dev.dump_registers().unwrap();
+    assert!(dev.diff_registers(&dump).is_empty());
+    assert!(dev.destroy().is_finished());
+}
+
+#[test]
+fn cannot_apply_config_of_other_mode() {
+    let recording = record(&OXIMETER, &[]);
+    let replay = ReplayI2c::new(&recording).unwrap();
+    let mut dev = Max3010x::new_max30102(replay).into_heart_rate().unwrap();
+    match dev.apply_recording_config(&OXIMETER) {
+        Err(Error::InvalidArguments) => (),
+        _ => panic!("should have failed"),
+    }
+}
@@ -0,0 +1,82 @@
+extern crate embedded_hal_mock as hal;
+use hal::eh1::i2c::Transaction as I2cTrans;
+extern crate max3010x;
+use max3010x::{Led, SampleAveraging, TimeSlot};
+mod base;
+use crate::base::{destroy, new, BitFlags as BF, Register as Reg, DEV_ADDR};
+
+fn status(status1: u8) -> I2cTrans {
+    I2cTrans::write_read(DEV_ADDR, vec![Reg::INT_STATUS], vec![status1, 0])
+}
+
+#[test]
+fn does_nothing_without_power_ready() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        status(BF::FIFO_A_FULL_INT),
+    ];
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    let check = dev.check_and_recover().unwrap();
+    assert!(!check.recovered);
+    assert!(check.status.fifo_almost_full);
+    destroy(dev);
+}
+
+#[test]
+fn restores_configuration_after_power_ready() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b111]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_CONFIG, 0b0100_0000]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED1_PA, 50]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED2_PA, 25]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SLOT_CONFIG0, 2 << 4 | 1, 1]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::INT_EN1, BF::ALC_OVF_INT]),
+        status(BF::PWR_RDY_INT),
+        I2cTrans::write(DEV_ADDR, vec![Reg::INT_EN1, BF::ALC_OVF_INT, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_CONFIG, 0b0100_0000]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED1_PA, 50, 25]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SLOT_CONFIG0, 2 << 4 | 1, 1]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b111]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ];
+    let mut dev = new(&transactions).into_multi_led().unwrap();
+    dev.set_sample_averaging(SampleAveraging::Sa4).unwrap();
+    dev.set_pulse_amplitude(Led::Led1, 50).unwrap();
+    dev.set_pulse_amplitude(Led::Led2, 25).unwrap();
+    dev.set_led_time_slots([
+        TimeSlot::Led1,
+        TimeSlot::Led2,
+        TimeSlot::Led1,
+        TimeSlot::Disabled,
+    ])
+    .unwrap();
+    dev.enable_alc_overflow_interrupt().unwrap();
+    let check = dev.check_and_recover().unwrap();
+    assert!(check.recovered);
+    assert!(check.status.power_ready);
+    destroy(dev);
+}
+
+#[test]
+fn restores_shutdown_state() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b011]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, BF::SHUTDOWN | 0b011]),
+        status(BF::PWR_RDY_INT),
+        I2cTrans::write(DEV_ADDR, vec![Reg::INT_EN1, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_CONFIG, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED1_PA, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SLOT_CONFIG0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, BF::SHUTDOWN | 0b011]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ];
+    let mut dev = new(&transactions).into_oximeter().unwrap();
+    dev.shutdown().unwrap();
+    assert!(dev.check_and_recover().unwrap().recovered);
+    destroy(dev);
+}
@@ -0,0 +1,254 @@
+extern crate embedded_hal_mock as hal;
+use hal::eh1::{delay::NoopDelay, i2c::Transaction as I2cTrans};
+extern crate max3010x;
+use max3010x::{
+    DutyCycle, DutyCycleScheduler, Error, Led, LedPulseWidth as LedPw, SamplingRate as SR, TimeSlot,
+};
+mod base;
+use crate::base::{destroy, new, BitFlags as BF, Register as Reg, DEV_ADDR};
+
+const DUTY_CYCLE: DutyCycle = DutyCycle {
+    settling_ms: 100,
+    acquisition_ms: 40,
+    sleep_ms: 1000,
+};
+
+fn fifo_read(sample_count: u8, data: Vec<u8>) -> [I2cTrans; 2] {
+    [
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![sample_count, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_DATA], data),
+    ]
+}
+
+fn assert_near(expected: f32, actual: f32) {
+    assert!((expected - actual).abs() < 0.01, "{} {}", expected, actual);
+}
+
+#[test]
+fn default_duty_cycle_is_one_minute() {
+    assert_eq!(60_000, DutyCycle::default().period_ms());
+}
+
+#[test]
+fn can_run_cycle_with_ticks() {
+    let mut transactions = vec![
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ];
+    transactions.extend(fifo_read(1, vec![0, 0, 8]));
+    transactions.extend(fifo_read(1, vec![0, 0, 16]));
+    transactions.push(I2cTrans::write(
+        DEV_ADDR,
+        vec![Reg::MODE, BF::SHUTDOWN | 0b010],
+    ));
+    transactions.push(I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]));
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    let mut scheduler = DutyCycleScheduler::new(DUTY_CYCLE);
+    let mut buffer = [0; 8];
+    assert_eq!(2, scheduler.sample_count(&dev));
+    assert_eq!(None, scheduler.poll(&mut dev, 0, &mut buffer).unwrap());
+    assert_eq!(None, scheduler.poll(&mut dev, 50, &mut buffer).unwrap());
+    assert_eq!(None, scheduler.poll(&mut dev, 100, &mut buffer).unwrap());
+    assert_eq!(None, scheduler.poll(&mut dev, 120, &mut buffer).unwrap());
+    assert_eq!(Some(2), scheduler.poll(&mut dev, 140, &mut buffer).unwrap());
+    assert_eq!([1, 2], buffer[..2]);
+    assert!(scheduler.is_sleeping());
+    assert_eq!(None, scheduler.poll(&mut dev, 1000, &mut buffer).unwrap());
+    assert_eq!(None, scheduler.poll(&mut dev, 1140, &mut buffer).unwrap());
+    assert!(!scheduler.is_sleeping());
+    destroy(dev);
+}
+
+#[test]
+fn can_run_cycle_with_delay() {
+    let mut transactions = vec![
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ];
+    transactions.extend(fifo_read(1, vec![0, 0, 8]));
+    transactions.extend(fifo_read(1, vec![0, 0, 16]));
+    transactions.push(I2cTrans::write(
+        DEV_ADDR,
+        vec![Reg::MODE, BF::SHUTDOWN | 0b010],
+    ));
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    let mut scheduler = DutyCycleScheduler::new(DUTY_CYCLE);
+    let mut buffer = [0; 8];
+    let sum = scheduler
+        .run_cycle(&mut dev, &mut NoopDelay, &mut buffer, |samples| {
+            assert_eq!([1, 2], samples);
+            samples.iter().sum::<u32>()
+        })
+        .unwrap();
+    assert_eq!(3, sum);
+    destroy(dev);
+}
+
+#[test]
+fn completes_cycle_if_buffer_shrinks() {
+    let mut transactions = vec![
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ];
+    transactions.extend(fifo_read(1, vec![0, 0, 8]));
+    transactions.push(I2cTrans::write(
+        DEV_ADDR,
+        vec![Reg::MODE, BF::SHUTDOWN | 0b010],
+    ));
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    let mut scheduler = DutyCycleScheduler::new(DUTY_CYCLE);
+    let mut buffer = [0; 8];
+    assert_eq!(None, scheduler.poll(&mut dev, 0, &mut buffer).unwrap());
+    assert_eq!(None, scheduler.poll(&mut dev, 100, &mut buffer).unwrap());
+    assert_eq!(None, scheduler.poll(&mut dev, 120, &mut buffer).unwrap());
+    let result = scheduler.poll(&mut dev, 140, &mut buffer[..1]).unwrap();
+    assert_eq!(Some(1), result);
+    assert_eq!(1, buffer[0]);
+    assert!(scheduler.is_sleeping());
+    destroy(dev);
+}
+
+#[test]
+fn run_cycle_times_out_if_samples_do_not_arrive() {
+    let mut transactions = vec![
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ];
+    // 2 samples at 50 SPS are waited for up to 80 ms: 0, 40 and 80 ms.
+    for _ in 0..3 {
+        transactions.push(I2cTrans::write_read(
+            DEV_ADDR,
+            vec![Reg::FIFO_WR_PTR],
+            vec![0, 0, 0],
+        ));
+    }
+    transactions.push(I2cTrans::write(
+        DEV_ADDR,
+        vec![Reg::MODE, BF::SHUTDOWN | 0b010],
+    ));
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    let mut scheduler = DutyCycleScheduler::new(DUTY_CYCLE);
+    let mut buffer = [0; 8];
+    let result = scheduler.run_cycle(&mut dev, &mut NoopDelay, &mut buffer, |_| {
+        panic!("Measured without samples")
+    });
+    assert!(matches!(result, Err(Error::Timeout)));
+    destroy(dev);
+}
+
+#[test]
+fn poll_times_out_if_samples_do_not_arrive() {
+    let mut transactions = vec![
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ];
+    transactions.extend(fifo_read(1, vec![0, 0, 8]));
+    transactions.push(I2cTrans::write_read(
+        DEV_ADDR,
+        vec![Reg::FIFO_WR_PTR],
+        vec![0, 0, 0],
+    ));
+    transactions.push(I2cTrans::write(
+        DEV_ADDR,
+        vec![Reg::MODE, BF::SHUTDOWN | 0b010],
+    ));
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    let mut scheduler = DutyCycleScheduler::new(DUTY_CYCLE);
+    let mut buffer = [0; 8];
+    assert_eq!(None, scheduler.poll(&mut dev, 0, &mut buffer).unwrap());
+    assert_eq!(None, scheduler.poll(&mut dev, 100, &mut buffer).unwrap());
+    assert_eq!(None, scheduler.poll(&mut dev, 150, &mut buffer).unwrap());
+    let result = scheduler.poll(&mut dev, 180, &mut buffer);
+    assert!(matches!(result, Err(Error::Timeout)));
+    assert!(scheduler.is_sleeping());
+    destroy(dev);
+}
+
+#[test]
+fn collects_only_samples_fitting_in_buffer() {
+    let mut transactions = vec![
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b011]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b011]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ];
+    transactions.extend(fifo_read(2, vec![0, 0, 8, 0, 0, 16]));
+    transactions.push(I2cTrans::write(
+        DEV_ADDR,
+        vec![Reg::MODE, BF::SHUTDOWN | 0b011],
+    ));
+    let mut dev = new(&transactions).into_oximeter().unwrap();
+    let mut scheduler = DutyCycleScheduler::new(DUTY_CYCLE);
+    let mut buffer = [0; 3];
+    let count = scheduler
+        .run_cycle(&mut dev, &mut NoopDelay, &mut buffer, |samples| {
+            samples.len()
+        })
+        .unwrap();
+    assert_eq!(2, count);
+    destroy(dev);
+}
+
+#[test]
+fn can_estimate_average_current_in_heart_rate_mode() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED1_PA, 50]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED2_PA, 50]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 3]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 1 << 2 | 3]),
+    ];
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    dev.set_pulse_amplitude(Led::Led1, 50).unwrap();
+    dev.set_pulse_amplitude(Led::Led2, 50).unwrap();
+    dev.set_pulse_width(LedPw::Pw411).unwrap();
+    dev.set_sampling_rate(SR::Sps100).unwrap();
+    let scheduler = DutyCycleScheduler::new(DutyCycle {
+        settling_ms: 100,
+        acquisition_ms: 900,
+        sleep_ms: 9000,
+    });
+    // 600 µA + 10 mA * 411 µs * 100 sps while active, 0.7 µA in shutdown.
+    assert_near(101.73, scheduler.average_current_ua(&dev));
+    destroy(dev);
+}
+
+#[test]
+fn can_estimate_average_current_in_multi_led_mode() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b111]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED1_PA, 50]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED2_PA, 100]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SLOT_CONFIG0, 2 << 4 | 1, 1]),
+    ];
+    let mut dev = new(&transactions).into_multi_led().unwrap();
+    dev.set_pulse_amplitude(Led::Led1, 50).unwrap();
+    dev.set_pulse_amplitude(Led::Led2, 100).unwrap();
+    dev.set_led_time_slots([
+        TimeSlot::Led1,
+        TimeSlot::Led2,
+        TimeSlot::Led1,
+        TimeSlot::Disabled,
+    ])
+    .unwrap();
+    let scheduler = DutyCycleScheduler::new(DutyCycle {
+        settling_ms: 0,
+        acquisition_ms: 1000,
+        sleep_ms: 0,
+    });
+    // 600 µA + (2 * 10 mA + 20 mA) * 69 µs * 50 sps
+    assert_near(738.0, scheduler.average_current_ua(&dev));
+    destroy(dev);
+}
@@ -0,0 +1,178 @@
+#![cfg(feature = "serde")]
+extern crate max3010x;
+use max3010x::{
+    signal::{
+        fixed::MedianFilter, hrv::BeatTimer, motion::NlmsFilter,
+        perfusion::PerfusionIndexCalculator, respiration::RespirationEstimator,
+        spo2::TemperatureCompensation,
+    },
+    AdcRange, GroupRead, InterruptStatus, LedPulseWidth, RegisterDiff, SampleAveraging,
+    SamplingRate, TimeSlot,
+};
+
+#[test]
+fn can_roundtrip_enum() {
+    let json = serde_json::to_string(&SamplingRate::Sps400).unwrap();
+    assert_eq!("\"Sps400\"", json);
+    let rate: SamplingRate = serde_json::from_str(&json).unwrap();
+    assert_eq!(SamplingRate::Sps400, rate);
+}
+
+#[test]
+fn can_roundtrip_configuration() {
+    let config = (
+        LedPulseWidth::Pw411,
+        AdcRange::Fs16k,
+        [
+            TimeSlot::Led1,
+            TimeSlot::Led2,
+            TimeSlot::Disabled,
+            TimeSlot::Disabled,
+        ],
+        TemperatureCompensation::default(),
+    );
+    let json = serde_json::to_string(&config).unwrap();
+    let result: (
+        LedPulseWidth,
+        AdcRange,
+        [TimeSlot; 4],
+        TemperatureCompensation,
+    ) = serde_json::from_str(&json).unwrap();
+    assert_eq!(config, result);
+}
+
+#[test]
+fn can_serialize_interrupt_status() {
+    let status = InterruptStatus {
+        power_ready: true,
+        fifo_almost_full: false,
+        new_fifo_data_ready: false,
+        alc_overflow: true,
+        temperature_ready: false,
+    };
+    let json = serde_json::to_string(&status).unwrap();
+    assert_eq!(
+        "{\"power_ready\":true,\"fifo_almost_full\":false,\"new_fifo_data_ready\":false,\
+         \"alc_overflow\":true,\"temperature_ready\":false}",
+        json
+    );
+}
+
+#[test]
+fn can_roundtrip_const_generic_arrays() {
+    let read = GroupRead {
+        sample_count: 3,
+        timestamps_us: [10, 20, 30],
+        skew_us: [0, 10, -20],
+        lost_sample_counts: [0, 2, 0],
+    };
+    let json = serde_json::to_string(&read).unwrap();
+    let result: GroupRead<3> = serde_json::from_str(&json).unwrap();
+    assert_eq!(read, result);
+    assert!(serde_json::from_str::<GroupRead<4>>(&json).is_err());
+}
+
+fn register_diff_json(len: usize) -> String {
+    let mismatch = "{\"register\":9,\"expected\":3,\"actual\":2}";
+    let mismatches = [mismatch; 9].join(",");
+    format!("{{\"mismatches\":[{}],\"len\":{}}}", mismatches, len)
+}
+
+#[test]
+fn can_roundtrip_register_diff() {
+    let diff: RegisterDiff = serde_json::from_str(&register_diff_json(1)).unwrap();
+    assert_eq!(1, diff.as_slice().len());
+    assert_eq!(9, diff.as_slice()[0].register);
+    let json = serde_json::to_string(&diff).unwrap();
+    assert_eq!(register_diff_json(1), json);
+}
+
+#[test]
+fn rejects_invalid_register_diff() {
+    assert!(serde_json::from_str::<RegisterDiff>(&register_diff_json(10)).is_err());
+}
+
+#[test]
+fn validates_perfusion_index_calculator() {
+    let calculator = PerfusionIndexCalculator::new(50.0);
+    let json = serde_json::to_string(&calculator).unwrap();
+    assert_eq!("{\"sample_rate\":50.0,\"channels\":1,\"channel\":0}", json);
+    let result: PerfusionIndexCalculator = serde_json::from_str(&json).unwrap();
+    assert_eq!(calculator, result);
+    let invalid = [
+        "{\"sample_rate\":50.0,\"channels\":0,\"channel\":0}",
+        "{\"sample_rate\":50.0,\"channels\":2,\"channel\":2}",
+        "{\"sample_rate\":0.0,\"channels\":1,\"channel\":0}",
+        "{\"sample_rate\":-50.0,\"channels\":1,\"channel\":0}",
+    ];
+    for json in invalid {
+        assert!(serde_json::from_str::<PerfusionIndexCalculator>(json).is_err());
+    }
+}
+
+#[test]
+fn validates_beat_timer() {
+    let timer = BeatTimer::new(SamplingRate::Sps50, SampleAveraging::Sa1);
+    let json = serde_json::to_string(&timer).unwrap();
+    assert_eq!(
+        "{\"sample_period_ms\":20.0,\"channels\":1,\"channel\":0}",
+        json
+    );
+    let result: BeatTimer = serde_json::from_str(&json).unwrap();
+    assert_eq!(timer, result);
+    let invalid = [
+        "{\"sample_period_ms\":20.0,\"channels\":0,\"channel\":0}",
+        "{\"sample_period_ms\":20.0,\"channels\":2,\"channel\":3}",
+        "{\"sample_period_ms\":0.0,\"channels\":1,\"channel\":0}",
+    ];
+    for json in invalid {
+        assert!(serde_json::from_str::<BeatTimer>(json).is_err());
+    }
+}
+
+#[test]
+fn validates_respiration_estimator() {
+    let estimator = RespirationEstimator::new(25.0);
+    let json = serde_json::to_string(&estimator).unwrap();
+    let result: RespirationEstimator = serde_json::from_str(&json).unwrap();
+    assert_eq!(estimator, result);
+    let invalid = [
+        "{\"sample_rate\":25.0,\"channels\":0,\"channel\":0}",
+        "{\"sample_rate\":25.0,\"channels\":1,\"channel\":1}",
+        "{\"sample_rate\":0.0,\"channels\":1,\"channel\":0}",
+        "{\"sample_rate\":-25.0,\"channels\":1,\"channel\":0}",
+    ];
+    for json in invalid {
+        assert!(serde_json::from_str::<RespirationEstimator>(json).is_err());
+    }
+}
+
+#[test]
+fn can_roundtrip_nested_const_generic_arrays() {
+    let mut filter = NlmsFilter::<40>::new(0.05);
+    filter.filter(10.0, [1.0, 2.0, 3.0]);
+    let json = serde_json::to_string(&filter).unwrap();
+    let result: NlmsFilter<40> = serde_json::from_str(&json).unwrap();
+    assert_eq!(filter, result);
+    assert!(serde_json::from_str::<NlmsFilter<41>>(&json).is_err());
+}
+
+#[test]
+fn validates_filter_window() {
+    let mut filter = MedianFilter::<3>::new();
+    filter.process(&[5, 1, 3, 7], &mut [0; 4]);
+    let json = serde_json::to_string(&filter).unwrap();
+    assert_eq!(
+        "{\"window\":{\"values\":[7,1,3],\"next\":1,\"len\":3}}",
+        json
+    );
+    let result: MedianFilter<3> = serde_json::from_str(&json).unwrap();
+    assert_eq!(filter, result);
+    let invalid = [
+        "{\"window\":{\"values\":[7,1,3],\"next\":3,\"len\":3}}",
+        "{\"window\":{\"values\":[7,1,3],\"next\":0,\"len\":4}}",
+    ];
+    for json in invalid {
+        assert!(serde_json::from_str::<MedianFilter<3>>(json).is_err());
+    }
+}
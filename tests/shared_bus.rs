@@ -0,0 +1,80 @@
+extern crate embedded_hal_mock as hal;
+use core::cell::RefCell;
+use critical_section::Mutex;
+use embedded_hal_bus::i2c::{CriticalSectionDevice, RefCellDevice};
+use hal::eh1::i2c::{Mock as I2cMock, Transaction as I2cTrans};
+extern crate max3010x;
+use max3010x::Max3010x;
+#[allow(unused)]
+mod base;
+use base::{Register as Reg, DEV_ADDR};
+
+const OTHER_ADDR: u8 = 0x58;
+
+#[test]
+fn can_use_custom_address() {
+    let transactions = [I2cTrans::write_read(
+        OTHER_ADDR,
+        vec![Reg::PART_ID],
+        vec![0x15],
+    )];
+    let mut dev = Max3010x::new_max30102_with_address(I2cMock::new(&transactions), OTHER_ADDR);
+    assert_eq!(0x15, dev.get_part_id().unwrap());
+    dev.destroy().done();
+}
+
+#[test]
+fn custom_address_is_kept_across_mode_changes() {
+    let transactions = [
+        I2cTrans::write(OTHER_ADDR, vec![Reg::MODE, 0b011]),
+        I2cTrans::write(OTHER_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(OTHER_ADDR, vec![Reg::FIFO_WR_PTR], vec![0, 0, 0]),
+    ];
+    let dev = Max3010x::new_max30102_with_address(I2cMock::new(&transactions), OTHER_ADDR);
+    let mut dev = dev.into_oximeter().unwrap();
+    assert_eq!(0, dev.get_available_sample_count().unwrap());
+    dev.destroy().done();
+}
+
+#[test]
+fn can_share_bus_with_ref_cell_device() {
+    let transactions = [
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::PART_ID], vec![0x15]),
+        I2cTrans::write_read(OTHER_ADDR, vec![Reg::PART_ID], vec![0x15]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(OTHER_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ];
+    let bus = RefCell::new(I2cMock::new(&transactions));
+    let mut finger = Max3010x::new_max30102(RefCellDevice::new(&bus));
+    let mut earlobe = Max3010x::new_max30102_with_address(RefCellDevice::new(&bus), OTHER_ADDR);
+    assert_eq!(0x15, finger.get_part_id().unwrap());
+    assert_eq!(0x15, earlobe.get_part_id().unwrap());
+    finger.clear_fifo().unwrap();
+    earlobe.clear_fifo().unwrap();
+    finger.destroy();
+    earlobe.destroy();
+    bus.into_inner().done();
+}
+
+#[test]
+fn can_share_bus_with_critical_section_device() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(OTHER_ADDR, vec![Reg::MODE, 0b011]),
+        I2cTrans::write(OTHER_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![0, 0, 0]),
+        I2cTrans::write_read(OTHER_ADDR, vec![Reg::FIFO_WR_PTR], vec![0, 0, 0]),
+    ];
+    let bus = Mutex::new(RefCell::new(I2cMock::new(&transactions)));
+    let dev = Max3010x::new_max30102(CriticalSectionDevice::new(&bus));
+    let mut finger = dev.into_heart_rate().unwrap();
+    let dev = Max3010x::new_max30102_with_address(CriticalSectionDevice::new(&bus), OTHER_ADDR);
+    let mut earlobe = dev.into_oximeter().unwrap();
+    let mut data = [0; 4];
+    assert_eq!(0, finger.read_fifo(&mut data).unwrap());
+    assert_eq!(0, earlobe.read_fifo(&mut data).unwrap());
+    finger.destroy();
+    earlobe.destroy();
+    bus.into_inner().into_inner().done();
+}
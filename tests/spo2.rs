@@ -0,0 +1,25 @@
+extern crate embedded_hal_mock as hal;
+use hal::eh1::i2c::Transaction as I2cTrans;
+extern crate max3010x;
+use max3010x::signal::spo2::Spo2Estimator;
+mod base;
+use base::{destroy, new, BitFlags as BF, Register as Reg, DEV_ADDR};
+
+#[test]
+fn polls_temperature_when_due() {
+    let transactions = [
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::TEMP_CONFIG], vec![0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::TEMP_CONFIG, BF::TEMP_EN]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::TEMP_CONFIG], vec![0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::TEMP_INT], vec![30, 8]),
+    ];
+    let mut dev = new(&transactions);
+    let mut estimator = Spo2Estimator::new(10);
+    estimator.poll_temperature(&mut dev).unwrap();
+    assert_eq!(None, estimator.temperature());
+    estimator.poll_temperature(&mut dev).unwrap();
+    assert_eq!(Some(30.5), estimator.temperature());
+    // not due anymore
+    estimator.poll_temperature(&mut dev).unwrap();
+    destroy(dev);
+}
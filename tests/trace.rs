@@ -0,0 +1,157 @@
+extern crate embedded_hal;
+extern crate embedded_hal_mock as hal;
+use embedded_hal::i2c::ErrorKind;
+use hal::eh1::i2c::{Mock as I2cMock, Transaction as I2cTrans};
+extern crate max3010x;
+use max3010x::{Error, I2cTracer, Led, Max3010x, TraceEvent};
+#[allow(unused)]
+mod base;
+use crate::base::{BitFlags as BF, Register as Reg, DEV_ADDR};
+
+fn trace<F>(transactions: &[I2cTrans], run: F) -> Vec<String>
+where
+    F: FnOnce(
+        Max3010x<
+            I2cTracer<I2cMock, &mut dyn FnMut(&TraceEvent), &mut dyn FnMut() -> u64>,
+            max3010x::marker::ic::Max30102,
+            max3010x::marker::mode::None,
+        >,
+    ) -> I2cMock,
+{
+    let mut events = Vec::new();
+    let mut now: u64 = 0;
+    let mut sink = |event: &TraceEvent| events.push(event.to_string());
+    let mut clock = || {
+        now += 10;
+        now
+    };
+    let tracer = I2cTracer::new(
+        I2cMock::new(transactions),
+        &mut sink as &mut dyn FnMut(&TraceEvent),
+        &mut clock as &mut dyn FnMut() -> u64,
+    );
+    run(Max3010x::new_max30102(tracer)).done();
+    events
+}
+
+#[test]
+fn decodes_configuration_writes() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b011]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED1_PA, 51]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, BF::SHUTDOWN | 0b011]),
+    ];
+    let events = trace(&transactions, |dev| {
+        let mut dev = dev.into_oximeter().unwrap();
+        dev.set_pulse_amplitude(Led::Led1, 51).unwrap();
+        dev.shutdown().unwrap();
+        dev.destroy().destroy().0
+    });
+    assert_eq!(
+        events,
+        [
+            "10 us +10 us: 0x57 write MODE=0x03 (MODE=SpO2)",
+            "30 us +10 us: 0x57 write FIFO_WR_PTR=0x00 OVF_COUNTER=0x00 FIFO_RD_PTR=0x00",
+            "50 us +10 us: 0x57 write LED1_PA=0x33 (10.2mA)",
+            "70 us +10 us: 0x57 write MODE=0x83 (SHDN MODE=SpO2)",
+        ]
+    );
+}
+
+#[test]
+fn decodes_register_reads() {
+    let transactions = [
+        I2cTrans::write_read(
+            DEV_ADDR,
+            vec![Reg::INT_STATUS],
+            vec![BF::FIFO_A_FULL_INT | BF::PWR_RDY_INT, BF::DIE_TEMP_RDY_INT],
+        ),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::PART_ID], vec![0x15]),
+    ];
+    let events = trace(&transactions, |mut dev| {
+        dev.read_interrupt_status().unwrap();
+        dev.get_part_id().unwrap();
+        dev.destroy().destroy().0
+    });
+    assert_eq!(
+        events,
+        [
+            "10 us +10 us: 0x57 write INT_STATUS read INT_STATUS=0x81 (A_FULL PWR_RDY) \
+             INT_STATUS2=0x02 (DIE_TEMP_RDY)",
+            "30 us +10 us: 0x57 write PART_ID read PART_ID=0x15",
+        ]
+    );
+}
+
+#[test]
+fn does_not_auto_increment_fifo_data() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![2, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_DATA], vec![0; 6]),
+    ];
+    let events = trace(&transactions, |dev| {
+        let mut dev = dev.into_heart_rate().unwrap();
+        let mut data = [0; 2];
+        assert_eq!(2, dev.read_fifo(&mut data).unwrap());
+        dev.destroy().destroy().0
+    });
+    assert_eq!(
+        events[3],
+        "70 us +10 us: 0x57 write FIFO_DATA read FIFO_DATA[6 bytes]"
+    );
+}
+
+#[test]
+fn records_errors() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::LED2_PA, 1]).with_error(ErrorKind::Other),
+    ];
+    let events = trace(&transactions, |dev| {
+        let mut dev = dev.into_heart_rate().unwrap();
+        match dev.set_pulse_amplitude(Led::Led2, 1) {
+            Err(Error::I2C(ErrorKind::Other)) => (),
+            _ => panic!("Did not return Error::I2C"),
+        }
+        dev.destroy().destroy().0
+    });
+    assert_eq!(
+        events[2],
+        "50 us +10 us: 0x57 write LED2_PA=0x01 (0.2mA) error: Other"
+    );
+}
+
+#[test]
+fn decodes_spo2_fifo_and_slot_configuration() {
+    let event = TraceEvent {
+        address: DEV_ADDR,
+        write: &[Reg::FIFO_CONFIG, 0b0101_0011, 0, 0b0010_0111],
+        read: &[],
+        read_register: None,
+        start_us: 5,
+        duration_us: 120,
+        error: Some(ErrorKind::NoAcknowledge(
+            embedded_hal::i2c::NoAcknowledgeSource::Address,
+        )),
+    };
+    assert_eq!(
+        event.to_string(),
+        "5 us +120 us: 0x57 write FIFO_CONFIG=0x53 (SMP_AVE=4 ROLLOVER_EN A_FULL=3) \
+         MODE=0x00 (MODE=0b000) SPO2_CONFIG=0x27 (ADC_RGE=4096nA SR=100sps LED_PW=411us) \
+         error: NoAcknowledge(Address)"
+    );
+    let event = TraceEvent {
+        write: &[Reg::SLOT_CONFIG0, 0x21, 0x01],
+        error: None,
+        ..event
+    };
+    assert_eq!(
+        event.to_string(),
+        "5 us +120 us: 0x57 write SLOT_CONFIG0=0x21 (SLOT1=LED1 SLOT2=LED2) \
+         SLOT_CONFIG1=0x01 (SLOT3=LED1 SLOT4=off)"
+    );
+}
@@ -0,0 +1,123 @@
+extern crate embedded_hal_mock as hal;
+use hal::eh1::i2c::Transaction as I2cTrans;
+extern crate max3010x;
+use max3010x::{FifoTransfer, LedPulseWidth as LedPw};
+mod base;
+use crate::base::{destroy, new, Register as Reg, DEV_ADDR};
+
+#[test]
+fn prepares_transfer_of_available_samples() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b011]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![2, 1, 30]),
+    ];
+    let mut dev = new(&transactions).into_oximeter().unwrap();
+    assert_eq!(None, dev.pending_fifo_read());
+    let transfer = dev.begin_fifo_read(64).unwrap();
+    assert_eq!(
+        FifoTransfer {
+            address: DEV_ADDR,
+            register: Reg::FIFO_DATA,
+            byte_count: 24,
+            sample_count: 4,
+            overflow_count: 1,
+        },
+        transfer
+    );
+    assert_eq!(Some(transfer), dev.pending_fifo_read());
+    destroy(dev);
+}
+
+#[test]
+fn limits_transfer_to_buffer_length() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![10, 0, 0]),
+    ];
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    let transfer = dev.begin_fifo_read(8).unwrap();
+    assert_eq!(6, transfer.byte_count);
+    assert_eq!(2, transfer.sample_count);
+    destroy(dev);
+}
+
+#[test]
+fn finishes_transfer_decoding_samples() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![2, 0, 0]),
+    ];
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    let transfer = dev.begin_fifo_read(32).unwrap();
+    assert_eq!(6, transfer.byte_count);
+    let mut data = [0; 2];
+    let count = dev
+        .finish_fifo_read(&[0, 0, 8, 0, 0, 16], &mut data)
+        .unwrap();
+    assert_eq!(2, count);
+    assert_eq!([1, 2], data);
+    assert_eq!(None, dev.pending_fifo_read());
+    destroy(dev);
+}
+
+#[test]
+fn prepares_transfer_of_full_fifo() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![7, 3, 7]),
+    ];
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    let transfer = dev.begin_fifo_read(96).unwrap();
+    assert_eq!(32, transfer.sample_count);
+    assert_eq!(3, transfer.overflow_count);
+    destroy(dev);
+}
+
+#[test]
+fn decodes_with_configuration_at_begin() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![1, 0, 0]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::SPO2_CONFIG, 0b11]),
+    ];
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    dev.begin_fifo_read(32).unwrap();
+    dev.set_pulse_width(LedPw::Pw411).unwrap();
+    let mut data = [0; 1];
+    assert_eq!(1, dev.finish_fifo_read(&[0, 0, 8], &mut data).unwrap());
+    assert_eq!([1], data);
+    destroy(dev);
+}
+
+#[test]
+fn cannot_finish_without_pending_transfer() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b010]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+    ];
+    let mut dev = new(&transactions).into_heart_rate().unwrap();
+    assert_invalid_args!(dev.finish_fifo_read(&[], &mut []));
+    destroy(dev);
+}
+
+#[test]
+fn keeps_transfer_pending_on_invalid_arguments() {
+    let transactions = [
+        I2cTrans::write(DEV_ADDR, vec![Reg::MODE, 0b011]),
+        I2cTrans::write(DEV_ADDR, vec![Reg::FIFO_WR_PTR, 0, 0, 0]),
+        I2cTrans::write_read(DEV_ADDR, vec![Reg::FIFO_WR_PTR], vec![1, 0, 0]),
+    ];
+    let mut dev = new(&transactions).into_oximeter().unwrap();
+    dev.begin_fifo_read(6).unwrap();
+    let mut data = [0; 2];
+    assert_invalid_args!(dev.finish_fifo_read(&[0; 3], &mut data));
+    assert_invalid_args!(dev.finish_fifo_read(&[0; 6], &mut data[..1]));
+    assert!(dev.pending_fifo_read().is_some());
+    assert_eq!(1, dev.finish_fifo_read(&[0; 6], &mut data).unwrap());
+    destroy(dev);
+}